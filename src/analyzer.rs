@@ -1,4 +1,6 @@
-use crate::scanner::{ScanResult, FileEntry};
+use crate::scanner::{FileEntry, FileKind, NameId, NodeId, ScanResult, SizeMode};
+use crate::tree::{NameTable, SubtreeChange};
+use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use humansize::{format_size, DECIMAL};
@@ -35,16 +37,21 @@ pub struct AnalysisFilters {
     pub max_size: Option<u64>,
     pub categories: HashSet<FileCategory>,
     pub extensions: HashSet<String>,
+    /// Entry kinds to show, empty shows all of them
+    pub kinds: HashSet<FileKind>,
     pub show_hidden: bool,
     pub show_system: bool,
 }
 
+/// Number of files kept in the largest files list
+const LARGE_FILE_COUNT: usize = 100;
+
 /// Main disk analyzer
 pub struct DiskAnalyzer {
     file_types: Vec<FileTypeInfo>,
-    duplicate_cache: HashMap<u64, Vec<PathBuf>>,
-    large_files: Vec<FileEntry>,
-    old_files: Vec<FileEntry>,
+    duplicate_cache: HashMap<u64, Vec<NodeId>>,
+    large_files: Vec<NodeId>,
+    old_files: Vec<NodeId>,
     analysis_time: std::time::Instant,
 }
 
@@ -63,89 +70,146 @@ impl DiskAnalyzer {
     pub fn analyze(&mut self, scan_result: &ScanResult) {
         self.analysis_time = std::time::Instant::now();
         
-        // Find large files (top 100 by size)
-        let mut all_files: Vec<&FileEntry> = scan_result
-            .entries
-            .iter()
-            .filter(|e| !e.is_directory)
-            .collect();
-        
-        all_files.sort_by(|a, b| b.size.cmp(&a.size));
-        self.large_files = all_files
-            .iter()
-            .take(100)
-            .cloned()
-            .cloned()
-            .collect();
+        // Find large files (top 100 by size), links and device nodes are not files here
+        self.large_files = Self::largest_files(scan_result);
         
         // Find old files (modified more than 1 year ago)
-        let one_year_ago = chrono::Utc::now() - chrono::Duration::days(365);
+        let one_year_ago = Self::one_year_ago();
         self.old_files = scan_result
-            .entries
             .iter()
-            .filter(|e| !e.is_directory && e.modified < one_year_ago)
-            .cloned()
+            .filter(|(_, e)| Self::is_old_file(e, one_year_ago))
+            .map(|(id, _)| id)
             .collect();
         
-        self.old_files.sort_by(|a, b| a.modified.cmp(&b.modified));
+        self.old_files.sort_by(|&a, &b| scan_result.entry(a).modified.cmp(&scan_result.entry(b).modified));
         
-        // Build duplicate cache (group by size as first pass)
+        // Build duplicate cache (group by size as first pass). Sizes with a single
+        // file are kept so that `update` can pair them with new files.
         self.duplicate_cache.clear();
-        for entry in &scan_result.entries {
-            if !entry.is_directory && entry.size > 0 {
+        for (id, entry) in scan_result.iter() {
+            if Self::is_duplicate_candidate(entry) {
                 self.duplicate_cache
                     .entry(entry.size)
                     .or_insert_with(Vec::new)
-                    .push(entry.path.clone());
+                    .push(id);
             }
         }
+    }
+    
+    /// Update the results after `ScanResult::graft` replaced a subtree,
+    /// only looking at the entries that changed
+    pub fn update(&mut self, scan_result: &ScanResult, change: &SubtreeChange) {
+        self.analysis_time = std::time::Instant::now();
+        let removed: HashSet<NodeId> = change.removed.iter().copied().collect();
+        // Entries added by one change can be gone again after a later one
+        let added = || {
+            change
+                .added
+                .iter()
+                .filter(|&&id| !scan_result.is_detached(id))
+                .map(|&id| (id, scan_result.entry(id)))
+        };
+        
+        let was_full = self.large_files.len() == LARGE_FILE_COUNT;
+        self.large_files.retain(|id| !removed.contains(id));
+        self.large_files.extend(added().filter(|(_, e)| Self::is_disk_file(e)).map(|(id, _)| id));
+        self.large_files.sort_by_key(|&id| std::cmp::Reverse(scan_result.entry(id).size));
+        self.large_files.truncate(LARGE_FILE_COUNT);
+        if was_full && self.large_files.len() < LARGE_FILE_COUNT {
+            // Files that were just below the list are not known, look them up again
+            self.large_files = Self::largest_files(scan_result);
+        }
         
-        // Remove sizes with only one file
-        self.duplicate_cache.retain(|_, paths| paths.len() > 1);
+        let one_year_ago = Self::one_year_ago();
+        self.old_files.retain(|id| !removed.contains(id));
+        self.old_files.extend(added().filter(|(_, e)| Self::is_old_file(e, one_year_ago)).map(|(id, _)| id));
+        self.old_files.sort_by_key(|&id| scan_result.entry(id).modified);
+        
+        for ids in self.duplicate_cache.values_mut() {
+            ids.retain(|id| !removed.contains(id));
+        }
+        self.duplicate_cache.retain(|_, ids| !ids.is_empty());
+        for (id, entry) in added() {
+            if Self::is_duplicate_candidate(entry) {
+                self.duplicate_cache.entry(entry.size).or_default().push(id);
+            }
+        }
+    }
+    
+    fn largest_files(scan_result: &ScanResult) -> Vec<NodeId> {
+        let mut all_files: Vec<NodeId> = scan_result
+            .iter()
+            .filter(|(_, e)| Self::is_disk_file(e))
+            .map(|(id, _)| id)
+            .collect();
+        
+        all_files.sort_by_key(|&id| std::cmp::Reverse(scan_result.entry(id).size));
+        all_files.truncate(LARGE_FILE_COUNT);
+        all_files
+    }
+    
+    fn one_year_ago() -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now() - chrono::Duration::days(365)
+    }
+    
+    /// Regular file that is on disk, archive members are left out of the analysis
+    fn is_disk_file(entry: &FileEntry) -> bool {
+        entry.kind == FileKind::File && !entry.in_archive
+    }
+    
+    fn is_old_file(entry: &FileEntry, one_year_ago: chrono::DateTime<chrono::Utc>) -> bool {
+        Self::is_disk_file(entry) && entry.modified.is_some_and(|m| m < one_year_ago)
+    }
+    
+    /// Files seen through followed symlinks are the same files, not duplicates
+    fn is_duplicate_candidate(entry: &FileEntry) -> bool {
+        Self::is_disk_file(entry) && !entry.via_link && entry.size > 0
     }
     
     /// Get statistics by file category
     pub fn get_category_stats(&self, scan_result: &ScanResult) -> HashMap<FileCategory, CategoryStats> {
         let mut stats = HashMap::new();
         
-        for entry in &scan_result.entries {
-            if !entry.is_directory {
-                let category = self.categorize_file(entry);
+        for (id, entry) in scan_result.iter() {
+            if Self::is_disk_file(entry) && !entry.via_link {
+                let category = self.categorize_file(scan_result, id);
                 let stat = stats.entry(category).or_insert_with(CategoryStats::default);
                 stat.total_size += entry.size;
                 stat.file_count += 1;
-                stat.files.push(entry.clone());
+                stat.files.push(id);
             }
         }
         
         // Sort files within each category by size
         for stat in stats.values_mut() {
-            stat.files.sort_by(|a, b| b.size.cmp(&a.size));
+            stat.files.sort_by(|&a, &b| scan_result.entry(b).size.cmp(&scan_result.entry(a).size));
         }
         
         stats
     }
     
     /// Categorize a file based on its extension
-    pub fn categorize_file(&self, entry: &FileEntry) -> FileCategory {
-        if let Some(ext) = &entry.extension {
+    pub fn categorize_file(&self, scan_result: &ScanResult, id: NodeId) -> FileCategory {
+        if let Some(ext) = scan_result.extension(id) {
             for file_type in &self.file_types {
-                if file_type.extensions.contains(&ext.to_lowercase()) {
+                if file_type.extensions.iter().any(|e| e == ext) {
                     return file_type.category;
                 }
             }
         }
         
         // Check for hidden/system files
-        if entry.name.starts_with('.') {
+        if scan_result.name(id).starts_with('.') {
             return FileCategory::Hidden;
         }
         
-        if entry.path.to_string_lossy().contains("node_modules")
-            || entry.path.to_string_lossy().contains("target/")
-            || entry.path.to_string_lossy().contains(".git/")
-        {
-            return FileCategory::System;
+        // Walk up the parents instead of building the full path
+        let mut parent = scan_result.entry(id).parent;
+        while let Some(dir) = parent {
+            if matches!(scan_result.name(dir), "node_modules" | "target" | ".git") {
+                return FileCategory::System;
+            }
+            parent = scan_result.entry(dir).parent;
         }
         
         FileCategory::Other
@@ -163,26 +227,41 @@ impl DiskAnalyzer {
     pub fn find_potential_duplicates(&self) -> Vec<DuplicateGroup> {
         let mut duplicates = Vec::new();
         
-        for (size, paths) in &self.duplicate_cache {
-            if paths.len() > 1 {
+        for (size, ids) in &self.duplicate_cache {
+            if ids.len() > 1 {
                 duplicates.push(DuplicateGroup {
                     size: *size,
-                    paths: paths.clone(),
+                    entries: ids.clone(),
                 });
             }
         }
         
-        duplicates.sort_by(|a, b| b.paths.len().cmp(&a.paths.len()));
+        duplicates.sort_by(|a, b| b.entries.len().cmp(&a.entries.len()));
         duplicates
     }
     
+    /// Regular files not accessed since `cutoff`, least recently accessed first.
+    ///
+    /// Files without an access time are left out. With `relatime` mounts the
+    /// access time is only updated once a day, which is plenty for this.
+    pub fn not_accessed_since(&self, scan_result: &ScanResult, cutoff: chrono::DateTime<chrono::Utc>) -> Vec<NodeId> {
+        let mut files: Vec<NodeId> = scan_result
+            .iter()
+            .filter(|(_, e)| e.kind == FileKind::File && e.accessed.is_some_and(|a| a < cutoff))
+            .map(|(id, _)| id)
+            .collect();
+        
+        files.sort_by_key(|&id| scan_result.entry(id).accessed);
+        files
+    }
+    
     /// Get largest files
-    pub fn get_largest_files(&self, count: usize) -> &[FileEntry] {
+    pub fn get_largest_files(&self, count: usize) -> &[NodeId] {
         &self.large_files[..count.min(self.large_files.len())]
     }
     
     /// Get oldest files
-    pub fn get_oldest_files(&self, count: usize) -> &[FileEntry] {
+    pub fn get_oldest_files(&self, count: usize) -> &[NodeId] {
         &self.old_files[..count.min(self.old_files.len())]
     }
     
@@ -264,14 +343,16 @@ impl DiskAnalyzer {
 pub struct CategoryStats {
     pub total_size: u64,
     pub file_count: u64,
-    pub files: Vec<FileEntry>,
+    /// Ids of the files in the analyzed `ScanResult`, largest first
+    pub files: Vec<NodeId>,
 }
 
 /// Group of potential duplicate files
 #[derive(Debug, Clone)]
 pub struct DuplicateGroup {
     pub size: u64,
-    pub paths: Vec<PathBuf>,
+    /// Ids of the files in the analyzed `ScanResult`
+    pub entries: Vec<NodeId>,
 }
 
 impl CategoryStats {
@@ -288,4 +369,331 @@ impl CategoryStats {
             (self.total_size as f64 / total as f64 * 100.0) as f32
         }
     }
-}
\ No newline at end of file
+}
+
+/// How a path changed between two scans
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Grown,
+    Shrunk,
+    Unchanged,
+}
+
+/// One path found in either of two scans
+#[derive(Debug, Clone)]
+pub struct DiffNode {
+    pub name: NameId,
+    /// Index of the parent in `ScanDiff::nodes`
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Entry in the older scan, if the path existed there
+    pub old: Option<NodeId>,
+    /// Entry in the newer scan, if the path exists there
+    pub new: Option<NodeId>,
+    pub old_size: u64,
+    pub new_size: u64,
+    /// Files at or below this path in the older scan
+    pub old_files: u64,
+    /// Files at or below this path in the newer scan
+    pub new_files: u64,
+    pub is_container: bool,
+    pub change: ChangeKind,
+}
+
+impl DiffNode {
+    /// Bytes gained, negative when the path shrank
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+    
+    /// Files gained, negative when files went away
+    pub fn file_delta(&self) -> i64 {
+        self.new_files as i64 - self.old_files as i64
+    }
+}
+
+/// Differences between two scans of the same root.
+///
+/// The nodes form one tree covering the paths of both scans, root first and
+/// every child after its parent. Sizes and file counts are subtree totals, so
+/// the deltas of a directory include everything below it.
+#[derive(Debug, Clone)]
+pub struct ScanDiff {
+    pub root_path: PathBuf,
+    pub old_time: DateTime<Utc>,
+    pub new_time: DateTime<Utc>,
+    pub size_mode: SizeMode,
+    pub nodes: Vec<DiffNode>,
+    pub names: NameTable,
+}
+
+impl ScanDiff {
+    pub fn root(&self) -> &DiffNode {
+        &self.nodes[0]
+    }
+    
+    pub fn name(&self, id: usize) -> &str {
+        self.names.get(self.nodes[id].name)
+    }
+    
+    /// Rebuild the full path of a node from the names of its ancestors
+    pub fn path_of(&self, id: usize) -> PathBuf {
+        let mut chain = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            chain.push(current);
+            current = parent;
+        }
+        
+        let mut path = self.root_path.clone();
+        for &node in chain.iter().rev() {
+            path.push(self.name(node));
+        }
+        path
+    }
+    
+    /// Number of paths with the given kind of change
+    pub fn count(&self, change: ChangeKind) -> usize {
+        self.nodes.iter().filter(|node| node.change == change).count()
+    }
+    
+    /// Files, or directories, that grew the most, largest growth first
+    pub fn biggest_growth(&self, count: usize, containers: bool) -> Vec<usize> {
+        let mut grown: Vec<usize> = (0..self.nodes.len())
+            .filter(|&id| self.nodes[id].is_container == containers && self.nodes[id].size_delta() > 0)
+            .collect();
+        grown.sort_by_key(|&id| std::cmp::Reverse(self.nodes[id].size_delta()));
+        grown.truncate(count);
+        grown
+    }
+}
+
+/// Compare an older and a newer scan of the same root.
+///
+/// Entries are matched by name, an entry that turned from a file into a
+/// directory or back counts as removed and added. Returns `None` when the
+/// scans have different roots or either is empty.
+pub fn compare_scans(old: &ScanResult, new: &ScanResult, size_mode: SizeMode) -> Option<ScanDiff> {
+    if old.root_path != new.root_path || old.root().is_none() || new.root().is_none() {
+        return None;
+    }
+    
+    let old_files = subtree_file_counts(old);
+    let new_files = subtree_file_counts(new);
+    let mut diff = ScanDiff {
+        root_path: new.root_path.clone(),
+        old_time: old.scan_time,
+        new_time: new.scan_time,
+        size_mode,
+        nodes: Vec::new(),
+        names: NameTable::default(),
+    };
+    
+    // (parent in the diff, entry in the old scan, entry in the new scan)
+    let mut stack = vec![(None, Some(NodeId::ROOT), Some(NodeId::ROOT))];
+    while let Some((parent, old_id, new_id)) = stack.pop() {
+        let old_entry = old_id.map(|id| old.entry(id));
+        let new_entry = new_id.map(|id| new.entry(id));
+        let name = match (old_id, new_id) {
+            (_, Some(id)) => new.name(id),
+            (Some(id), None) => old.name(id),
+            (None, None) => continue,
+        };
+        let old_size = old_entry.map_or(0, |e| e.size_in(size_mode));
+        let new_size = new_entry.map_or(0, |e| e.size_in(size_mode));
+        let change = match (old_entry, new_entry) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ if new_size > old_size => ChangeKind::Grown,
+            _ if new_size < old_size => ChangeKind::Shrunk,
+            _ => ChangeKind::Unchanged,
+        };
+        
+        let id = diff.nodes.len();
+        diff.nodes.push(DiffNode {
+            name: diff.names.intern(name),
+            parent,
+            children: Vec::new(),
+            old: old_id,
+            new: new_id,
+            old_size,
+            new_size,
+            old_files: old_id.map_or(0, |id| old_files[id.index()]),
+            new_files: new_id.map_or(0, |id| new_files[id.index()]),
+            is_container: new_entry.or(old_entry).is_some_and(|e| e.is_container()),
+            change,
+        });
+        if let Some(parent) = parent {
+            diff.nodes[parent].children.push(id);
+        }
+        
+        // Pair up the children by name, placeholders of excluded paths are left out
+        let mut unmatched: HashMap<&str, NodeId> = old_id
+            .map(|id| old.children(id))
+            .unwrap_or_default()
+            .iter()
+            .filter(|&&child| old.entry(child).excluded.is_none())
+            .map(|&child| (old.name(child), child))
+            .collect();
+        for &child in new_id.map(|id| new.children(id)).unwrap_or_default() {
+            if new.entry(child).excluded.is_some() {
+                continue;
+            }
+            let name = new.name(child);
+            let matched = unmatched
+                .get(name)
+                .copied()
+                .filter(|&old_child| old.entry(old_child).is_container() == new.entry(child).is_container());
+            if matched.is_some() {
+                unmatched.remove(name);
+            }
+            stack.push((Some(id), matched, Some(child)));
+        }
+        for old_child in unmatched.into_values() {
+            stack.push((Some(id), Some(old_child), None));
+        }
+    }
+    
+    diff.names.release_lookup();
+    Some(diff)
+}
+
+/// Files at or below every entry, summarized contents included
+fn subtree_file_counts(scan_result: &ScanResult) -> Vec<u64> {
+    let mut counts = vec![0; scan_result.entries.len()];
+    for (id, entry) in scan_result.iter().filter(|(_, e)| e.excluded.is_none()) {
+        counts[id.index()] = u64::from(!entry.is_directory())
+            + entry.summary.as_ref().map_or(0, |summary| summary.file_count);
+    }
+    // Children are stored after their parents
+    for i in (1..counts.len()).rev() {
+        if let Some(parent) = scan_result.entries[i].parent {
+            counts[parent.index()] += counts[i];
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{PathRule, PatternKind, RuleAction};
+    use crate::scanner::{FileSystemScanner, ScanOptions};
+    use crate::source::testing::scan;
+    use crate::source::MemoryTree;
+    use std::path::Path;
+    
+    fn without_tmp() -> ScanOptions {
+        ScanOptions {
+            rules: vec![PathRule::new(RuleAction::Exclude, PatternKind::Glob, "*.tmp")],
+            ..Default::default()
+        }
+    }
+    
+    /// The nodes of the diff at `path`, two when a file turned into a directory
+    fn nodes_at<'a>(diff: &'a ScanDiff, path: &str) -> Vec<&'a DiffNode> {
+        (0..diff.nodes.len())
+            .filter(|&id| diff.path_of(id) == Path::new(path))
+            .map(|id| &diff.nodes[id])
+            .collect()
+    }
+    
+    fn change_at(diff: &ScanDiff, path: &str) -> ChangeKind {
+        let nodes = nodes_at(diff, path);
+        assert_eq!(nodes.len(), 1, "{}", path);
+        nodes[0].change
+    }
+    
+    #[test]
+    fn scans_are_compared_by_path() {
+        let mut before = MemoryTree::new("/data");
+        let docs = before.dir(MemoryTree::ROOT, "docs");
+        before.file(docs, "a.txt", 100);
+        before.file(docs, "b.txt", 200);
+        before.file(MemoryTree::ROOT, "gone.txt", 50);
+        before.file(MemoryTree::ROOT, "shrink.bin", 1000);
+        before.file(MemoryTree::ROOT, "swap", 10);
+        before.file(MemoryTree::ROOT, "skip.tmp", 500);
+        
+        let mut after = MemoryTree::new("/data");
+        let docs = after.dir(MemoryTree::ROOT, "docs");
+        after.file(docs, "a.txt", 100);
+        after.file(docs, "b.txt", 700);
+        after.file(docs, "c.txt", 5);
+        after.file(MemoryTree::ROOT, "shrink.bin", 400);
+        let swap = after.dir(MemoryTree::ROOT, "swap");
+        after.file(swap, "inner.txt", 20);
+        after.file(MemoryTree::ROOT, "skip.tmp", 900);
+        after.file(MemoryTree::ROOT, "new.txt", 30);
+        
+        let old = scan(&before, &without_tmp());
+        let new = scan(&after, &without_tmp());
+        let diff = compare_scans(&old, &new, SizeMode::Apparent).unwrap();
+        
+        assert_eq!(change_at(&diff, "/data/docs"), ChangeKind::Grown);
+        assert_eq!(change_at(&diff, "/data/docs/a.txt"), ChangeKind::Unchanged);
+        assert_eq!(change_at(&diff, "/data/docs/b.txt"), ChangeKind::Grown);
+        assert_eq!(change_at(&diff, "/data/docs/c.txt"), ChangeKind::Added);
+        assert_eq!(change_at(&diff, "/data/gone.txt"), ChangeKind::Removed);
+        assert_eq!(change_at(&diff, "/data/shrink.bin"), ChangeKind::Shrunk);
+        assert_eq!(change_at(&diff, "/data/new.txt"), ChangeKind::Added);
+        assert_eq!(change_at(&diff, "/data/swap/inner.txt"), ChangeKind::Added);
+        
+        // A file that became a directory is one path removed and another added
+        let mut swap: Vec<_> = nodes_at(&diff, "/data/swap").iter().map(|node| (node.change, node.is_container)).collect();
+        swap.sort_by_key(|&(_, container)| container);
+        assert_eq!(swap, [(ChangeKind::Removed, false), (ChangeKind::Added, true)]);
+        
+        // Excluded placeholders are not compared
+        assert!(nodes_at(&diff, "/data/skip.tmp").is_empty());
+        
+        let root = diff.root();
+        assert_eq!((root.old_size, root.new_size), (1360, 1255));
+        assert_eq!(root.change, ChangeKind::Shrunk);
+        assert_eq!((root.old_files, root.new_files), (5, 6));
+        assert_eq!(root.file_delta(), 1);
+        assert_eq!(diff.count(ChangeKind::Added), 4);
+        assert_eq!(diff.count(ChangeKind::Removed), 2);
+        assert_eq!(diff.count(ChangeKind::Grown), 2);
+        assert_eq!(diff.count(ChangeKind::Shrunk), 2);
+        assert_eq!(diff.count(ChangeKind::Unchanged), 1);
+        
+        let other_root = FileSystemScanner::scan_source_blocking(&after, Path::new("/data/docs"), &ScanOptions::default()).unwrap();
+        assert!(compare_scans(&old, &other_root, SizeMode::Apparent).is_none());
+    }
+    
+    #[test]
+    fn file_counts_roll_up_into_parents() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        tree.file(docs, "a.txt", 1);
+        let deep = tree.dir(docs, "deep");
+        tree.file(deep, "b.txt", 1);
+        tree.file(deep, "c.txt", 1);
+        let summarized = tree.dir(deep, "summarized");
+        tree.file(summarized, "d.txt", 1);
+        let inner = tree.dir(summarized, "inner");
+        tree.file(inner, "e.txt", 1);
+        tree.dir(MemoryTree::ROOT, "empty");
+        tree.file(MemoryTree::ROOT, "top.txt", 1);
+        tree.file(MemoryTree::ROOT, "skip.tmp", 1);
+        
+        // Folders three levels down are summarized instead of listed
+        let options = ScanOptions { max_depth: Some(3), ..without_tmp() };
+        let result = scan(&tree, &options);
+        let counts = subtree_file_counts(&result);
+        let count_at = |path: &str| counts[result.find(Path::new(path)).unwrap().index()];
+        
+        assert!(result.entry(result.find(Path::new("/data/docs/deep/summarized")).unwrap()).summary.is_some());
+        assert_eq!(count_at("/data/docs/deep/summarized"), 2);
+        assert_eq!(count_at("/data/docs/deep"), 4);
+        assert_eq!(count_at("/data/docs"), 5);
+        assert_eq!(count_at("/data/empty"), 0);
+        assert_eq!(count_at("/data/skip.tmp"), 0);
+        assert_eq!(count_at("/data/top.txt"), 1);
+        assert_eq!(count_at("/data"), 6);
+        assert_eq!(count_at("/data"), result.file_count);
+    }
+}
@@ -1,7 +1,8 @@
 use eframe::egui;
 use crate::scanner::{FileSystemScanner, ScanResult, ScanProgress};
 use crate::analyzer::{DiskAnalyzer, AnalysisFilters};
-use crate::ui::{MainPanel, TreePanel, ChartPanel, DetailsPanel, DisksPanel};
+use crate::watcher::TreeWatcher;
+use crate::ui::{MainPanel, TreePanel, TreeContext, ChartPanel, DetailsPanel, DisksPanel, ErrorsPanel, AccessPanel, DiffPanel, ProgressPanel};
 use std::path::PathBuf;
 use std::sync::Arc;
 use parking_lot::RwLock;
@@ -9,6 +10,10 @@ use parking_lot::RwLock;
 /// Main application state
 pub struct DiskRayApp {
     pub scanner: Option<FileSystemScanner>,
+    /// Rescan of a single folder, grafted into `scan_result` once it finishes
+    pub subtree_scanner: Option<FileSystemScanner>,
+    /// Live updates of the finished scan, when enabled in the settings
+    pub watcher: Option<TreeWatcher>,
     pub scan_result: Arc<RwLock<Option<ScanResult>>>,
     pub scan_progress: Arc<RwLock<ScanProgress>>,
     pub analyzer: DiskAnalyzer,
@@ -18,6 +23,10 @@ pub struct DiskRayApp {
     pub chart_panel: ChartPanel,
     pub details_panel: DetailsPanel,
     pub disks_panel: DisksPanel,
+    pub errors_panel: ErrorsPanel,
+    pub access_panel: AccessPanel,
+    pub diff_panel: DiffPanel,
+    pub progress_panel: ProgressPanel,
     pub current_path: PathBuf,
     pub is_scanning: bool,
     pub selected_path: Option<PathBuf>,
@@ -30,6 +39,8 @@ impl DiskRayApp {
     pub fn new() -> Self {
         Self {
             scanner: None,
+            subtree_scanner: None,
+            watcher: None,
             scan_result: Arc::new(RwLock::new(None)),
             scan_progress: Arc::new(RwLock::new(ScanProgress::default())),
             analyzer: DiskAnalyzer::new(),
@@ -39,6 +50,10 @@ impl DiskRayApp {
             chart_panel: ChartPanel::new(),
             details_panel: DetailsPanel::new(),
             disks_panel: DisksPanel::new(),
+            errors_panel: ErrorsPanel::new(),
+            access_panel: AccessPanel::new(),
+            diff_panel: DiffPanel::new(),
+            progress_panel: ProgressPanel::new(),
             current_path: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
             is_scanning: false,
             selected_path: None,
@@ -47,6 +62,19 @@ impl DiskRayApp {
             sort_descending: true,
         }
     }
+    
+    /// App that starts scanning `roots` right away, into one result when there are several
+    pub fn with_roots(roots: Vec<PathBuf>) -> Self {
+        let mut app = Self::new();
+        if !roots.is_empty() {
+            let mut scanner = FileSystemScanner::with_roots(roots, app.main_panel.scan_options.clone());
+            scanner.start();
+            app.current_path = scanner.root_path().to_path_buf();
+            app.scanner = Some(scanner);
+            app.is_scanning = true;
+        }
+        app
+    }
 }
 
 impl eframe::App for DiskRayApp {
@@ -59,19 +87,78 @@ impl eframe::App for DiskRayApp {
 
 impl DiskRayApp {
     fn update_scanning(&mut self) {
+        if self.subtree_scanner.as_ref().is_some_and(|s| s.is_finished()) {
+            let mut subtree_scanner = self.subtree_scanner.take().unwrap();
+            if let (Some(subtree), Some(result)) = (subtree_scanner.take_result(), &mut *self.scan_result.write()) {
+                // The tree may have been replaced by another scan in the meantime
+                let at = result.find(subtree_scanner.root_path());
+                if let Some(at) = at.filter(|&at| result.entry(at).is_directory()) {
+                    let change = result.graft(at, subtree);
+                    self.analyzer.update(result, &change);
+                }
+            }
+        }
+        
+        if let Some(result) = self.main_panel.opened_scan.take() {
+            // An opened snapshot replaces whatever was shown or being scanned
+            if let Some(scanner) = self.scanner.take() {
+                scanner.stop();
+            }
+            self.subtree_scanner = None;
+            self.is_scanning = false;
+            self.current_path = result.root_path.clone();
+            self.selected_path = None;
+            self.analyzer.analyze(&result);
+            *self.scan_result.write() = Some(result);
+        }
+        
+        self.update_watcher();
+        
         if self.is_scanning {
             if let Some(scanner) = &mut self.scanner {
                 if scanner.is_finished() {
                     self.is_scanning = false;
+                    // Cancelled scans also leave a (partial) result, failed ones do not
                     if let Some(result) = scanner.take_result() {
                         *self.scan_result.write() = Some(result.clone());
                         self.analyzer.analyze(&result);
                     }
+                } else if let Some(snapshot) = scanner.take_snapshot() {
+                    // Show the tree built so far, the analyzer waits for the final result
+                    *self.scan_result.write() = Some(snapshot);
                 }
             }
         }
     }
     
+    /// Keep a watcher on the finished scan while live updates are enabled
+    fn update_watcher(&mut self) {
+        let scan_result = self.scan_result.read();
+        let watchable = self.main_panel.watch_changes
+            && !self.is_scanning
+            && scan_result.as_ref().is_some_and(|result| {
+                // Snapshots from other machines describe paths that are not here,
+                // and scans of several roots have no one folder to watch
+                !result.partial
+                    && result.roots.is_empty()
+                    && (result.hostname.is_empty() || result.hostname == crate::scanner::local_hostname())
+                    && self.watcher.as_ref().is_none_or(|w| w.root_path() == result.root_path)
+            });
+        drop(scan_result);
+        
+        if !watchable {
+            self.watcher = None;
+        } else if self.watcher.is_none() {
+            self.watcher = TreeWatcher::start(self.scan_result.clone(), self.main_panel.scan_options.clone());
+        }
+        
+        if let Some(change) = self.watcher.as_ref().and_then(|w| w.take_changes()) {
+            if let Some(result) = &*self.scan_result.read() {
+                self.analyzer.update(result, &change);
+            }
+        }
+    }
+    
     fn render_ui(&mut self, ctx: &egui::Context) {
         // Main menu bar
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
@@ -89,25 +176,53 @@ impl DiskRayApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             match self.view_mode {
                 ViewMode::Tree => {
-                    let selected_path = &mut self.selected_path;
-                    let scan_result = self.scan_result.clone();
-                    let scanner = &mut self.scanner;
-                    let is_scanning = &mut self.is_scanning;
-                    let current_path = &mut self.current_path;
+                    let context = TreeContext {
+                        selected_path: &mut self.selected_path,
+                        scan_result: self.scan_result.clone(),
+                        scanner: &mut self.scanner,
+                        subtree_scanner: &mut self.subtree_scanner,
+                        is_scanning: &mut self.is_scanning,
+                        current_path: &mut self.current_path,
+                        scan_options: &self.main_panel.scan_options,
+                        size_mode: self.main_panel.size_mode,
+                    };
                     
-                    self.tree_panel.render(ui, selected_path, scan_result, scanner, is_scanning, current_path);
+                    self.tree_panel.render(ui, context);
                 }
                 ViewMode::Chart => {
-                    // Просто рисуем панель без данных
-                    self.chart_panel.render(ui);
+                    let scan_result = self.scan_result.clone();
+                    let size_mode = self.main_panel.size_mode;
+                    
+                    self.chart_panel.render(ui, scan_result, size_mode);
                 }
                 ViewMode::Details => {
-                    // Просто рисуем панель без данных
-                    self.details_panel.render(ui);
+                    let scan_result = self.scan_result.clone();
+                    
+                    self.details_panel.render(ui, scan_result, &self.selected_path);
                 }
                 ViewMode::Disks => {
                     self.disks_panel.render(ui);
                 }
+                ViewMode::Errors => {
+                    let scan_result = self.scan_result.clone();
+                    
+                    self.errors_panel.render(ui, scan_result);
+                }
+                ViewMode::Access => {
+                    let scan_result = self.scan_result.clone();
+                    let size_mode = self.main_panel.size_mode;
+                    
+                    self.access_panel.render(ui, scan_result, &self.analyzer, size_mode);
+                }
+                ViewMode::Diff => {
+                    let scan_result = self.scan_result.clone();
+                    let size_mode = self.main_panel.size_mode;
+                    
+                    self.diff_panel.render(ui, scan_result, size_mode);
+                }
+                ViewMode::Progress => {
+                    self.progress_panel.render(ui, &self.scanner);
+                }
             }
         });
         
@@ -115,8 +230,10 @@ impl DiskRayApp {
         egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
             let current_path = &self.current_path;
             let scan_result = self.scan_result.clone();
+            let scan_state = self.scanner.as_ref().map(|s| s.state());
+            let watch_status = self.watcher.as_ref().map(|w| w.status());
             
-            self.main_panel.render_status(ui, current_path, scan_result);
+            self.main_panel.render_status(ui, current_path, scan_result, scan_state, watch_status);
         });
     }
 }
@@ -128,6 +245,10 @@ pub enum ViewMode {
     Chart,
     Details,
     Disks,
+    Errors,
+    Access,
+    Diff,
+    Progress,
 }
 
 /// Columns for sorting
@@ -0,0 +1,445 @@
+use crate::scanner::{FileEntry, FileKind, NameId, NodeId, ScanResult};
+use crate::source::ScanSource;
+use chrono::{DateTime, Utc};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Archive formats whose contents can be listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveKind {
+    /// Format of an archive, judged by its file name
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveKind::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+/// One file, folder or link stored in an archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveMember {
+    /// Path inside the archive
+    pub path: PathBuf,
+    pub kind: FileKind,
+    /// Uncompressed size
+    pub size: u64,
+    /// Bytes the member takes up in the archive. Compressed tarballs are one
+    /// stream, so there it is measured from the stream position, which is
+    /// only exact to a compression block.
+    pub compressed_size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub mode: Option<u32>,
+}
+
+/// List the members of the archive at `path`.
+///
+/// Zip archives are read from their central directory, tarballs have to be
+/// read (and decompressed) from start to end.
+pub fn list_members(source: &dyn ScanSource, path: &Path, kind: ArchiveKind) -> io::Result<Vec<ArchiveMember>> {
+    let reader = source.open(path)?;
+    match kind {
+        ArchiveKind::Zip => list_zip(reader),
+        ArchiveKind::Tar => {
+            let (reader, consumed) = Counted::new(reader);
+            list_tar(reader, &consumed)
+        }
+        ArchiveKind::TarGz => {
+            let (reader, consumed) = Counted::new(reader);
+            list_tar(flate2::bufread::GzDecoder::new(reader), &consumed)
+        }
+        ArchiveKind::TarZst => {
+            let (reader, consumed) = Counted::new(reader);
+            list_tar(zstd::Decoder::with_buffer(reader)?, &consumed)
+        }
+    }
+}
+
+fn list_zip(reader: impl Read + Seek) -> io::Result<Vec<ArchiveMember>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut members = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        // Names that would leave the archive's folder are skipped, like an extractor does
+        let Some(path) = file.enclosed_name() else {
+            continue;
+        };
+        let kind = if file.is_dir() {
+            FileKind::Directory
+        } else if file.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::File
+        };
+        members.push(ArchiveMember {
+            path,
+            kind,
+            size: file.size(),
+            compressed_size: file.compressed_size(),
+            modified: file.last_modified().and_then(zip_time),
+            mode: file.unix_mode(),
+        });
+    }
+    Ok(members)
+}
+
+/// Zip times carry no time zone, they are taken as UTC
+fn zip_time(time: zip::DateTime) -> Option<DateTime<Utc>> {
+    chrono::NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+        .and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())
+        .map(|time| time.and_utc())
+}
+
+/// List a tarball, `consumed` counting the bytes read from the archive file
+fn list_tar(reader: impl Read, consumed: &Cell<u64>) -> io::Result<Vec<ArchiveMember>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    // Position in the archive file after each member's header
+    let mut starts = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let kind = if header.entry_type().is_dir() {
+            FileKind::Directory
+        } else if header.entry_type().is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::File
+        };
+        starts.push(consumed.get());
+        members.push(ArchiveMember {
+            path: entry.path()?.into_owned(),
+            kind,
+            size: entry.size(),
+            compressed_size: 0,
+            modified: header.mtime().ok().and_then(|mtime| DateTime::from_timestamp(mtime as i64, 0)),
+            mode: header.mode().ok(),
+        });
+    }
+    
+    // The first member also takes what was read before it, so the members
+    // add up to the whole archive even when one block held all of them
+    if let Some(first) = starts.first_mut() {
+        *first = 0;
+    }
+    let end = consumed.get();
+    for (i, member) in members.iter_mut().enumerate() {
+        member.compressed_size = starts.get(i + 1).unwrap_or(&end) - starts[i];
+    }
+    Ok(members)
+}
+
+/// Buffered reader that counts the bytes taken out of it.
+///
+/// Decompressors reading through `BufRead` only consume the input they
+/// have decoded, so the count follows the compressed stream position
+/// instead of running ahead by a whole buffer.
+struct Counted<R> {
+    inner: BufReader<R>,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Counted<R> {
+    fn new(inner: R) -> (Self, Rc<Cell<u64>>) {
+        let consumed = Rc::new(Cell::new(0));
+        (Self { inner: BufReader::new(inner), consumed: consumed.clone() }, consumed)
+    }
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.consumed.set(self.consumed.get() + read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: Read> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    
+    fn consume(&mut self, amount: usize) {
+        self.consumed.set(self.consumed.get() + amount as u64);
+        self.inner.consume(amount);
+    }
+}
+
+/// Add the members of an archive below its entry `at`, as virtual entries
+/// with `FileEntry::in_archive` set.
+///
+/// Folders the archive does not list on their own are made up from the
+/// member paths, and a name listed twice keeps its last listing like an
+/// extractor would. The virtual folders are summed up here; the archive
+/// keeps its own size, so nothing above it changes. Returns the added entries.
+pub fn attach(result: &mut ScanResult, at: NodeId, members: Vec<ArchiveMember>) -> Vec<NodeId> {
+    let mut added = Vec::new();
+    let mut lookup: HashMap<(NodeId, String), NodeId> = HashMap::new();
+    for member in members {
+        let names: Vec<String> = member
+            .path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let Some((last, parents)) = names.split_last() else {
+            continue;
+        };
+        
+        let mut parent = at;
+        for name in parents {
+            parent = match lookup.get(&(parent, name.clone())) {
+                Some(&id) => id,
+                None => {
+                    let id = result.push_child(parent, name, virtual_entry(FileKind::Directory, 0, 0, None, None));
+                    lookup.insert((parent, name.clone()), id);
+                    added.push(id);
+                    id
+                }
+            };
+        }
+        
+        let entry = virtual_entry(member.kind, member.size, member.compressed_size, member.modified, member.mode);
+        match lookup.get(&(parent, last.clone())) {
+            Some(&id) if result.entry(id).is_directory() && member.kind == FileKind::Directory => {
+                // A folder listed after its contents
+                let existing = result.entry_mut(id);
+                existing.modified = member.modified;
+                existing.mode = member.mode;
+            }
+            Some(&id) if result.entry(id).children.is_empty() => {
+                let name = result.entry(id).name;
+                let extension = result.entry(id).extension;
+                *result.entry_mut(id) = FileEntry { name, extension, parent: Some(parent), ..entry };
+            }
+            // A file and a folder of the same name, the file cannot be shown
+            Some(_) => {}
+            None => {
+                let id = result.push_child(parent, last, entry);
+                lookup.insert((parent, last.clone()), id);
+                added.push(id);
+            }
+        }
+    }
+    result.names.release_lookup();
+    
+    // Children are added after their folders, so a reverse pass sums up from the leaves
+    for &id in added.iter().rev() {
+        if result.entry(id).is_directory() {
+            let (size, allocated) = result
+                .children(id)
+                .iter()
+                .map(|&child| result.entry(child))
+                .fold((0, 0), |(size, allocated), child| (size + child.size, allocated + child.allocated_size));
+            let entry = result.entry_mut(id);
+            entry.size = size;
+            entry.allocated_size = allocated;
+        }
+    }
+    added
+}
+
+/// Entry for an archive member, with the compressed size as its allocated size
+fn virtual_entry(
+    kind: FileKind,
+    size: u64,
+    compressed_size: u64,
+    modified: Option<DateTime<Utc>>,
+    mode: Option<u32>,
+) -> FileEntry {
+    FileEntry {
+        name: NameId::default(),
+        extension: None,
+        parent: None,
+        children: Vec::new(),
+        size,
+        allocated_size: compressed_size,
+        own_allocated: if kind == FileKind::Directory { 0 } else { compressed_size },
+        kind,
+        link_target: None,
+        modified,
+        accessed: None,
+        changed: None,
+        created: None,
+        uid: None,
+        gid: None,
+        mode,
+        nlink: 1,
+        shared: false,
+        via_link: false,
+        inode: None,
+        excluded: None,
+        read_error: false,
+        unfinished: false,
+        incomplete: false,
+        scanning: false,
+        summary: None,
+        in_archive: true,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::ScanOptions;
+    use crate::source::testing::scan;
+    use crate::source::{MemoryTree, RealFs};
+    use std::fs::File;
+    use std::io::Write;
+    
+    /// What the test archives hold, a file that packs well and a small one
+    const CONTENTS: [(&str, usize); 2] = [("docs/a.txt", 20_000), ("b.bin", 100)];
+    const MTIME: i64 = 1_700_000_000;
+    
+    fn write_tar<W: Write>(writer: W) -> W {
+        let mut builder = tar::Builder::new(writer);
+        for (path, len) in CONTENTS {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(len as u64);
+            header.set_mode(0o640);
+            header.set_mtime(MTIME as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, path, vec![b'a'; len].as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+    
+    fn listing(members: &[ArchiveMember]) -> Vec<(PathBuf, FileKind, u64)> {
+        members.iter().map(|member| (member.path.clone(), member.kind, member.size)).collect()
+    }
+    
+    fn member(path: &str, kind: FileKind, size: u64) -> ArchiveMember {
+        ArchiveMember { path: PathBuf::from(path), kind, size, compressed_size: size / 10, modified: None, mode: None }
+    }
+    
+    #[test]
+    fn tarballs_are_listed_with_their_packed_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["pack.tar", "pack.tgz", "pack.tar.zst"] {
+            let path = dir.path().join(name);
+            let file = File::create(&path).unwrap();
+            let kind = ArchiveKind::from_name(name).unwrap();
+            match kind {
+                ArchiveKind::Tar => {
+                    write_tar(file);
+                }
+                ArchiveKind::TarGz => {
+                    write_tar(flate2::write::GzEncoder::new(file, flate2::Compression::default())).finish().unwrap();
+                }
+                ArchiveKind::TarZst => {
+                    write_tar(zstd::Encoder::new(file, 3).unwrap()).finish().unwrap();
+                }
+                ArchiveKind::Zip => unreachable!(),
+            }
+            
+            let members = list_members(&RealFs, &path, kind).unwrap();
+            let expected: Vec<_> =
+                CONTENTS.iter().map(|&(path, len)| (PathBuf::from(path), FileKind::File, len as u64)).collect();
+            assert_eq!(listing(&members), expected, "{}", name);
+            assert!(members.iter().all(|m| m.mode == Some(0o640) && m.modified == DateTime::from_timestamp(MTIME, 0)));
+            
+            let packed: Vec<u64> = members.iter().map(|member| member.compressed_size).collect();
+            let on_disk = std::fs::metadata(&path).unwrap().len();
+            if kind == ArchiveKind::Tar {
+                // Each member with the header after it, the last one with the
+                // block that ends the archive; the padding behind it is not read
+                assert_eq!(packed, vec![512 + 20_480 + 512, 512 + 512]);
+                assert_eq!(packed.iter().sum::<u64>(), on_disk - 512);
+            } else {
+                let total: u64 = packed.iter().sum();
+                assert!(total > 0 && total <= on_disk, "{}: {:?} of {}", name, packed, on_disk);
+            }
+        }
+    }
+    
+    #[test]
+    fn zip_members_are_listed_from_the_central_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pack.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .unix_permissions(0o640);
+        zip.add_directory("docs/", options).unwrap();
+        for (path, len) in CONTENTS.into_iter().chain([("../outside.txt", 10)]) {
+            zip.start_file(path, options).unwrap();
+            zip.write_all(&vec![b'a'; len]).unwrap();
+        }
+        zip.finish().unwrap();
+        
+        let members = list_members(&RealFs, &path, ArchiveKind::Zip).unwrap();
+        // The name leaving the archive is left out
+        assert_eq!(
+            listing(&members),
+            vec![
+                (PathBuf::from("docs"), FileKind::Directory, 0),
+                (PathBuf::from("docs/a.txt"), FileKind::File, 20_000),
+                (PathBuf::from("b.bin"), FileKind::File, 100),
+            ]
+        );
+        // Stored members take up as much as they hold
+        assert!(members.iter().all(|member| member.compressed_size == member.size));
+        assert_eq!(members[1].mode.map(|mode| mode & 0o777), Some(0o640));
+        assert!(members[1].modified.is_some());
+    }
+    
+    #[test]
+    fn members_are_attached_below_the_archive() {
+        let mut tree = MemoryTree::new("/data");
+        tree.file(MemoryTree::ROOT, "pack.zip", 500);
+        let mut result = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        let totals = (result.total_size, result.total_allocated, result.file_count, result.dir_count);
+        let pack = result.find(Path::new("/data/pack.zip")).unwrap();
+        
+        let folder_time = DateTime::from_timestamp(MTIME, 0);
+        let added = attach(
+            &mut result,
+            pack,
+            vec![
+                member("docs/a.txt", FileKind::File, 1000),
+                member("docs/b.txt", FileKind::File, 10),
+                // Listed twice, the later listing wins
+                member("docs/a.txt", FileKind::File, 50),
+                // The folder after its contents, and a file by the same name
+                ArchiveMember { modified: folder_time, ..member("docs", FileKind::Directory, 0) },
+                member("docs", FileKind::File, 7),
+            ],
+        );
+        
+        assert_eq!(added.len(), 3);
+        assert!(added.iter().all(|&id| result.entry(id).in_archive));
+        let docs = result.entry(result.find(Path::new("/data/pack.zip/docs")).unwrap());
+        assert_eq!(docs.kind, FileKind::Directory);
+        assert_eq!((docs.size, docs.allocated_size), (60, 6));
+        assert_eq!(docs.modified, folder_time);
+        assert_eq!(result.entry(result.find(Path::new("/data/pack.zip/docs/a.txt")).unwrap()).size, 50);
+        
+        // The archive keeps its size on disk, the members add nothing to it
+        assert_eq!(result.entry(pack).size, 500);
+        assert_eq!((result.total_size, result.total_allocated, result.file_count, result.dir_count), totals);
+        result.recalculate_totals();
+        assert_eq!((result.total_size, result.total_allocated, result.file_count, result.dir_count), totals);
+        assert_eq!(result.entry(pack).size, 500);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::scanner::{NodeId, ScanOptions, ScanResult};
+use crate::snapshot::{self, SnapshotError};
+use bincode::Options;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of every checkpoint file
+const MAGIC: &[u8; 8] = b"DISKRAYC";
+
+/// What a checkpoint holds, readable without loading the tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointInfo {
+    pub root_path: PathBuf,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub saved: DateTime<Utc>,
+    /// Entries in the tree built so far
+    pub entries: u64,
+    /// Time spent scanning before the checkpoint, over all resumed runs
+    pub elapsed: std::time::Duration,
+    pub options: ScanOptions,
+}
+
+/// State of an unfinished scan, enough to continue it in a later run.
+///
+/// `result` holds every directory listed so far, `queue` the directories in
+/// it that were found but not listed yet. Directories at the depth limit
+/// whose summary was still being gathered are queued again as a whole.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub info: CheckpointInfo,
+    pub result: ScanResult,
+    pub queue: Vec<NodeId>,
+    /// (device, inode) of the directories already entered through a symlink
+    pub followed: HashSet<(u64, u64)>,
+    /// (device, inode) of the hard-linked files already added to a summary
+    pub summarized_links: HashSet<(u64, u64)>,
+}
+
+/// Where checkpoints of scans of `root` are kept, in the user's cache directory
+pub fn file_for(root: &Path) -> Option<PathBuf> {
+    // FNV-1a, stable across runs and Rust versions unlike the std hasher
+    let hash = root
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    dirs::cache_dir().map(|dir| dir.join("diskray").join("checkpoints").join(format!("{:016x}.checkpoint", hash)))
+}
+
+/// Write a checkpoint.
+///
+/// The layout is the magic bytes and format version like a snapshot, the
+/// `CheckpointInfo` as plain bincode, then the rest zstd-compressed.
+pub fn write_checkpoint<W: Write>(checkpoint: &Checkpoint, mut writer: W) -> Result<(), SnapshotError> {
+    snapshot::write_header(&mut writer, MAGIC)?;
+    snapshot::encoding().serialize_into(&mut writer, &checkpoint.info)?;
+    let body = (&checkpoint.result, &checkpoint.queue, &checkpoint.followed, &checkpoint.summarized_links);
+    snapshot::write_compressed(&body, writer)
+}
+
+/// Read only the `CheckpointInfo` of a checkpoint
+pub fn read_info<R: Read>(mut reader: R) -> Result<CheckpointInfo, SnapshotError> {
+    snapshot::read_header(&mut reader, MAGIC)?;
+    Ok(snapshot::encoding().deserialize_from(&mut reader)?)
+}
+
+/// Read a checkpoint written by `write_checkpoint`
+pub fn read_checkpoint<R: Read>(mut reader: R) -> Result<Checkpoint, SnapshotError> {
+    let info = read_info(&mut reader)?;
+    let (result, queue, followed, summarized_links) = snapshot::read_compressed(reader)?;
+    Ok(Checkpoint {
+        info,
+        result,
+        queue,
+        followed,
+        summarized_links,
+    })
+}
+
+/// Save a checkpoint to `path`, replacing the previous one only once the new one is complete
+pub fn save(checkpoint: &Checkpoint, path: &Path) -> Result<(), SnapshotError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("partial");
+    write_checkpoint(checkpoint, BufWriter::new(File::create(&partial)?))?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Load a checkpoint saved with `save`
+pub fn load(path: &Path) -> Result<Checkpoint, SnapshotError> {
+    read_checkpoint(BufReader::new(File::open(path)?))
+}
+
+/// Load only the `CheckpointInfo` of a checkpoint saved with `save`
+pub fn load_info(path: &Path) -> Result<CheckpointInfo, SnapshotError> {
+    read_info(BufReader::new(File::open(path)?))
+}
+
+/// Delete a checkpoint, a missing one is not an error
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::testing::scan;
+    use crate::source::MemoryTree;
+    
+    fn sample() -> Checkpoint {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        tree.file(docs, "a.txt", 100);
+        tree.dir(MemoryTree::ROOT, "media");
+        let options = ScanOptions { threads: 1, ..Default::default() };
+        let result = scan(&tree, &options);
+        let queue = vec![result.find(Path::new("/data/media")).unwrap()];
+        Checkpoint {
+            info: CheckpointInfo {
+                root_path: PathBuf::from("/data"),
+                saved: Utc::now(),
+                entries: result.entries.len() as u64,
+                elapsed: std::time::Duration::from_secs(90),
+                options,
+            },
+            result,
+            queue,
+            followed: HashSet::from([(1, 2)]),
+            summarized_links: HashSet::from([(3, 4), (5, 6)]),
+        }
+    }
+    
+    #[test]
+    fn checkpoints_read_back_the_same() {
+        let checkpoint = sample();
+        let mut data = Vec::new();
+        write_checkpoint(&checkpoint, &mut data).unwrap();
+        
+        let info = read_info(data.as_slice()).unwrap();
+        assert_eq!(info.root_path, Path::new("/data"));
+        assert_eq!((info.entries, info.elapsed), (4, std::time::Duration::from_secs(90)));
+        assert_eq!(info.saved.timestamp(), checkpoint.info.saved.timestamp());
+        
+        let loaded = read_checkpoint(data.as_slice()).unwrap();
+        assert!(loaded.info.options.same_walk(&checkpoint.info.options));
+        assert_eq!(loaded.queue, checkpoint.queue);
+        assert_eq!(loaded.result.path_of(loaded.queue[0]), Path::new("/data/media"));
+        assert_eq!(loaded.followed, checkpoint.followed);
+        assert_eq!(loaded.summarized_links, checkpoint.summarized_links);
+        assert_eq!(loaded.result.total_size, 100);
+        assert_eq!(loaded.result.entries.len(), checkpoint.result.entries.len());
+    }
+    
+    #[test]
+    fn checkpoints_are_saved_loaded_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("scan.checkpoint");
+        save(&sample(), &path).unwrap();
+        assert!(!path.with_extension("partial").exists());
+        assert_eq!(load_info(&path).unwrap().entries, 4);
+        assert_eq!(load(&path).unwrap().queue.len(), 1);
+        
+        // A snapshot is not a checkpoint, even with the same format version
+        let snapshot = dir.path().join("scan.diskray");
+        snapshot::save(&sample().result, &snapshot).unwrap();
+        assert!(matches!(load(&snapshot), Err(SnapshotError::NotASnapshot)));
+        
+        remove(&path);
+        assert!(!path.exists());
+        remove(&path);
+    }
+    
+    #[test]
+    fn each_root_has_its_own_checkpoint_file() {
+        let Some(data) = file_for(Path::new("/data")) else {
+            return;
+        };
+        assert_eq!(file_for(Path::new("/data")), Some(data.clone()));
+        assert_ne!(file_for(Path::new("/data/docs")), Some(data.clone()));
+        assert_eq!(data.extension().unwrap(), "checkpoint");
+    }
+}
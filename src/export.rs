@@ -0,0 +1,89 @@
+use crate::scanner::ScanResult;
+use chrono::{DateTime, Utc};
+use std::io::{self, Write};
+
+/// Write every entry of a scan as one CSV row
+pub fn write_csv<W: Write>(scan_result: &ScanResult, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "path,type,size,allocated_size,modified,extension,links,hard_link,link_target,broken_link,uid,user,gid,group,mode,accessed,changed,created,in_archive")?;
+    
+    let mut result = Ok(());
+    scan_result.for_each_path(|id, path| {
+        if result.is_err() {
+            return;
+        }
+        let entry = scan_result.entry(id);
+        result = writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&path.to_string_lossy()),
+            entry.kind.as_str(),
+            entry.size,
+            entry.allocated_size,
+            csv_time(entry.modified),
+            csv_field(scan_result.extension(id).unwrap_or("")),
+            entry.nlink,
+            entry.shared,
+            csv_field(&entry.link_target.as_ref().map_or(String::new(), |t| t.path.to_string_lossy().to_string())),
+            entry.is_broken_link(),
+            entry.uid.map_or(String::new(), |uid| uid.to_string()),
+            csv_field(scan_result.user_name(id).unwrap_or("")),
+            entry.gid.map_or(String::new(), |gid| gid.to_string()),
+            csv_field(scan_result.group_name(id).unwrap_or("")),
+            entry.mode.map_or(String::new(), |mode| format!("{:o}", mode)),
+            csv_time(entry.accessed),
+            csv_time(entry.changed),
+            csv_time(entry.created),
+            entry.in_archive,
+        );
+    });
+    result?;
+    
+    writer.flush()
+}
+
+/// RFC 3339 timestamp, empty when unknown
+fn csv_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or(String::new(), |time| time.to_rfc3339())
+}
+
+/// Quote a field if it contains separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::ScanOptions;
+    use crate::source::testing::scan;
+    use crate::source::MemoryTree;
+    
+    #[test]
+    fn hard_links_are_counted_once_and_flagged_in_the_csv() {
+        let mut tree = MemoryTree::new("/data");
+        let a = tree.dir(MemoryTree::ROOT, "a");
+        let b = tree.dir(MemoryTree::ROOT, "b");
+        let original = tree.file(a, "f.bin", 1000);
+        tree.hard_link(a, "g.bin", original);
+        tree.hard_link(b, "h.bin", original);
+        tree.file(b, "x, \"y\".txt", 10);
+        let result = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        assert_eq!(result.total_size, 1010);
+        assert_eq!(result.entry(result.find(std::path::Path::new("/data/a")).unwrap()).size, 1000);
+        assert_eq!(result.entry(result.find(std::path::Path::new("/data/b")).unwrap()).size, 1010);
+        
+        let mut csv = Vec::new();
+        write_csv(&result, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 1 + result.entries.len());
+        let row = |path: &str| csv.lines().find(|line| line.starts_with(path)).unwrap().to_string();
+        let columns = |path: &str| row(path).split(',').skip(6).take(2).map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(columns("/data/a/f.bin,"), ["3", "true"]);
+        assert_eq!(columns("/data/b/h.bin,"), ["3", "true"]);
+        assert!(row("\"/data/b/x, \"\"y\"\".txt\",file,10,4096,").ends_with(",false"));
+    }
+}
@@ -1,6 +1,17 @@
 pub mod app;
 pub mod scanner;
+pub mod tree;
+pub mod rules;
 pub mod analyzer;
+pub mod export;
+pub mod snapshot;
+pub mod ncdu;
+pub mod checkpoint;
+pub mod throttle;
+pub mod progress;
+pub mod source;
+pub mod archive;
+pub mod watcher;
 pub mod ui;
 
 // Re-export commonly used types
@@ -1,7 +1,11 @@
 use eframe::egui;
 use diskray::app::DiskRayApp;
+use std::path::PathBuf;
 
 fn main() -> Result<(), eframe::Error> {
+    // Folders given on the command line are scanned right away, several of them into one tree
+    let roots: Vec<PathBuf> = std::env::args_os().skip(1).map(PathBuf::from).collect();
+    
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1400.0, 900.0])
@@ -30,7 +34,7 @@ fn main() -> Result<(), eframe::Error> {
                 // И т.д. для остальных полей
             });
 
-            Ok(Box::new(DiskRayApp::new()))
+            Ok(Box::new(DiskRayApp::with_roots(roots)))
         }),
     )
 }
\ No newline at end of file
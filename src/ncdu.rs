@@ -0,0 +1,484 @@
+use crate::scanner::{ExcludeReason, FileEntry, FileKind, NameId, NodeId, ScanError, ScanErrorKind, ScanOperation, ScanResult};
+use chrono::{DateTime, Utc};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the ncdu export format that is written, and the only major version read
+const MAJOR_VERSION: u64 = 1;
+const MINOR_VERSION: u64 = 2;
+
+/// File type bits of `st_mode`
+const S_IFMT: u32 = 0o170000;
+
+/// Header object of an export
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Metadata {
+    progname: String,
+    progver: String,
+    timestamp: Option<i64>,
+}
+
+/// One entry as ncdu writes it. Directories are written as an array holding
+/// their own entry followed by their children.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NcduEntry {
+    name: String,
+    /// Apparent size; for directories only their own, ncdu sums up the rest
+    #[serde(default, skip_serializing_if = "is_zero")]
+    asize: u64,
+    /// Allocated size
+    #[serde(default, skip_serializing_if = "is_zero")]
+    dsize: u64,
+    /// Device id, only written where it differs from the parent's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dev: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ino: Option<u64>,
+    /// The inode has more than one hard link
+    #[serde(default, skip_serializing_if = "is_false")]
+    hlnkc: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nlink: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    read_error: bool,
+    /// "pattern", "otherfs", "kernfs" or "frmlnk"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    excluded: Option<String>,
+    /// Neither a regular file nor a directory
+    #[serde(default, skip_serializing_if = "is_false")]
+    notreg: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gid: Option<u32>,
+    /// Full `st_mode`, file type bits included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime: Option<i64>,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Write a scan in the JSON export format of `ncdu -o`.
+///
+/// Directories get no apparent size of their own, and the contents of
+/// directories summarized by the depth limit become their own size.
+/// Archive members are left out, they are not on disk.
+pub fn write_ncdu<W: Write>(result: &ScanResult, mut writer: W) -> io::Result<()> {
+    write!(writer, "[{},{},", MAJOR_VERSION, MINOR_VERSION)?;
+    let metadata = Metadata {
+        progname: "diskray".to_string(),
+        progver: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp: Some(result.scan_time.timestamp()),
+    };
+    serde_json::to_writer(&mut writer, &metadata)?;
+    
+    // Directories still open, with the index of their next child and their device;
+    // an explicit stack, since exports are as deep as the tree
+    let mut open: Vec<(NodeId, usize, Option<u64>)> = Vec::new();
+    if result.root().is_some() {
+        writer.write_all(b",\n")?;
+        if write_node(result, NodeId::ROOT, None, &mut writer)? {
+            open.push((NodeId::ROOT, 0, result.entry(NodeId::ROOT).device()));
+        }
+    }
+    while let Some((dir, next, dev)) = open.last_mut() {
+        let dev = *dev;
+        let Some(&child) = result.children(*dir).get(*next) else {
+            writer.write_all(b"]")?;
+            open.pop();
+            continue;
+        };
+        *next += 1;
+        if result.entry(child).in_archive {
+            continue;
+        }
+        writer.write_all(b",\n")?;
+        if write_node(result, child, dev, &mut writer)? {
+            open.push((child, 0, result.entry(child).device().or(dev)));
+        }
+    }
+    writer.write_all(b"]\n")?;
+    writer.flush()
+}
+
+/// Write the entry itself, opening an array for a directory's children.
+/// Returns whether the array was opened.
+fn write_node<W: Write>(result: &ScanResult, id: NodeId, parent_dev: Option<u64>, writer: &mut W) -> io::Result<bool> {
+    let entry = result.entry(id);
+    let name = if id == NodeId::ROOT {
+        result.root_path.to_string_lossy().to_string()
+    } else {
+        result.name(id).to_string()
+    };
+    let summary = entry.summary.as_deref();
+    let info = NcduEntry {
+        name,
+        asize: if entry.is_directory() {
+            summary.map_or(0, |summary| summary.size)
+        } else {
+            entry.size
+        },
+        dsize: entry.own_allocated + summary.map_or(0, |summary| summary.allocated_size),
+        dev: entry.device().filter(|&dev| Some(dev) != parent_dev),
+        ino: entry.inode.map(|(_, ino)| ino),
+        hlnkc: entry.shared,
+        nlink: entry.shared.then_some(entry.nlink),
+        read_error: entry.read_error,
+        excluded: entry.excluded.map(|reason| {
+            match reason {
+                ExcludeReason::OtherFilesystem => "otherfs",
+                ExcludeReason::Pattern => "pattern",
+            }
+            .to_string()
+        }),
+        notreg: !matches!(entry.kind, FileKind::File | FileKind::Directory),
+        uid: entry.uid,
+        gid: entry.gid,
+        mode: entry.mode,
+        mtime: entry.modified.map(|time| time.timestamp()),
+    };
+    
+    // Excluded directories are plain entries, like ncdu writes them
+    let open = entry.is_directory() && entry.excluded.is_none();
+    if open {
+        writer.write_all(b"[")?;
+    }
+    serde_json::to_writer(&mut *writer, &info)?;
+    Ok(open)
+}
+
+/// Read an export written by `ncdu -o` or `write_ncdu`.
+///
+/// Entries are added to the tree while the JSON is parsed, so large exports
+/// are never held in memory twice. The nesting depth is not limited, an
+/// export is as deep as the directory tree it was made from, so the stack
+/// is grown on the heap as the parser descends.
+pub fn read_ncdu<R: Read>(reader: R) -> serde_json::Result<ScanResult> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.disable_recursion_limit();
+    let result = ExportSeed.deserialize(serde_stacker::Deserializer::new(&mut deserializer))?;
+    deserializer.end()?;
+    Ok(result)
+}
+
+/// Save a scan as an ncdu export at `path`
+pub fn save(result: &ScanResult, path: &Path) -> io::Result<()> {
+    write_ncdu(result, BufWriter::new(File::create(path)?))
+}
+
+/// Load an ncdu export from `path`
+pub fn load(path: &Path) -> serde_json::Result<ScanResult> {
+    let file = File::open(path).map_err(serde_json::Error::io)?;
+    read_ncdu(BufReader::new(file))
+}
+
+/// The whole export: `[major, minor, metadata, root directory]`
+struct ExportSeed;
+
+impl<'de> DeserializeSeed<'de> for ExportSeed {
+    type Value = ScanResult;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<ScanResult, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ExportSeed {
+    type Value = ScanResult;
+    
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an ncdu export")
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ScanResult, A::Error> {
+        let major: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        if major != MAJOR_VERSION {
+            return Err(de::Error::custom(format!("ncdu export format {} is not supported", major)));
+        }
+        let _minor: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let metadata: Metadata = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        
+        let mut result = ScanResult::new(PathBuf::new());
+        seq.next_element_seed(DirSeed { result: &mut result, parent: None, dev: 0 })?
+            .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        
+        result.names.release_lookup();
+        result.recalculate_totals();
+        result.scan_time = metadata
+            .timestamp
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+            .unwrap_or_else(Utc::now);
+        Ok(result)
+    }
+}
+
+/// A directory: its own entry, then its children
+struct DirSeed<'a> {
+    result: &'a mut ScanResult,
+    /// `None` for the root, whose name is its full path
+    parent: Option<NodeId>,
+    /// Device of the parent, for entries that do not repeat it
+    dev: u64,
+}
+
+impl<'de> DeserializeSeed<'de> for DirSeed<'_> {
+    type Value = ();
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DirSeed<'_> {
+    type Value = ();
+    
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an ncdu directory array")
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let info: NcduEntry = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let dev = info.dev.unwrap_or(self.dev);
+        let id = add_entry(self.result, self.parent, info, true, dev);
+        while seq
+            .next_element_seed(ItemSeed { result: &mut *self.result, parent: id, dev })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// A child of a directory, an object for everything but directories
+struct ItemSeed<'a> {
+    result: &'a mut ScanResult,
+    parent: NodeId,
+    dev: u64,
+}
+
+impl<'de> DeserializeSeed<'de> for ItemSeed<'_> {
+    type Value = ();
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ItemSeed<'_> {
+    type Value = ();
+    
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an ncdu entry object or directory array")
+    }
+    
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        let info = NcduEntry::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        let dev = info.dev.unwrap_or(self.dev);
+        add_entry(self.result, Some(self.parent), info, false, dev);
+        Ok(())
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+        DirSeed { result: self.result, parent: Some(self.parent), dev: self.dev }.visit_seq(seq)
+    }
+}
+
+/// Add an imported entry to the tree, recording its read error
+fn add_entry(result: &mut ScanResult, parent: Option<NodeId>, info: NcduEntry, is_dir: bool, dev: u64) -> NodeId {
+    let excluded = info.excluded.as_deref().map(|reason| match reason {
+        "otherfs" | "kernfs" | "frmlnk" => ExcludeReason::OtherFilesystem,
+        _ => ExcludeReason::Pattern,
+    });
+    let kind = match info.mode.map(|mode| mode & S_IFMT) {
+        _ if is_dir => FileKind::Directory,
+        Some(0o040000) => FileKind::Directory,
+        Some(0o120000) => FileKind::Symlink,
+        Some(0o010000) => FileKind::Fifo,
+        Some(0o140000) => FileKind::Socket,
+        Some(0o060000) => FileKind::BlockDevice,
+        Some(0o020000) => FileKind::CharDevice,
+        Some(0o100000) => FileKind::File,
+        // Without the mode a placeholder of another filesystem is its mount point
+        _ if excluded == Some(ExcludeReason::OtherFilesystem) => FileKind::Directory,
+        // ncdu only says "not regular" (`notreg`), the exact kind is lost
+        _ => FileKind::File,
+    };
+    let is_dir = kind == FileKind::Directory;
+    let nlink = info.nlink.unwrap_or(if info.hlnkc { 2 } else { 1 });
+    
+    let entry = FileEntry {
+        name: NameId::default(),
+        extension: None,
+        parent: None,
+        children: Vec::new(),
+        size: if is_dir { 0 } else { info.asize },
+        allocated_size: info.dsize,
+        own_allocated: info.dsize,
+        kind,
+        link_target: None,
+        modified: info.mtime.and_then(|mtime| DateTime::from_timestamp(mtime, 0)),
+        accessed: None,
+        changed: None,
+        created: None,
+        uid: info.uid,
+        gid: info.gid,
+        mode: info.mode,
+        nlink,
+        shared: !is_dir && info.hlnkc,
+        via_link: false,
+        inode: info.ino.map(|ino| (dev, ino)),
+        excluded,
+        read_error: info.read_error,
+        unfinished: false,
+        incomplete: false,
+        scanning: false,
+        summary: None,
+        in_archive: false,
+    };
+    
+    let id = match parent {
+        Some(parent) => result.push_child(parent, &info.name, entry),
+        None => {
+            result.root_path = PathBuf::from(&info.name);
+            result.push_root(entry)
+        }
+    };
+    if excluded == Some(ExcludeReason::Pattern) {
+        result.excluded_count += 1;
+    }
+    if info.read_error {
+        result.errors.push(ScanError {
+            path: result.path_of(id),
+            operation: if is_dir { ScanOperation::ReadDir } else { ScanOperation::Stat },
+            kind: ScanErrorKind::Io,
+            message: "could not be read when ncdu scanned it".to_string(),
+        });
+    }
+    id
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{PathRule, PatternKind, RuleAction};
+    use crate::scanner::ScanOptions;
+    use crate::source::testing::{listing, scan};
+    use crate::source::MemoryTree;
+    
+    /// Export written by ncdu 1.15 with `-e`: a hard link pair, a symlink, an
+    /// excluded folder, an unreadable one, a mount point and a sparse file
+    const NCDU_SAMPLE: &str = include_str!("../tests/data/ncdu-1.x.json");
+    
+    fn round_trip(result: &ScanResult) -> (String, ScanResult) {
+        let mut data = Vec::new();
+        write_ncdu(result, &mut data).unwrap();
+        let imported = read_ncdu(data.as_slice()).unwrap();
+        (String::from_utf8(data).unwrap(), imported)
+    }
+    
+    #[test]
+    fn exports_read_back_the_same() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        let report = tree.file(docs, "report.pdf", 10000);
+        tree.file(docs, "notes.txt", 10);
+        let backup = tree.dir(MemoryTree::ROOT, "backup");
+        tree.hard_link(backup, "report.pdf", report);
+        tree.file(MemoryTree::ROOT, "cache.tmp", 500);
+        let secret = tree.dir(MemoryTree::ROOT, "secret");
+        tree.file(secret, "key", 32);
+        tree.deny(secret);
+        
+        let options = ScanOptions {
+            rules: vec![PathRule::new(RuleAction::Exclude, PatternKind::Glob, "*.tmp")],
+            ..Default::default()
+        };
+        let result = scan(&tree, &options);
+        let (json, imported) = round_trip(&result);
+        
+        // Both names of the hard link carry their inode, so the import counts it once
+        assert_eq!(json.matches("\"hlnkc\":true").count(), 2);
+        assert_eq!(json.matches("\"excluded\":\"pattern\"").count(), 1);
+        assert_eq!(json.matches("\"read_error\":true").count(), 1);
+        
+        assert_eq!(imported.root_path, Path::new("/data"));
+        assert_eq!(listing(&imported), listing(&result));
+        // Apparent sizes and whole 4 KiB blocks differ
+        assert_eq!(imported.total_size, 10010);
+        assert_eq!(imported.total_allocated, (3 + 1) * 4096 + 4 * 4096);
+        assert_eq!((imported.total_size, imported.total_allocated), (result.total_size, result.total_allocated));
+        assert_eq!((imported.file_count, imported.dir_count), (result.file_count, result.dir_count));
+        assert_eq!(imported.excluded_count, 1);
+        assert_eq!(imported.errors.len(), 1);
+        assert_eq!(imported.errors[0].path, Path::new("/data/secret"));
+        assert_eq!(imported.errors[0].operation, ScanOperation::ReadDir);
+        assert_eq!(imported.scan_time.timestamp(), result.scan_time.timestamp());
+    }
+    
+    #[test]
+    fn deep_exports_do_not_overflow_the_stack() {
+        const DEPTH: usize = 20000;
+        let mut json = String::from("[1,2,{},\n[{\"name\":\"/deep\"}");
+        for _ in 0..DEPTH {
+            json.push_str(",[{\"name\":\"d\"}");
+        }
+        json.push_str(",{\"name\":\"bottom.bin\",\"asize\":7,\"dsize\":4096}");
+        json.push_str(&"]".repeat(DEPTH + 2));
+        
+        let result = read_ncdu(json.as_bytes()).unwrap();
+        assert_eq!(result.entries.len(), DEPTH + 2);
+        assert_eq!((result.total_size, result.file_count), (7, 1));
+        let bottom = NodeId(result.entries.len() as u32 - 1);
+        assert_eq!(result.path_of(bottom).components().count(), DEPTH + 3);
+        
+        let (_, again) = round_trip(&result);
+        assert_eq!(again.entries.len(), DEPTH + 2);
+        assert_eq!(again.total_size, 7);
+    }
+    
+    #[test]
+    fn ncdu_1_exports_are_imported() {
+        let result = read_ncdu(NCDU_SAMPLE.as_bytes()).unwrap();
+        let root = Path::new("/home/user/project");
+        let entry_at = |path: &str| result.entry(result.find(&root.join(path)).unwrap());
+        
+        assert_eq!(result.root_path, root);
+        assert_eq!(result.scan_time.timestamp(), 1600000000);
+        // Directories count only their contents, the hard link pair once
+        assert_eq!(result.total_size, 1534 + 12000 + 6 + 1073741824);
+        assert_eq!(entry_at("src").size, 12006);
+        assert_eq!(entry_at("src").allocated_size, 4096 + 12288);
+        assert!(entry_at("src/main.c").shared && entry_at("src/main-copy.c").shared);
+        assert_eq!(entry_at("src/main.c").inode, Some((2049, 1003)));
+        assert_eq!(entry_at("src/current").kind, FileKind::Symlink);
+        
+        // A sparse file takes far less space than its length
+        let image = entry_at("disk.img");
+        assert_eq!((image.size, image.allocated_size), (1073741824, 8192));
+        assert_eq!(image.mode, Some(0o100644));
+        assert_eq!(image.modified.unwrap().timestamp(), 1599930000);
+        
+        assert_eq!(entry_at("build").excluded, Some(ExcludeReason::Pattern));
+        let mount = entry_at("data");
+        assert_eq!((mount.excluded, mount.kind), (Some(ExcludeReason::OtherFilesystem), FileKind::Directory));
+        assert_eq!(mount.inode, None);
+        assert!(entry_at("private").read_error);
+        assert_eq!(result.excluded_count, 1);
+        assert_eq!(result.errors.len(), 1);
+        // Excluded entries are not counted, the mount point included
+        assert_eq!((result.file_count, result.dir_count), (5, 3));
+    }
+}
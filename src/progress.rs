@@ -0,0 +1,279 @@
+use crate::scanner::{ScanState, TotalEstimate};
+use crate::throttle::ThrottleState;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the folder being listed is recorded for display
+const PATH_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Span over which the speed is measured, long enough to smooth out single
+/// large folders and short enough to follow a change of pace
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Minimum time between two counter readings kept for the speed
+const RATE_SAMPLE_SPACING: Duration = Duration::from_millis(250);
+
+/// Scan progress information, as read at one moment
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    /// Folder being listed, sampled every `PATH_SAMPLE_INTERVAL`
+    pub current_path: PathBuf,
+    pub files_scanned: u64,
+    pub estimated_total: Option<TotalEstimate>,
+    pub bytes_scanned: u64,
+    pub state: ScanState,
+    pub error_count: u64,
+    /// How much the scan is holding back, see `ScanOptions::gentle`
+    pub throttle: ThrottleState,
+    /// Time since the scan started, stopped once it ends
+    pub elapsed: Duration,
+    /// Entries listed per second, over the last few seconds while the scan
+    /// runs and over the whole scan once it ended
+    pub entries_per_sec: f64,
+    /// Apparent size of the listed files per second, measured like `entries_per_sec`
+    pub bytes_per_sec: f64,
+    /// Time left at the current speed, while the estimated total is not reached yet
+    pub eta: Option<Duration>,
+}
+
+impl ScanProgress {
+    /// Share of the estimated total listed so far, at most 1
+    pub fn fraction(&self) -> Option<f32> {
+        self.estimated_total
+            .map(|estimate| (self.files_scanned as f64 / estimate.entries.max(1) as f64).min(1.0) as f32)
+    }
+}
+
+/// Progress of a scan, reported by its workers and read by the UI.
+///
+/// The counters are atomics, so workers never wait on each other to report
+/// a listing. The folder shown is behind a lock, but it is only replaced
+/// every `PATH_SAMPLE_INTERVAL` by the one worker that claims the sample.
+pub(crate) struct LiveProgress {
+    files_scanned: AtomicU64,
+    bytes_scanned: AtomicU64,
+    error_count: AtomicU64,
+    /// When the folder may be sampled next, in nanoseconds since `epoch`
+    next_path_sample: AtomicU64,
+    epoch: Instant,
+    current_path: Mutex<PathBuf>,
+    estimated_total: Mutex<Option<TotalEstimate>>,
+    state: Mutex<ScanState>,
+    throttle: Mutex<ThrottleState>,
+    timing: Mutex<Timing>,
+}
+
+/// When a scan ran, and recent counter readings to measure its speed
+#[derive(Default)]
+struct Timing {
+    started: Option<Instant>,
+    ended: Option<Instant>,
+    /// Entries and bytes counted before the start or taken over from a checkpoint
+    initial: (u64, u64),
+    /// (time, entries, bytes), oldest first
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl LiveProgress {
+    pub fn new() -> Self {
+        Self {
+            files_scanned: AtomicU64::new(0),
+            bytes_scanned: AtomicU64::new(0),
+            error_count: AtomicU64::new(0),
+            next_path_sample: AtomicU64::new(0),
+            epoch: Instant::now(),
+            current_path: Mutex::new(PathBuf::new()),
+            estimated_total: Mutex::new(None),
+            state: Mutex::new(ScanState::Idle),
+            throttle: Mutex::new(ThrottleState::default()),
+            timing: Mutex::new(Timing::default()),
+        }
+    }
+    
+    /// Mark the scan as running and start the clock
+    pub fn start(&self) {
+        let now = Instant::now();
+        let initial = (self.files_scanned.load(Ordering::Relaxed), self.bytes_scanned.load(Ordering::Relaxed));
+        *self.timing.lock() = Timing {
+            started: Some(now),
+            ended: None,
+            initial,
+            samples: VecDeque::from([(now, initial.0, initial.1)]),
+        };
+        *self.state.lock() = ScanState::Running;
+    }
+    
+    /// Count the entries of a listed folder and the apparent size of its files
+    pub fn add(&self, dir: &Path, entries: u64, bytes: u64) {
+        self.files_scanned.fetch_add(entries, Ordering::Relaxed);
+        self.bytes_scanned.fetch_add(bytes, Ordering::Relaxed);
+        
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        let due = self.next_path_sample.load(Ordering::Relaxed);
+        let next = now + PATH_SAMPLE_INTERVAL.as_nanos() as u64;
+        if now >= due
+            && self
+                .next_path_sample
+                .compare_exchange(due, next, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            *self.current_path.lock() = dir.to_path_buf();
+        }
+    }
+    
+    /// Count the entries taken over from a checkpoint, without them speeding up the scan
+    pub fn add_resumed(&self, entries: u64, bytes: u64) {
+        let mut timing = self.timing.lock();
+        self.files_scanned.fetch_add(entries, Ordering::Relaxed);
+        self.bytes_scanned.fetch_add(bytes, Ordering::Relaxed);
+        timing.initial.0 += entries;
+        timing.initial.1 += bytes;
+        for sample in &mut timing.samples {
+            sample.1 += entries;
+            sample.2 += bytes;
+        }
+    }
+    
+    pub fn record_error(&self) {
+        self.error_count.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    pub fn set_estimate(&self, estimate: Option<TotalEstimate>) {
+        *self.estimated_total.lock() = estimate;
+    }
+    
+    pub fn set_throttle(&self, throttle: ThrottleState) {
+        *self.throttle.lock() = throttle;
+    }
+    
+    /// Move the scan to `state`, stopping the clock when it is terminal
+    pub fn set_state(&self, state: ScanState) {
+        if state.is_terminal() {
+            self.timing.lock().ended = Some(Instant::now());
+        }
+        *self.state.lock() = state;
+    }
+    
+    pub fn state(&self) -> ScanState {
+        self.state.lock().clone()
+    }
+    
+    /// Read the progress, with the speed over the last `RATE_WINDOW`
+    pub fn snapshot(&self) -> ScanProgress {
+        let now = Instant::now();
+        let mut timing = self.timing.lock();
+        let files_scanned = self.files_scanned.load(Ordering::Relaxed);
+        let bytes_scanned = self.bytes_scanned.load(Ordering::Relaxed);
+        
+        let elapsed = timing
+            .started
+            .map_or(Duration::ZERO, |started| timing.ended.unwrap_or(now).saturating_duration_since(started));
+        if timing.ended.is_none() && timing.started.is_some() {
+            if timing.samples.back().is_none_or(|&(time, _, _)| now - time >= RATE_SAMPLE_SPACING) {
+                timing.samples.push_back((now, files_scanned, bytes_scanned));
+            }
+            // Keep one reading at least a window old, to measure over the whole window
+            while timing.samples.len() > 2 && now - timing.samples[1].0 >= RATE_WINDOW {
+                timing.samples.pop_front();
+            }
+        }
+        
+        // A finished scan is measured from start to end
+        let (since, base_entries, base_bytes) = match (timing.ended, timing.samples.front()) {
+            (None, Some(&oldest)) => oldest,
+            _ => (timing.started.unwrap_or(now), timing.initial.0, timing.initial.1),
+        };
+        let span = timing.ended.unwrap_or(now).saturating_duration_since(since).as_secs_f64();
+        drop(timing);
+        let (entries_per_sec, bytes_per_sec) = if span > 0.0 {
+            (
+                files_scanned.saturating_sub(base_entries) as f64 / span,
+                bytes_scanned.saturating_sub(base_bytes) as f64 / span,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        
+        let state = self.state();
+        let estimated_total = *self.estimated_total.lock();
+        let eta = estimated_total
+            .filter(|estimate| state == ScanState::Running && estimate.entries > files_scanned && entries_per_sec > 0.0)
+            .map(|estimate| Duration::from_secs_f64((estimate.entries - files_scanned) as f64 / entries_per_sec));
+        
+        ScanProgress {
+            current_path: self.current_path.lock().clone(),
+            files_scanned,
+            estimated_total,
+            bytes_scanned,
+            state,
+            error_count: self.error_count.load(Ordering::Relaxed),
+            throttle: *self.throttle.lock(),
+            elapsed,
+            entries_per_sec,
+            bytes_per_sec,
+            eta,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::EstimateSource;
+    
+    fn estimate(entries: u64) -> Option<TotalEstimate> {
+        Some(TotalEstimate { entries, source: EstimateSource::PreviousScan })
+    }
+    
+    #[test]
+    fn the_fraction_is_capped_at_the_estimate() {
+        let mut progress = ScanProgress { files_scanned: 50, ..Default::default() };
+        assert_eq!(progress.fraction(), None);
+        progress.estimated_total = estimate(200);
+        assert_eq!(progress.fraction(), Some(0.25));
+        // Estimates can fall short of what the scan finds
+        progress.files_scanned = 300;
+        assert_eq!(progress.fraction(), Some(1.0));
+        progress.estimated_total = estimate(0);
+        assert_eq!(progress.fraction(), Some(1.0));
+    }
+    
+    #[test]
+    fn the_eta_follows_the_speed_until_the_estimate_is_reached() {
+        let progress = LiveProgress::new();
+        progress.start();
+        progress.add(Path::new("/data"), 100, 1000);
+        std::thread::sleep(Duration::from_millis(20));
+        let without = progress.snapshot();
+        assert!(without.entries_per_sec > 0.0);
+        assert_eq!(without.eta, None);
+        
+        progress.set_estimate(estimate(1000));
+        let running = progress.snapshot();
+        let eta = running.eta.unwrap();
+        // What is left, at the measured speed
+        let left = eta.as_secs_f64() * running.entries_per_sec;
+        assert!((left - 900.0).abs() < 1.0, "{} entries left", left);
+        
+        // Entries from a checkpoint count toward the estimate but not the speed
+        progress.add_resumed(500, 0);
+        let resumed = progress.snapshot();
+        assert_eq!(resumed.files_scanned, 600);
+        assert!(resumed.entries_per_sec <= running.entries_per_sec);
+        
+        progress.add(Path::new("/data/docs"), 600, 0);
+        let past = progress.snapshot();
+        assert_eq!(past.eta, None);
+        assert_eq!(past.fraction(), Some(1.0));
+        
+        progress.set_estimate(estimate(5000));
+        progress.set_state(ScanState::Finished);
+        let finished = progress.snapshot();
+        assert_eq!(finished.eta, None);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(progress.snapshot().elapsed, finished.elapsed);
+    }
+}
@@ -0,0 +1,395 @@
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+
+/// Syntax of a rule's pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatternKind {
+    /// Shell glob: `*`, `**`, `?` and `[...]`
+    Glob,
+    /// Regular expression searched in the absolute path
+    Regex,
+}
+
+/// What a rule does with matching paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleAction {
+    /// Only files matching an include rule are kept, once any exists
+    Include,
+    /// Matching entries are skipped, directories are not descended into
+    Exclude,
+}
+
+/// One include or exclude rule of a scan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRule {
+    pub action: RuleAction,
+    pub kind: PatternKind,
+    pub pattern: String,
+}
+
+impl PathRule {
+    pub fn new(action: RuleAction, kind: PatternKind, pattern: &str) -> Self {
+        Self {
+            action,
+            kind,
+            pattern: pattern.to_string(),
+        }
+    }
+    
+    /// Check that the pattern compiles
+    pub fn validate(&self) -> Result<(), regex::Error> {
+        Matcher::new(self).map(|_| ())
+    }
+}
+
+/// What part of a path a compiled pattern is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchTarget {
+    /// Only the file name, for globs without a `/`
+    Name,
+    /// The path relative to the scan root, for globs with a `/`
+    RelativePath,
+    /// The absolute path, for globs starting with `/` and for regexes
+    AbsolutePath,
+}
+
+#[derive(Debug, Clone)]
+struct Matcher {
+    regex: Regex,
+    target: MatchTarget,
+}
+
+impl Matcher {
+    fn new(rule: &PathRule) -> Result<Self, regex::Error> {
+        match rule.kind {
+            PatternKind::Regex => Ok(Self {
+                regex: Regex::new(&rule.pattern)?,
+                target: MatchTarget::AbsolutePath,
+            }),
+            PatternKind::Glob => {
+                let pattern = rule.pattern.trim_end_matches('/');
+                let target = if pattern.starts_with('/') {
+                    MatchTarget::AbsolutePath
+                } else if pattern.contains('/') {
+                    MatchTarget::RelativePath
+                } else {
+                    MatchTarget::Name
+                };
+                Ok(Self {
+                    regex: Regex::new(&glob_to_regex(pattern))?,
+                    target,
+                })
+            }
+        }
+    }
+    
+    fn is_match(&self, name: &str, relative: &str, absolute: &str) -> bool {
+        match self.target {
+            MatchTarget::Name => self.regex.is_match(name),
+            MatchTarget::RelativePath => self.regex.is_match(relative),
+            MatchTarget::AbsolutePath => self.regex.is_match(absolute),
+        }
+    }
+}
+
+/// Compiled include/exclude rules, relative to the scan roots.
+///
+/// Rules apply in order and the last one matching a path decides, so an
+/// include rule after an exclude rule brings back the paths it matches.
+#[derive(Debug, Clone)]
+pub struct PathRules {
+    roots: Vec<String>,
+    rules: Vec<(RuleAction, Matcher)>,
+    has_include: bool,
+    has_exclude: bool,
+}
+
+impl PathRules {
+    pub fn new(root: &Path, rules: &[PathRule]) -> Result<Self, regex::Error> {
+        Self::with_roots(&[root.to_path_buf()], rules)
+    }
+    
+    /// Rules for a scan of several roots, relative paths are matched from
+    /// the root an entry is in
+    pub fn with_roots(roots: &[PathBuf], rules: &[PathRule]) -> Result<Self, regex::Error> {
+        let rules = rules
+            .iter()
+            .filter(|r| !r.pattern.trim().is_empty())
+            .map(|rule| Ok((rule.action, Matcher::new(rule)?)))
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+        
+        Ok(Self {
+            roots: roots.iter().map(|root| normalize(root)).collect(),
+            has_include: rules.iter().any(|(action, _)| *action == RuleAction::Include),
+            has_exclude: rules.iter().any(|(action, _)| *action == RuleAction::Exclude),
+            rules,
+        })
+    }
+    
+    /// True when there are no rules at all
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Whether the last rule matching an entry excludes it. Excluded
+    /// directories are not descended into, so no rule can bring back
+    /// anything below them.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.has_exclude && self.last_match(path) == Some(RuleAction::Exclude)
+    }
+    
+    /// Whether a file passes the include rules, once any exists
+    pub fn is_included(&self, path: &Path) -> bool {
+        !self.has_include || self.last_match(path) == Some(RuleAction::Include)
+    }
+    
+    /// Action of the last rule matching `path`
+    fn last_match(&self, path: &Path) -> Option<RuleAction> {
+        let (name, relative, absolute) = self.match_targets(path);
+        self.rules
+            .iter()
+            .rev()
+            .find(|(_, matcher)| matcher.is_match(&name, &relative, &absolute))
+            .map(|(action, _)| *action)
+    }
+    
+    fn match_targets(&self, path: &Path) -> (String, String, String) {
+        let absolute = normalize(path);
+        let relative = self
+            .roots
+            .iter()
+            .find_map(|root| {
+                let rest = absolute.strip_prefix(root.as_str())?;
+                // With several roots, `/srv` must not claim `/srv2/...`
+                let inside = self.roots.len() == 1 || root.ends_with('/') || rest.is_empty() || rest.starts_with('/');
+                inside.then_some(rest)
+            })
+            .map(|rest| rest.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| absolute.clone());
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        (name, relative, absolute)
+    }
+}
+
+/// Path as a string with `/` separators on every platform
+fn normalize(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    }
+}
+
+/// Translate a shell glob into an anchored regular expression
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directory at all
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' && !class.is_empty() {
+                        closed = true;
+                        break;
+                    }
+                    class.push(c);
+                }
+                if closed {
+                    let negated = class.starts_with('!');
+                    let body = if negated { &class[1..] } else { &class[..] };
+                    regex.push('[');
+                    if negated {
+                        regex.push('^');
+                    }
+                    regex.push_str(&body.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                } else {
+                    // No closing bracket, match everything literally
+                    regex.push_str(&regex::escape("["));
+                    regex.push_str(&regex::escape(&class));
+                }
+            }
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn glob(pattern: &str) -> Regex {
+        Regex::new(&glob_to_regex(pattern)).unwrap()
+    }
+    
+    fn rules(rules: &[(RuleAction, PatternKind, &str)]) -> PathRules {
+        let rules: Vec<PathRule> = rules
+            .iter()
+            .map(|&(action, kind, pattern)| PathRule::new(action, kind, pattern))
+            .collect();
+        PathRules::new(Path::new("/root"), &rules).unwrap()
+    }
+    
+    fn excludes(pattern: &str, path: &str) -> bool {
+        rules(&[(RuleAction::Exclude, PatternKind::Glob, pattern)]).is_excluded(Path::new(path))
+    }
+    
+    #[test]
+    fn star_stays_within_one_name() {
+        let star = glob("*.log");
+        assert!(star.is_match("error.log"));
+        assert!(star.is_match(".log"));
+        assert!(!star.is_match("error.log.gz"));
+        assert!(!star.is_match("logs/error.log"));
+        
+        assert!(excludes("src/*.rs", "/root/src/main.rs"));
+        assert!(!excludes("src/*.rs", "/root/src/ui/panel.rs"));
+    }
+    
+    #[test]
+    fn double_star_crosses_folders() {
+        let nested = glob("src/**/*.rs");
+        assert!(nested.is_match("src/main.rs"));
+        assert!(nested.is_match("src/ui/widgets/panel.rs"));
+        assert!(!nested.is_match("tests/main.rs"));
+        
+        assert!(glob("docs/**").is_match("docs/a/b/c.md"));
+        assert!(excludes("**/target", "/root/target"));
+        assert!(excludes("**/target", "/root/crates/core/target"));
+        assert!(!excludes("**/target", "/root/crates/target-specs"));
+    }
+    
+    #[test]
+    fn question_mark_is_one_character() {
+        let one = glob("file?.txt");
+        assert!(one.is_match("file1.txt"));
+        assert!(!one.is_match("file.txt"));
+        assert!(!one.is_match("file10.txt"));
+        assert!(!glob("a?b").is_match("a/b"));
+    }
+    
+    #[test]
+    fn character_classes() {
+        let class = glob("[abc].txt");
+        assert!(class.is_match("a.txt"));
+        assert!(!class.is_match("d.txt"));
+        
+        let range = glob("log[0-9]");
+        assert!(range.is_match("log7"));
+        assert!(!range.is_match("logx"));
+        
+        let negated = glob("[!.]*");
+        assert!(negated.is_match("visible"));
+        assert!(!negated.is_match(".hidden"));
+        
+        // `]` right after the opening bracket is part of the class
+        assert!(glob("[]]").is_match("]"));
+        // Without a closing bracket the pattern is literal
+        assert!(glob("[abc").is_match("[abc"));
+        assert!(!glob("[abc").is_match("a"));
+        // Regex syntax in the rest of a glob is literal too
+        assert!(glob("a+b.(c)").is_match("a+b.(c)"));
+        assert!(!glob("a+b.(c)").is_match("aab.(c)"));
+    }
+    
+    #[test]
+    fn globs_are_anchored() {
+        // Without '/', the whole name has to match
+        assert!(excludes("build", "/root/build"));
+        assert!(excludes("build", "/root/deep/build"));
+        assert!(!excludes("build", "/root/rebuild"));
+        assert!(!excludes("build", "/root/build.rs"));
+        
+        // With '/', the path from the scan root
+        assert!(excludes("deep/build", "/root/deep/build"));
+        assert!(!excludes("deep/build", "/root/other/deep/build"));
+        assert!(excludes("deep/build/", "/root/deep/build"));
+        
+        // Starting with '/', the absolute path
+        assert!(excludes("/root/build", "/root/build"));
+        assert!(!excludes("/root/build", "/root/deep/build"));
+    }
+    
+    #[test]
+    fn regexes_are_searched_unless_anchored() {
+        let cache = rules(&[(RuleAction::Exclude, PatternKind::Regex, "cache")]);
+        assert!(cache.is_excluded(Path::new("/root/a/.cache/b")));
+        assert!(cache.is_excluded(Path::new("/root/cached")));
+        
+        let anchored = rules(&[(RuleAction::Exclude, PatternKind::Regex, "^/root/tmp$")]);
+        assert!(anchored.is_excluded(Path::new("/root/tmp")));
+        assert!(!anchored.is_excluded(Path::new("/root/tmp/x")));
+        assert!(!anchored.is_excluded(Path::new("/home/root/tmp")));
+        
+        assert!(PathRule::new(RuleAction::Exclude, PatternKind::Regex, "(").validate().is_err());
+    }
+    
+    #[test]
+    fn later_include_overrides_exclude() {
+        let rules = rules(&[
+            (RuleAction::Exclude, PatternKind::Glob, "*.log"),
+            (RuleAction::Include, PatternKind::Glob, "keep.log"),
+        ]);
+        let keep = Path::new("/root/logs/keep.log");
+        assert!(!rules.is_excluded(keep));
+        assert!(rules.is_included(keep));
+        assert!(rules.is_excluded(Path::new("/root/logs/other.log")));
+        // Once an include rule exists, files it does not match are left out
+        assert!(!rules.is_excluded(Path::new("/root/notes.txt")));
+        assert!(!rules.is_included(Path::new("/root/notes.txt")));
+    }
+    
+    #[test]
+    fn later_exclude_overrides_include() {
+        let rules = rules(&[
+            (RuleAction::Include, PatternKind::Glob, "*.log"),
+            (RuleAction::Exclude, PatternKind::Glob, "debug.log"),
+        ]);
+        assert!(rules.is_excluded(Path::new("/root/debug.log")));
+        assert!(!rules.is_excluded(Path::new("/root/error.log")));
+        assert!(rules.is_included(Path::new("/root/error.log")));
+    }
+    
+    #[test]
+    fn empty_patterns_are_ignored() {
+        let rules = rules(&[(RuleAction::Include, PatternKind::Glob, " "), (RuleAction::Exclude, PatternKind::Regex, "")]);
+        assert!(rules.is_empty());
+        assert!(rules.is_included(Path::new("/root/anything")));
+        assert!(!rules.is_excluded(Path::new("/root/anything")));
+    }
+    
+    #[test]
+    fn relative_paths_from_the_matching_root() {
+        let rules = PathRules::with_roots(
+            &[PathBuf::from("/srv"), PathBuf::from("/srv2")],
+            &[PathRule::new(RuleAction::Exclude, PatternKind::Glob, "data/*.bin")],
+        )
+        .unwrap();
+        assert!(rules.is_excluded(Path::new("/srv/data/a.bin")));
+        assert!(rules.is_excluded(Path::new("/srv2/data/a.bin")));
+        assert!(!rules.is_excluded(Path::new("/srv2/other/data/a.bin")));
+    }
+}
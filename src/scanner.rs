@@ -1,93 +1,579 @@
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use walkdir::WalkDir;
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use crate::rules::{PathRule, PathRules};
+use crate::checkpoint::{self, Checkpoint, CheckpointInfo};
+use crate::snapshot;
+use bincode::Options;
+use crate::throttle::{self, Throttle};
+use crate::source::{EntryStat, RealFs, ScanSource};
+use crate::archive::{self, ArchiveKind, ArchiveMember};
+use crate::progress::LiveProgress;
 
-/// Represents a file or directory in the scan result
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FileEntry {
+pub use crate::progress::ScanProgress;
+pub use crate::throttle::{BusyReason, ThrottleState};
+pub use crate::tree::{ExcludeReason, FileEntry, FileKind, LinkTarget, NameId, NodeId, RootTotals, ScanResult, SizeMode, SubtreeSummary};
+
+/// Filesystem operation that failed during a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanOperation {
+    /// Reading the metadata of an entry
+    Stat,
+    /// Opening a directory for listing
+    ReadDir,
+    /// Reading the next entry of an open directory
+    ReadEntry,
+    /// Listing the members of an archive
+    ReadArchive,
+}
+
+impl std::fmt::Display for ScanOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanOperation::Stat => write!(f, "stat"),
+            ScanOperation::ReadDir => write!(f, "read directory"),
+            ScanOperation::ReadEntry => write!(f, "read directory entry"),
+            ScanOperation::ReadArchive => write!(f, "read archive"),
+        }
+    }
+}
+
+/// Classified cause of a scan error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    /// The entry was deleted while the scan was running
+    Vanished,
+    /// Too many levels of symbolic links
+    Loop,
+    Io,
+}
+
+impl std::fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanErrorKind::PermissionDenied => write!(f, "permission denied"),
+            ScanErrorKind::Vanished => write!(f, "vanished"),
+            ScanErrorKind::Loop => write!(f, "symlink loop"),
+            ScanErrorKind::Io => write!(f, "I/O error"),
+        }
+    }
+}
+
+/// A path that could not be scanned
+#[derive(Debug, Clone, thiserror::Error, Serialize, Deserialize)]
+#[error("cannot {operation} {}: {message}", path.display())]
+pub struct ScanError {
     pub path: PathBuf,
-    pub name: String,
-    pub size: u64,
-    pub is_directory: bool,
-    #[serde(with = "chrono::serde::ts_seconds")]
-    pub modified: DateTime<Utc>,
-    pub extension: Option<String>,
-    pub parent: Option<PathBuf>,
-    pub children: Vec<PathBuf>,
-}
-
-/// Result of a file system scan
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ScanResult {
-    pub root_path: PathBuf,
-    pub total_size: u64,
-    pub file_count: u64,
-    pub dir_count: u64,
-    pub entries: Vec<FileEntry>,
-    pub scan_duration: std::time::Duration,
-    #[serde(with = "chrono::serde::ts_seconds")]
-    pub scan_time: DateTime<Utc>,
-}
-
-/// Scan progress information
-#[derive(Debug, Clone, Default)]
-pub struct ScanProgress {
-    pub current_path: PathBuf,
-    pub files_scanned: u64,
-    pub total_files: Option<u64>,
-    pub bytes_scanned: u64,
-    pub is_complete: bool,
-    pub error_count: u64,
+    pub operation: ScanOperation,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn from_io(path: PathBuf, operation: ScanOperation, error: &std::io::Error) -> Self {
+        let kind = match error.kind() {
+            std::io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            std::io::ErrorKind::NotFound => ScanErrorKind::Vanished,
+            _ if is_loop_error(error) => ScanErrorKind::Loop,
+            _ => ScanErrorKind::Io,
+        };
+        
+        Self {
+            path,
+            operation,
+            kind,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Options controlling how a scan is performed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Number of worker threads, 0 means one per CPU core
+    pub threads: usize,
+    /// Do not descend into directories on other filesystems, like `du -x`
+    pub one_file_system: bool,
+    /// Include/exclude rules, applied to everything below the root
+    pub rules: Vec<PathRule>,
+    /// Descend into symlinked directories and measure symlinked files, like `du -L`
+    pub follow_symlinks: bool,
+    /// Keep entries only down to this many levels below the root. The contents
+    /// of directories at the limit are folded into a `SubtreeSummary`, where
+    /// symlinks are not followed.
+    pub max_depth: Option<usize>,
+    /// List the members of .zip, .tar, .tar.gz and .tar.zst files as virtual
+    /// entries below them. Tarballs are read in full, which makes the scan
+    /// as slow as decompressing them.
+    #[serde(default)]
+    pub expand_archives: bool,
+    /// Run at low I/O and CPU priority and pause while the system is busy.
+    /// Only changes how fast the scan runs, so it is not saved with results.
+    #[serde(skip)]
+    pub gentle: bool,
+    /// Cap on metadata operations per second, over all threads
+    #[serde(skip)]
+    pub max_ops_per_sec: Option<u32>,
+}
+
+impl ScanOptions {
+    /// Whether scans with both options visit and keep the same entries,
+    /// however many threads they use
+    pub fn same_walk(&self, other: &ScanOptions) -> bool {
+        self.one_file_system == other.one_file_system
+            && self.rules == other.rules
+            && self.follow_symlinks == other.follow_symlinks
+            && self.max_depth == other.max_depth
+            && self.expand_archives == other.expand_archives
+    }
+}
+
+/// Where an estimated entry total comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstimateSource {
+    /// Used inode count of the filesystem mounted at the scan root
+    FilesystemInodes,
+    /// Entry count of the previous scan of the same root
+    PreviousScan,
+}
+
+/// Estimated number of entries a scan will visit.
+///
+/// Scans are single-pass, so the real total is only known at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalEstimate {
+    pub entries: u64,
+    pub source: EstimateSource,
+}
+
+/// Minimum time between two snapshots of a running scan.
+/// Tests publish them as often as copying the tree allows instead.
+const SNAPSHOT_INTERVAL: std::time::Duration = if cfg!(test) {
+    std::time::Duration::ZERO
+} else {
+    std::time::Duration::from_millis(500)
+};
+
+/// Minimum time between two checkpoints, shorter scans never write one.
+/// Tests write one as soon as possible instead.
+const CHECKPOINT_INTERVAL: std::time::Duration = if cfg!(test) {
+    std::time::Duration::ZERO
+} else {
+    std::time::Duration::from_secs(60)
+};
+
+/// Roots whose entry count is kept for the next scans, the least recently scanned are dropped
+const MAX_REMEMBERED_ROOTS: usize = 256;
+
+lazy_static::lazy_static! {
+    /// Entry counts of finished scans by root path, most recent last.
+    /// Loaded from `entry_counts_file` on first use, so they outlive the process.
+    static ref PREVIOUS_ENTRY_COUNTS: parking_lot::Mutex<Vec<(PathBuf, u64)>> =
+        parking_lot::Mutex::new(load_entry_counts());
+    
+    /// Name of this machine, looked up once
+    static ref LOCAL_HOSTNAME: String = sysinfo::System::host_name().unwrap_or_default();
+}
+
+/// Where the entry counts of finished scans are kept, next to the checkpoints
+fn entry_counts_file() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("diskray").join("entry-counts"))
+}
+
+/// Entry counts saved by earlier runs, none when the file is missing or unreadable
+fn load_entry_counts() -> Vec<(PathBuf, u64)> {
+    entry_counts_file()
+        .and_then(|file| std::fs::read(file).ok())
+        .and_then(|data| snapshot::encoding().deserialize(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Remember the entry counts of finished scans, to estimate later scans of the same roots
+fn remember_entry_counts(counts: impl IntoIterator<Item = (PathBuf, u64)>) {
+    let mut remembered = PREVIOUS_ENTRY_COUNTS.lock();
+    for (root, entries) in counts {
+        remembered.retain(|(path, _)| *path != root);
+        remembered.push((root, entries));
+    }
+    let excess = remembered.len().saturating_sub(MAX_REMEMBERED_ROOTS);
+    remembered.drain(..excess);
+    
+    // Best effort, without the file the next run only starts without an estimate
+    if let Some(file) = entry_counts_file() {
+        let _ = save_entry_counts(&remembered, &file);
+    }
+}
+
+/// Write the entry counts, replacing the previous file only once the new one is complete
+fn save_entry_counts(counts: &[(PathBuf, u64)], file: &Path) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = file.with_extension("partial");
+    std::fs::write(&partial, snapshot::encoding().serialize(counts)?)?;
+    std::fs::rename(&partial, file)?;
+    Ok(())
+}
+
+/// Lifecycle of a scan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ScanState {
+    /// Created but not started
+    #[default]
+    Idle,
+    Running,
+    /// Walked the whole tree
+    Finished,
+    /// Stopped early, the result only covers what was visited
+    Cancelled,
+    /// No result could be produced
+    Failed(String),
+}
+
+impl ScanState {
+    /// True once the worker thread is done, whatever the outcome
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, ScanState::Finished | ScanState::Cancelled | ScanState::Failed(_))
+    }
+}
+
+/// Shared state of one parallel scan
+struct ScanContext<'a> {
+    source: &'a dyn ScanSource,
+    options: &'a ScanOptions,
+    rules: PathRules,
+    /// Devices of the roots, for `ScanOptions::one_file_system`
+    root_devices: Vec<Option<u64>>,
+    excluded_count: AtomicU64,
+    excluded_size: AtomicU64,
+    should_stop: &'a AtomicBool,
+    progress: &'a LiveProgress,
+    /// Tree built so far, each directory is added under one lock
+    tree: parking_lot::Mutex<PartialTree>,
+    errors: parking_lot::Mutex<Vec<ScanError>>,
+    /// Where snapshots of the running scan are published
+    snapshot: &'a parking_lot::Mutex<Option<ScanResult>>,
+    start_time: std::time::Instant,
+    /// When the next snapshot is due
+    next_snapshot: parking_lot::Mutex<std::time::Instant>,
+    /// Where checkpoints of the scan are saved, if anywhere
+    checkpoint: Option<&'a Path>,
+    /// When the next checkpoint is due
+    next_checkpoint: parking_lot::Mutex<std::time::Instant>,
+    /// Time spent in the runs before a resumed scan
+    earlier_runs: std::time::Duration,
+    throttle: Throttle,
+}
+
+/// Entries of one directory listing, not yet added to the tree
+struct Listing {
+    /// (name, entry, path to descend into)
+    batch: Vec<(String, FileEntry, Option<PathBuf>)>,
+    /// Apparent size of the files in the batch
+    bytes: u64,
+    /// Members of the archives in the batch, by position in the batch
+    archives: Vec<(usize, Vec<ArchiveMember>)>,
+    excluded: u64,
+    excluded_bytes: u64,
+    read_error: bool,
+    stopped: bool,
+}
+
+/// Tree of a running scan, with the number of unfinished subdirectories of each directory
+struct PartialTree {
+    result: ScanResult,
+    pending: Vec<u32>,
+    /// (device, inode) of the directories already entered through a symlink
+    followed: HashSet<(u64, u64)>,
+    /// (device, inode) of the hard-linked files already added to a summary
+    summarized_links: HashSet<(u64, u64)>,
+}
+
+impl PartialTree {
+    /// Whether `dir` or one of its ancestors is the directory `inode`
+    fn in_ancestors(&self, dir: NodeId, inode: (u64, u64)) -> bool {
+        let mut current = Some(dir);
+        while let Some(id) = current {
+            let entry = self.result.entry(id);
+            if entry.inode == Some(inode) {
+                return true;
+            }
+            current = entry.parent;
+        }
+        false
+    }
+    
+    /// Whether to descend into `entry`, a directory found in `dir`.
+    ///
+    /// Each linked directory is entered once, and never one the link sits in.
+    fn should_descend(&mut self, dir: NodeId, entry: &mut FileEntry) -> bool {
+        if let (FileKind::Symlink, Some(inode)) = (entry.kind, entry.inode) {
+            let cycle = self.in_ancestors(dir, inode);
+            if cycle || !self.followed.insert(inode) {
+                entry.scanning = false;
+                if let Some(target) = &mut entry.link_target {
+                    target.cycle = cycle;
+                }
+                return false;
+            }
+        }
+        true
+    }
+    
+    /// Cut `id` and its subtree out of the tree, along with the errors recorded
+    /// below it, so it can be listed again
+    fn forget(&mut self, id: NodeId, path: &Path, errors: &mut Vec<ScanError>) {
+        let entry = self.result.entry(id);
+        if let (FileKind::Symlink, Some(inode), true) = (entry.kind, entry.inode, entry.is_container()) {
+            self.followed.remove(&inode);
+        }
+        self.result.detach(id);
+        errors.retain(|error| !error.path.starts_with(path));
+    }
+    
+    /// Mark a listed directory without pending subdirectories as done,
+    /// along with every ancestor it was the last pending subdirectory of
+    fn finish_dir(&mut self, mut id: NodeId) {
+        loop {
+            self.result.entry_mut(id).scanning = false;
+            let Some(parent) = self.result.entry(id).parent else {
+                break;
+            };
+            let pending = &mut self.pending[parent.index()];
+            *pending -= 1;
+            if *pending > 0 {
+                break;
+            }
+            id = parent;
+        }
+    }
+}
+
+impl ScanContext<'_> {
+    /// Wait as far as gentle mode and the rate limit ask for, before one metadata operation
+    fn pace(&self) {
+        self.throttle.wait(self.should_stop, self.progress);
+    }
+    
+    fn record_error(&self, error: ScanError) {
+        self.progress.record_error();
+        self.errors.lock().push(error);
+    }
+    
+    /// Publish a copy of the tree built so far, if one is due
+    fn publish_snapshot(&self) {
+        // One worker builds the snapshot, the others keep scanning
+        let Some(mut next_snapshot) = self.next_snapshot.try_lock() else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        if now < *next_snapshot {
+            return;
+        }
+        
+        let mut snapshot = self.tree.lock().result.clone();
+        snapshot.recalculate_totals();
+        snapshot.excluded_count = self.excluded_count.load(Ordering::Relaxed);
+        snapshot.excluded_size = self.excluded_size.load(Ordering::Relaxed);
+        snapshot.errors = self.errors.lock().clone();
+        snapshot.scan_duration = self.earlier_runs + self.start_time.elapsed();
+        *self.snapshot.lock() = Some(snapshot);
+        
+        // Copying a huge tree is slow, so keep snapshots to a small share of the scan time
+        *next_snapshot = std::time::Instant::now() + SNAPSHOT_INTERVAL.max(now.elapsed() * 10);
+    }
+    
+    /// Save the tree built so far and the directories still to list, if a checkpoint is due
+    fn write_checkpoint(&self) {
+        let Some(path) = self.checkpoint else {
+            return;
+        };
+        let Some(mut next_checkpoint) = self.next_checkpoint.try_lock() else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        // Stopped directories are only partly listed, the previous checkpoint is kept instead
+        if now < *next_checkpoint || self.should_stop.load(Ordering::Relaxed) {
+            return;
+        }
+        
+        let (mut result, followed, summarized_links) = {
+            let tree = self.tree.lock();
+            (tree.result.clone(), tree.followed.clone(), tree.summarized_links.clone())
+        };
+        // Listings are added under one lock, so a directory without children is either empty and done, or not listed yet
+        let queue = result
+            .iter()
+            .filter(|(_, entry)| entry.scanning && entry.children.is_empty())
+            .map(|(id, _)| id)
+            .collect();
+        result.recalculate_totals();
+        result.excluded_count = self.excluded_count.load(Ordering::Relaxed);
+        result.excluded_size = self.excluded_size.load(Ordering::Relaxed);
+        result.errors = self.errors.lock().clone();
+        result.scan_duration = self.earlier_runs + self.start_time.elapsed();
+        result.options = self.options.clone();
+        
+        let checkpoint = Checkpoint {
+            info: CheckpointInfo {
+                root_path: result.root_path.clone(),
+                saved: Utc::now(),
+                entries: result.iter().count() as u64,
+                elapsed: result.scan_duration,
+                options: self.options.clone(),
+            },
+            result,
+            queue,
+            followed,
+            summarized_links,
+        };
+        // Without a checkpoint the scan can only not be resumed, it goes on regardless
+        let _ = checkpoint::save(&checkpoint, path);
+        
+        *next_checkpoint = std::time::Instant::now() + CHECKPOINT_INTERVAL.max(now.elapsed() * 10);
+    }
 }
 
 /// File system scanner with progress tracking
 pub struct FileSystemScanner {
     root_path: PathBuf,
+    /// Folders to scan, just `root_path` unless there are several
+    roots: Vec<PathBuf>,
+    options: ScanOptions,
     should_stop: Arc<AtomicBool>,
-    progress: Arc<parking_lot::Mutex<ScanProgress>>,
+    progress: Arc<LiveProgress>,
     result: Arc<parking_lot::Mutex<Option<ScanResult>>>,
+    /// Latest unseen snapshot of the running scan
+    snapshot: Arc<parking_lot::Mutex<Option<ScanResult>>>,
+    /// Checkpoint file, and whether to continue from the checkpoint in it
+    checkpoint: Option<(PathBuf, bool)>,
+    /// Where directories are read from
+    source: Arc<dyn ScanSource>,
+    /// Whether finished scans leave their entry counts for later estimates,
+    /// only for scans of the mounted filesystems
+    remember_counts: bool,
 }
 
 impl FileSystemScanner {
     pub fn new(path: PathBuf) -> Self {
+        Self::with_options(path, ScanOptions::default())
+    }
+    
+    pub fn with_options(path: PathBuf, options: ScanOptions) -> Self {
+        let mut scanner = Self::with_source(path, options, Arc::new(RealFs));
+        scanner.remember_counts = true;
+        scanner
+    }
+    
+    /// Scanner that reads from `source` instead of the mounted filesystems
+    pub fn with_source(path: PathBuf, options: ScanOptions, source: Arc<dyn ScanSource>) -> Self {
         Self {
+            roots: vec![path.clone()],
             root_path: path,
+            options,
             should_stop: Arc::new(AtomicBool::new(false)),
-            progress: Arc::new(parking_lot::Mutex::new(ScanProgress::default())),
+            progress: Arc::new(LiveProgress::new()),
             result: Arc::new(parking_lot::Mutex::new(None)),
+            snapshot: Arc::new(parking_lot::Mutex::new(None)),
+            checkpoint: None,
+            source,
+            remember_counts: false,
         }
     }
     
+    /// Scanner for several folders at once, combined in one result below a
+    /// synthetic top entry. Nested and repeated folders are scanned once, a
+    /// single folder left makes a normal scan.
+    pub fn with_roots(roots: Vec<PathBuf>, options: ScanOptions) -> Self {
+        let roots = Self::distinct_roots(roots);
+        let root_path = match roots.as_slice() {
+            [root] => root.clone(),
+            _ => PathBuf::new(),
+        };
+        let mut scanner = Self::with_options(root_path, options);
+        scanner.roots = roots;
+        scanner
+    }
+    
+    /// Save checkpoints of the scan to `file` while it runs, and delete the
+    /// file once the scan finishes. With `resume`, the scan continues from
+    /// the checkpoint already in `file` when it fits the root and options,
+    /// otherwise it starts over. Scans of several roots are not checkpointed.
+    pub fn use_checkpoint(&mut self, file: PathBuf, resume: bool) {
+        self.checkpoint = Some((file, resume));
+    }
+    
     /// Start scanning in a separate thread
     pub fn start(&mut self) {
-        let root_path = self.root_path.clone();
+        let roots = self.roots.clone();
+        let options = self.options.clone();
         let should_stop = self.should_stop.clone();
         let progress = self.progress.clone();
         let result = self.result.clone();
+        let snapshot = self.snapshot.clone();
+        let checkpoint = self.checkpoint.clone();
+        let source = self.source.clone();
+        let remember_counts = self.remember_counts;
+        
+        progress.start();
         
         std::thread::spawn(move || {
-            if let Ok(scan_result) = Self::scan_directory(&root_path, &should_stop, &progress) {
-                *result.lock() = Some(scan_result);
-                if let Some(mut prog) = progress.try_lock() {
-                    prog.is_complete = true;
+            let checkpoint = checkpoint.as_ref().map(|(file, resume)| (file.as_path(), *resume));
+            match Self::scan_directory(&*source, &roots, &options, &should_stop, &progress, &snapshot, checkpoint) {
+                Ok(scan_result) => {
+                    if remember_counts && !scan_result.partial {
+                        // Each root of a combined scan is remembered on its own, for any scan including it
+                        if roots.len() > 1 {
+                            remember_entry_counts(Self::root_entry_counts(&scan_result));
+                        } else {
+                            remember_entry_counts([(roots[0].clone(), progress.snapshot().files_scanned)]);
+                        }
+                    }
+                    let state = if scan_result.partial {
+                        ScanState::Cancelled
+                    } else {
+                        ScanState::Finished
+                    };
+                    // Publish the result before the state, so a finished scan always has one
+                    *snapshot.lock() = None;
+                    *result.lock() = Some(scan_result);
+                    progress.set_state(state);
+                }
+                Err(e) => {
+                    progress.set_state(ScanState::Failed(format!("{:#}", e)));
                 }
             }
         });
     }
     
+    /// Directory this scanner walks, empty when it walks several
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+    
     /// Stop the scanning process
     pub fn stop(&self) {
         self.should_stop.store(true, Ordering::SeqCst);
     }
     
-    /// Check if scanning is finished
+    /// Check if scanning is finished, cancelled or failed
     pub fn is_finished(&self) -> bool {
-        self.progress.lock().is_complete
+        self.progress.state().is_terminal()
+    }
+    
+    /// Current lifecycle state of the scan
+    pub fn state(&self) -> ScanState {
+        self.progress.state()
+    }
+    
+    /// Whether `stop` was called
+    pub fn is_stopping(&self) -> bool {
+        self.should_stop.load(Ordering::Relaxed)
     }
     
     /// Get the scan result if available
@@ -95,162 +581,1464 @@ impl FileSystemScanner {
         self.result.lock().take()
     }
     
-    /// Get current progress
+    /// Latest snapshot of the running scan, if one was published since the last call.
+    ///
+    /// Directories still being scanned are marked with `FileEntry::scanning`,
+    /// everything else is final.
+    pub fn take_snapshot(&mut self) -> Option<ScanResult> {
+        self.snapshot.lock().take()
+    }
+    
+    /// Get current progress, with the speed of the last few seconds
     pub fn get_progress(&self) -> ScanProgress {
-        self.progress.lock().clone()
+        self.progress.snapshot()
+    }
+    
+    /// Scan `root` on the calling thread, without progress reporting
+    pub fn scan_blocking(root: &Path, options: &ScanOptions) -> Result<ScanResult> {
+        Self::scan_source_blocking(&RealFs, root, options)
+    }
+    
+    /// Scan `root` in `source` on the calling thread, without progress reporting
+    pub fn scan_source_blocking(source: &dyn ScanSource, root: &Path, options: &ScanOptions) -> Result<ScanResult> {
+        let progress = LiveProgress::new();
+        let snapshot = parking_lot::Mutex::new(None);
+        Self::scan_directory(source, &[root.to_path_buf()], options, &AtomicBool::new(false), &progress, &snapshot, None)
+    }
+    
+    /// Scan several folders into one result on the calling thread, see `with_roots`
+    pub fn scan_roots_blocking(roots: Vec<PathBuf>, options: &ScanOptions) -> Result<ScanResult> {
+        let progress = LiveProgress::new();
+        let snapshot = parking_lot::Mutex::new(None);
+        let roots = Self::distinct_roots(roots);
+        Self::scan_directory(&RealFs, &roots, options, &AtomicBool::new(false), &progress, &snapshot, None)
+    }
+    
+    /// Absolute roots without repeats, and without roots inside other roots
+    /// that would be counted twice. Keeps the order they were given in.
+    fn distinct_roots(roots: Vec<PathBuf>) -> Vec<PathBuf> {
+        let roots: Vec<PathBuf> = roots
+            .into_iter()
+            .map(|root| std::path::absolute(&root).unwrap_or(root))
+            .collect();
+        let mut outermost: Vec<&PathBuf> = roots.iter().collect();
+        outermost.sort_by_key(|root| root.components().count());
+        let mut kept: Vec<&PathBuf> = Vec::new();
+        for root in outermost {
+            if !kept.iter().any(|outer| root.starts_with(outer)) {
+                kept.push(root);
+            }
+        }
+        
+        let mut seen = HashSet::new();
+        roots
+            .iter()
+            .filter(|root| kept.contains(root) && seen.insert(*root))
+            .cloned()
+            .collect()
     }
     
-    /// Actual scanning implementation
+    /// Actual scanning implementation. Several `roots` are scanned in one
+    /// pool, each below a synthetic top entry.
     fn scan_directory(
-        root: &Path,
+        source: &dyn ScanSource,
+        roots: &[PathBuf],
+        options: &ScanOptions,
         should_stop: &AtomicBool,
-        progress: &parking_lot::Mutex<ScanProgress>,
+        progress: &LiveProgress,
+        snapshot: &parking_lot::Mutex<Option<ScanResult>>,
+        checkpoint: Option<(&Path, bool)>,
     ) -> Result<ScanResult> {
         let start_time = std::time::Instant::now();
-        let mut entries = Vec::new();
-        let mut total_size = 0;
-        let mut file_count = 0;
-        let mut dir_count = 0;
+        let several = roots.len() > 1;
+        let root = match roots {
+            [] => anyhow::bail!("no folder to scan"),
+            [root] => root.as_path(),
+            _ => Path::new(""),
+        };
+        // Checkpoints are found by root folder
+        let checkpoint = checkpoint.filter(|_| !several);
+        
+        progress.set_estimate(Self::estimate_total(source, roots));
         
-        let walker = WalkDir::new(root)
-            .follow_links(false)
+        let mut pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .thread_name(|i| format!("diskray-scan-{}", i));
+        if options.gentle {
+            pool = pool.start_handler(|_| throttle::lower_thread_priority());
+        }
+        let pool = pool.build()?;
+        
+        // Roots are stat'ed through symlinks, like WalkDir does when descending.
+        // One unreadable root out of several is only an error of the scan.
+        let mut root_entries = Vec::new();
+        let mut root_errors = Vec::new();
+        for path in roots {
+            match source.metadata(path) {
+                Ok(stat) => {
+                    let mut entry = Self::make_entry(source, path, &stat, &|| ());
+                    entry.scanning = entry.is_directory();
+                    root_entries.push((path, entry));
+                }
+                Err(e) => root_errors.push(ScanError::from_io(path.clone(), ScanOperation::Stat, &e)),
+            }
+        }
+        if root_entries.is_empty() {
+            return Err(root_errors.swap_remove(0).into());
+        }
+        let root_devices: Vec<Option<u64>> = root_entries.iter().map(|(_, entry)| entry.device()).collect();
+        
+        // A checkpoint of another root or of a different walk would not fit this scan
+        let resumed = match checkpoint {
+            Some((file, true)) => checkpoint::load(file)
+                .ok()
+                .filter(|c| c.result.root_path == root && c.info.options.same_walk(options)),
+            _ => None,
+        };
+        let earlier_runs = resumed.as_ref().map_or(std::time::Duration::ZERO, |c| c.info.elapsed);
+        
+        let mut tree = ScanResult::new(root.to_path_buf());
+        if several {
+            tree.roots = roots.to_vec();
+        }
+        let context = ScanContext {
+            source,
+            options,
+            rules: PathRules::with_roots(roots, &options.rules)?,
+            throttle: Throttle::new(options.gentle, options.max_ops_per_sec, root_devices[0]),
+            root_devices,
+            excluded_count: AtomicU64::new(0),
+            excluded_size: AtomicU64::new(0),
+            should_stop,
+            progress,
+            tree: parking_lot::Mutex::new(PartialTree {
+                result: tree,
+                pending: Vec::new(),
+                followed: HashSet::new(),
+                summarized_links: HashSet::new(),
+            }),
+            errors: parking_lot::Mutex::new(root_errors),
+            snapshot,
+            start_time,
+            next_snapshot: parking_lot::Mutex::new(start_time + SNAPSHOT_INTERVAL),
+            checkpoint: checkpoint.map(|(file, _)| file),
+            next_checkpoint: parking_lot::Mutex::new(start_time + CHECKPOINT_INTERVAL),
+            earlier_runs,
+        };
+        progress.set_throttle(context.throttle.base_state());
+        
+        // (directory, path, reached through a symlink, depth) of everything left to list
+        let subdirs = match resumed {
+            Some(resumed) => pool.install(|| Self::prepare_resume(&context, resumed)),
+            None => {
+                for (path, entry) in &root_entries {
+                    progress.add(path, 1, entry.size);
+                }
+                let mut tree = context.tree.lock();
+                let top = several.then(|| {
+                    let stat = EntryStat {
+                        kind: FileKind::Directory,
+                        len: 0,
+                        allocated: 0,
+                        modified: None,
+                        accessed: None,
+                        changed: None,
+                        created: None,
+                        uid: None,
+                        gid: None,
+                        mode: None,
+                        nlink: 1,
+                        inode: None,
+                    };
+                    tree.pending.push(0);
+                    tree.result.push_root(Self::make_entry(source, root, &stat, &|| ()))
+                });
+                
+                // Every root is listed at depth 0, the top entry does not count as a level
+                let mut subdirs = Vec::new();
+                for (path, entry) in root_entries {
+                    let is_dir = entry.is_directory();
+                    tree.pending.push(0);
+                    let id = match top {
+                        Some(top) => tree.result.push_child(top, &path.to_string_lossy(), entry),
+                        None => tree.result.push_root(entry),
+                    };
+                    if is_dir {
+                        subdirs.push((id, path.to_path_buf(), false, 0));
+                    }
+                }
+                if let Some(top) = top {
+                    tree.pending[top.index()] = subdirs.len() as u32;
+                    tree.result.entry_mut(top).scanning = !subdirs.is_empty();
+                }
+                subdirs
+            }
+        };
+        
+        if !subdirs.is_empty() {
+            let context = &context;
+            pool.scope(|scope| {
+                for (id, path, via_link, depth) in subdirs {
+                    scope.spawn(move |scope| Self::scan_dir_parallel(scope, context, id, path, via_link, depth));
+                }
+            });
+        }
+        
+        let mut result = context.tree.into_inner().result;
+        result.recalculate_totals();
+        Self::resolve_owner_names(&mut result);
+        // The scan is done adding names, the lookup index is rebuilt if one is added later
+        result.names.release_lookup();
+        result.excluded_count = context.excluded_count.into_inner();
+        result.excluded_size = context.excluded_size.into_inner();
+        result.errors = context.errors.into_inner();
+        result.partial = should_stop.load(Ordering::Relaxed);
+        result.scan_duration = earlier_runs + start_time.elapsed();
+        result.scan_time = Utc::now();
+        result.options = options.clone();
+        result.hostname = local_hostname().to_string();
+        
+        if !result.partial {
+            if let Some((file, _)) = checkpoint {
+                checkpoint::remove(file);
+            }
+        }
+        
+        Ok(result)
+    }
+    
+    /// Entries below each root of a combined scan, counted like the progress
+    /// counts them: excluded entries included, archive members left out.
+    fn root_entry_counts(result: &ScanResult) -> Vec<(PathBuf, u64)> {
+        result
+            .children(NodeId::ROOT)
+            .iter()
+            .map(|&top| {
+                let mut entries = 0;
+                let mut stack = vec![top];
+                while let Some(id) = stack.pop() {
+                    let entry = result.entry(id);
+                    if entry.in_archive {
+                        continue;
+                    }
+                    entries += 1 + entry.summary.as_ref().map_or(0, |summary| summary.file_count + summary.dir_count);
+                    stack.extend_from_slice(result.children(id));
+                }
+                (result.path_of(top), entries)
+            })
+            .collect()
+    }
+    
+    /// Take over a checkpointed scan and return the directories left to list.
+    ///
+    /// Every directory listed before the checkpoint is compared with its stored
+    /// mtime, and the ones that changed are listed again. A file that changed
+    /// in place leaves its directory's mtime alone, so it keeps its
+    /// checkpointed size until the next full scan.
+    fn prepare_resume(context: &ScanContext<'_>, resumed: Checkpoint) -> Vec<(NodeId, PathBuf, bool, usize)> {
+        let Checkpoint {
+            mut result,
+            queue,
+            followed,
+            summarized_links,
+            ..
+        } = resumed;
+        context.excluded_count.store(result.excluded_count, Ordering::Relaxed);
+        context.excluded_size.store(result.excluded_size, Ordering::Relaxed);
+        let mut errors = std::mem::take(&mut result.errors);
+        context.progress.add_resumed(result.iter().count() as u64, result.total_size);
+        
+        let queued: HashSet<NodeId> = queue.iter().copied().collect();
+        let mut listed = Vec::new();
+        result.for_each_path(|id, path| {
+            let entry = result.entry(id);
+            if entry.kind == FileKind::Directory && entry.excluded.is_none() && !entry.in_archive && !queued.contains(&id) {
+                listed.push((id, path.to_path_buf(), entry.modified));
+            }
+        });
+        
+        // Only whole seconds are stored, a change within the second of the listing goes unnoticed
+        let changed: Vec<(NodeId, PathBuf, Option<EntryStat>)> = {
+            use rayon::prelude::*;
+            listed
+                .into_par_iter()
+                .filter_map(|(id, path, modified)| {
+                    context.pace();
+                    // The root is stat'ed through symlinks, everything else is not
+                    let stat = if id == NodeId::ROOT {
+                        context.source.metadata(&path)
+                    } else {
+                        context.source.symlink_metadata(&path)
+                    };
+                    match stat {
+                        Ok(stat) => (stat.modified.map(|t| t.timestamp()) != modified.map(|t| t.timestamp()))
+                            .then_some((id, path, Some(stat))),
+                        Err(_) => Some((id, path, None)),
+                    }
+                })
+                .collect()
+        };
+        
+        let mut tree = PartialTree {
+            result,
+            pending: Vec::new(),
+            followed,
+            summarized_links,
+        };
+        let mut subdirs = queue;
+        // Parents come before their children, so a directory that went away with its parent is already detached
+        for (id, path, stat) in changed {
+            if !tree.result.is_detached(id) {
+                subdirs.extend(Self::relist_dir(context, &mut tree, &mut errors, id, &path, stat.as_ref()));
+            }
+        }
+        // Errors of the relisted directories were recorded in the context
+        errors.append(&mut context.errors.lock());
+        subdirs.retain(|&id| !tree.result.is_detached(id));
+        
+        // A directory is scanning while it or anything below it is left to list
+        for &id in &subdirs {
+            let entry = tree.result.entry_mut(id);
+            entry.scanning = true;
+            entry.summary = None;
+            entry.read_error = false;
+            entry.unfinished = false;
+        }
+        tree.pending = vec![0; tree.result.entries.len()];
+        for i in (1..tree.result.entries.len()).rev() {
+            if let (true, Some(parent)) = (tree.result.entries[i].scanning, tree.result.entries[i].parent) {
+                tree.pending[parent.index()] += 1;
+                tree.result.entry_mut(parent).scanning = true;
+            }
+        }
+        
+        let subdirs = subdirs
             .into_iter()
-            .filter_map(|e| e.ok());
+            .map(|id| {
+                let mut depth = 0;
+                let mut parent = tree.result.entry(id).parent;
+                while let Some(parent_id) = parent {
+                    depth += 1;
+                    parent = tree.result.entry(parent_id).parent;
+                }
+                (id, tree.result.path_of(id), tree.result.entry(id).via_link, depth)
+            })
+            .collect();
+        *context.errors.lock() = errors;
+        *context.tree.lock() = tree;
+        subdirs
+    }
+    
+    /// List a directory of a resumed scan again, keeping the subtrees of the
+    /// subdirectories that are still there. Returns the directories to list.
+    fn relist_dir(
+        context: &ScanContext<'_>,
+        tree: &mut PartialTree,
+        errors: &mut Vec<ScanError>,
+        dir_id: NodeId,
+        dir: &Path,
+        stat: Option<&EntryStat>,
+    ) -> Vec<NodeId> {
+        let entry = tree.result.entry_mut(dir_id);
+        if let Some(stat) = stat {
+            let fresh = Self::make_entry(context.source, dir, stat, &|| context.pace());
+            entry.modified = fresh.modified;
+            entry.accessed = fresh.accessed;
+            entry.changed = fresh.changed;
+            entry.own_allocated = fresh.own_allocated;
+        }
+        entry.read_error = false;
+        errors.retain(|error| error.path != dir && error.path.parent() != Some(dir));
+        if entry.summary.is_some() {
+            // A summary cannot be taken apart, the whole directory is summarized again
+            errors.retain(|error| !error.path.starts_with(dir));
+            return vec![dir_id];
+        }
         
-        let total_entries = walker.count();
+        let via_link = entry.via_link;
+        let old_children = std::mem::take(&mut entry.children);
+        let listing = match Self::list_dir(context, dir, via_link) {
+            Ok(listing) => listing,
+            Err(e) => {
+                for child in old_children {
+                    let path = tree.result.path_of(child);
+                    tree.forget(child, &path, errors);
+                }
+                context.record_error(ScanError::from_io(dir.to_path_buf(), ScanOperation::ReadDir, &e));
+                tree.result.entry_mut(dir_id).read_error = true;
+                return Vec::new();
+            }
+        };
         
-        {
-            let mut prog = progress.lock();
-            prog.total_files = Some(total_entries as u64);
+        let mut old: HashMap<String, NodeId> = old_children
+            .into_iter()
+            .map(|child| (tree.result.name(child).to_string(), child))
+            .collect();
+        let mut subdirs = Vec::new();
+        let mut added = 0;
+        let mut archives = listing.archives.into_iter().peekable();
+        for (i, (name, mut file_entry, subdir)) in listing.batch.into_iter().enumerate() {
+            if subdir.is_some() {
+                // A subdirectory that is still there keeps its subtree, it is checked on its own
+                let kept = old.get(&name).copied().filter(|&old_id| {
+                    let old_entry = tree.result.entry(old_id);
+                    old_entry.kind == file_entry.kind && old_entry.excluded.is_none() && old_entry.is_container()
+                });
+                if let Some(old_id) = kept {
+                    old.remove(&name);
+                    tree.result.entry_mut(dir_id).children.push(old_id);
+                    continue;
+                }
+            }
+            
+            if let Some(old_id) = old.remove(&name) {
+                tree.forget(old_id, &dir.join(&name), errors);
+            }
+            let descend = subdir.is_some() && tree.should_descend(dir_id, &mut file_entry);
+            let id = tree.result.push_child(dir_id, &name, file_entry);
+            if descend {
+                subdirs.push(id);
+            }
+            if let Some((_, members)) = archives.next_if(|(at, _)| *at == i) {
+                archive::attach(&mut tree.result, id, members);
+            }
+            added += 1;
+        }
+        for (name, old_id) in old {
+            tree.forget(old_id, &dir.join(&name), errors);
         }
         
-        let mut path_to_index: HashMap<PathBuf, usize> = HashMap::new();
+        // Exclusions are not tracked per directory, like in `ScanResult::graft`
+        // the ones counted before the checkpoint stay and the new ones are not added
+        let dir_entry = tree.result.entry_mut(dir_id);
+        dir_entry.read_error = listing.read_error;
+        dir_entry.unfinished = listing.stopped;
+        context.progress.add(dir, added, 0);
+        subdirs
+    }
+    
+    /// Look up the user and group name of every distinct uid and gid, once each
+    fn resolve_owner_names(result: &mut ScanResult) {
+        let mut uids = HashSet::new();
+        let mut gids = HashSet::new();
+        for entry in &result.entries {
+            uids.extend(entry.uid);
+            gids.extend(entry.gid);
+        }
         
-        for (i, entry) in WalkDir::new(root)
-            .follow_links(false)
+        result.user_names = uids
+            .into_iter()
+            .filter_map(|uid| user_name(uid).map(|name| (uid, name)))
+            .collect();
+        result.group_names = gids
             .into_iter()
-            .enumerate()
+            .filter_map(|gid| group_name(gid).map(|name| (gid, name)))
+            .collect();
+    }
+    
+    /// Estimate how many entries a scan of `roots` will visit, if every root can be estimated
+    fn estimate_total(source: &dyn ScanSource, roots: &[PathBuf]) -> Option<TotalEstimate> {
+        let mut total = TotalEstimate {
+            entries: 0,
+            source: EstimateSource::PreviousScan,
+        };
+        for root in roots {
+            let estimate = Self::estimate_root(source, root)?;
+            total.entries += estimate.entries;
+            if estimate.source == EstimateSource::FilesystemInodes {
+                total.source = EstimateSource::FilesystemInodes;
+            }
+        }
+        Some(total)
+    }
+    
+    /// Estimate how many entries a scan of `root` will visit
+    fn estimate_root(source: &dyn ScanSource, root: &Path) -> Option<TotalEstimate> {
+        let previous = PREVIOUS_ENTRY_COUNTS
+            .lock()
+            .iter()
+            .find(|(path, _)| path == root)
+            .map(|&(_, entries)| entries);
+        if let Some(entries) = previous {
+            return Some(TotalEstimate {
+                entries,
+                source: EstimateSource::PreviousScan,
+            });
+        }
+        
+        source.used_inodes(root).map(|entries| TotalEstimate {
+            entries,
+            source: EstimateSource::FilesystemInodes,
+        })
+    }
+    
+    /// Read one directory and fan its subdirectories out over the pool.
+    ///
+    /// Every directory is a separate rayon task, so idle workers steal
+    /// pending directories from busy ones.
+    fn scan_dir_parallel<'s>(
+        scope: &rayon::Scope<'s>,
+        context: &'s ScanContext<'s>,
+        dir_id: NodeId,
+        dir: PathBuf,
+        via_link: bool,
+        depth: usize,
+    ) {
+        if context.options.max_depth.is_some_and(|max| depth >= max) {
+            {
+                let mut tree = context.tree.lock();
+                tree.result.entry_mut(dir_id).summary = Some(Box::default());
+                // The directory's own listing is the first pending part of its summary
+                tree.pending[dir_id.index()] = 1;
+            }
+            Self::summarize_dir_parallel(scope, context, dir_id, dir);
+            return;
+        }
+        
+        if context.should_stop.load(Ordering::Relaxed) {
+            let mut tree = context.tree.lock();
+            tree.result.entry_mut(dir_id).unfinished = true;
+            tree.finish_dir(dir_id);
+            return;
+        }
+        
+        let listing = match Self::list_dir(context, &dir, via_link) {
+            Ok(listing) => listing,
+            Err(e) => {
+                context.record_error(ScanError::from_io(dir, ScanOperation::ReadDir, &e));
+                let mut tree = context.tree.lock();
+                tree.result.entry_mut(dir_id).read_error = true;
+                tree.finish_dir(dir_id);
+                return;
+            }
+        };
+        
+        // Counted once per directory instead of once per entry
+        context.progress.add(&dir, listing.batch.len() as u64, listing.bytes);
+        
+        if listing.excluded > 0 {
+            context.excluded_count.fetch_add(listing.excluded, Ordering::Relaxed);
+            context.excluded_size.fetch_add(listing.excluded_bytes, Ordering::Relaxed);
+        }
+        
+        let mut subdirs = Vec::new();
         {
-            if should_stop.load(Ordering::Relaxed) {
+            let mut tree = context.tree.lock();
+            let dir_entry = tree.result.entry_mut(dir_id);
+            dir_entry.read_error |= listing.read_error;
+            dir_entry.unfinished |= listing.stopped;
+            let mut archives = listing.archives.into_iter().peekable();
+            for (i, (name, mut file_entry, mut subdir)) in listing.batch.into_iter().enumerate() {
+                if subdir.is_some() && !tree.should_descend(dir_id, &mut file_entry) {
+                    subdir = None;
+                }
+                let via_link = file_entry.via_link;
+                let id = tree.result.push_child(dir_id, &name, file_entry);
+                if let Some(path) = subdir {
+                    subdirs.push((id, path, via_link));
+                }
+                if let Some((_, members)) = archives.next_if(|(at, _)| *at == i) {
+                    archive::attach(&mut tree.result, id, members);
+                }
+            }
+            let len = tree.result.entries.len();
+            tree.pending.resize(len, 0);
+            tree.pending[dir_id.index()] = subdirs.len() as u32;
+            if subdirs.is_empty() {
+                tree.finish_dir(dir_id);
+            }
+        }
+        
+        for (id, path, via_link) in subdirs {
+            scope.spawn(move |scope| Self::scan_dir_parallel(scope, context, id, path, via_link, depth + 1));
+        }
+        
+        context.publish_snapshot();
+        context.write_checkpoint();
+    }
+    
+    /// Read the entries of `dir`, applying the rules and options of the scan.
+    ///
+    /// Errors on single entries are recorded and flagged in the listing, only
+    /// a directory that cannot be opened at all is an error.
+    fn list_dir(context: &ScanContext<'_>, dir: &Path, via_link: bool) -> std::io::Result<Listing> {
+        context.pace();
+        let mut read_dir = context.source.read_dir(dir)?;
+        
+        let mut listing = Listing {
+            batch: Vec::new(),
+            bytes: 0,
+            archives: Vec::new(),
+            excluded: 0,
+            excluded_bytes: 0,
+            read_error: false,
+            stopped: false,
+        };
+        
+        loop {
+            if context.should_stop.load(Ordering::Relaxed) {
+                listing.stopped = true;
                 break;
             }
             
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => continue,
+            // Items are stat'ed as they are read
+            context.pace();
+            let Some(entry) = read_dir.next() else {
+                break;
+            };
+            let item = match entry {
+                Ok(item) => item,
+                Err(e) => {
+                    context.record_error(ScanError::from_io(dir.to_path_buf(), ScanOperation::ReadEntry, &e));
+                    listing.read_error = true;
+                    continue;
+                }
             };
             
-            let path = entry.path().to_path_buf();
+            let path = dir.join(&item.name);
+            let stat = match item.stat {
+                Ok(stat) => stat,
+                Err(e) => {
+                    context.record_error(ScanError::from_io(path, ScanOperation::Stat, &e));
+                    listing.read_error = true;
+                    continue;
+                }
+            };
             
-            {
-                let mut prog = progress.lock();
-                prog.current_path = path.clone();
-                prog.files_scanned = i as u64 + 1;
+            let name = item.name.to_string_lossy().to_string();
+            let mut file_entry = Self::make_entry(context.source, &path, &stat, &|| context.pace());
+            let mut descend = file_entry.is_directory();
+            if via_link {
+                file_entry.via_link = true;
+                if descend {
+                    // The blocks of the real directory are counted where it lives
+                    file_entry.allocated_size = 0;
+                    file_entry.own_allocated = 0;
+                }
+            }
+            if context.options.follow_symlinks && file_entry.kind == FileKind::Symlink && !file_entry.is_broken_link() {
+                context.pace();
+                if let Ok(target) = context.source.metadata(&path) {
+                    Self::follow_link(&mut file_entry, &target);
+                    descend = target.is_dir();
+                }
             }
             
-            let metadata = match entry.metadata() {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
+            if !context.rules.is_empty() {
+                if context.rules.is_excluded(&path) {
+                    listing.excluded += 1;
+                    listing.excluded_bytes += file_entry.size;
+                    file_entry.excluded = Some(ExcludeReason::Pattern);
+                    listing.batch.push((name, file_entry, None));
+                    continue;
+                }
+                // Followed directory symlinks are walked like directories, include rules match what is inside
+                if !descend && !context.rules.is_included(&path) {
+                    listing.excluded += 1;
+                    listing.excluded_bytes += file_entry.size;
+                    continue;
+                }
+            }
             
-            let is_dir = metadata.is_dir();
-            let size = if is_dir { 0 } else { metadata.len() };
+            let mut subdir = None;
+            if descend {
+                if context.options.one_file_system && !context.root_devices.contains(&file_entry.device()) {
+                    // Mount point of another filesystem, its blocks belong there too
+                    file_entry.excluded = Some(ExcludeReason::OtherFilesystem);
+                    file_entry.allocated_size = 0;
+                    file_entry.own_allocated = 0;
+                } else {
+                    file_entry.scanning = true;
+                    subdir = Some(path);
+                }
+            } else {
+                listing.bytes += file_entry.size;
+                if context.options.expand_archives && file_entry.kind == FileKind::File && !file_entry.via_link {
+                    if let Some(kind) = ArchiveKind::from_name(&name) {
+                        context.pace();
+                        match archive::list_members(context.source, &path, kind) {
+                            Ok(members) => listing.archives.push((listing.batch.len(), members)),
+                            // Sources without file contents just keep archives closed
+                            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {}
+                            Err(e) => context.record_error(ScanError::from_io(path, ScanOperation::ReadArchive, &e)),
+                        }
+                    }
+                }
+            }
             
-            let extension = path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .map(|s| s.to_lowercase());
+            listing.batch.push((name, file_entry, subdir));
+        }
+        
+        Ok(listing)
+    }
+    
+    /// Fold the contents of `dir` into the summary of `owner`, a directory at the depth limit.
+    ///
+    /// Works like `scan_dir_parallel`, but only counts what it finds instead
+    /// of adding entries to the tree.
+    fn summarize_dir_parallel<'s>(
+        scope: &rayon::Scope<'s>,
+        context: &'s ScanContext<'s>,
+        owner: NodeId,
+        dir: PathBuf,
+    ) {
+        let mut summary = SubtreeSummary::default();
+        // (inode, size, allocated size) of hard-linked files, added once per inode
+        let mut links = Vec::new();
+        let mut subdirs = Vec::new();
+        let mut entry_count = 0;
+        let mut batch_excluded = 0;
+        let mut batch_excluded_bytes = 0;
+        let mut read_error = false;
+        let mut stopped = context.should_stop.load(Ordering::Relaxed);
+        
+        let mut read_dir = if stopped {
+            None
+        } else {
+            context.pace();
+            match context.source.read_dir(&dir) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    context.record_error(ScanError::from_io(dir.clone(), ScanOperation::ReadDir, &e));
+                    read_error = true;
+                    None
+                }
+            }
+        };
+        
+        while let Some(read_dir) = &mut read_dir {
+            if context.should_stop.load(Ordering::Relaxed) {
+                stopped = true;
+                break;
+            }
             
-            let modified = match metadata.modified() {
-                Ok(time) => DateTime::<Utc>::from(time),
-                Err(_) => Utc::now(),
+            // Items are stat'ed as they are read
+            context.pace();
+            let Some(entry) = read_dir.next() else {
+                break;
+            };
+            let item = match entry {
+                Ok(item) => item,
+                Err(e) => {
+                    context.record_error(ScanError::from_io(dir.clone(), ScanOperation::ReadEntry, &e));
+                    read_error = true;
+                    continue;
+                }
             };
             
-            let file_entry = FileEntry {
-                path: path.clone(),
-                name: path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("")
-                    .to_string(),
-                size,
-                is_directory: is_dir,
-                modified,
-                extension,
-                parent: path.parent().map(|p| p.to_path_buf()),
-                children: Vec::new(),
+            let path = dir.join(&item.name);
+            let stat = match item.stat {
+                Ok(stat) => stat,
+                Err(e) => {
+                    context.record_error(ScanError::from_io(path, ScanOperation::Stat, &e));
+                    read_error = true;
+                    continue;
+                }
             };
             
-            let idx = entries.len();
-            path_to_index.insert(path.clone(), idx);
+            let is_dir = stat.is_dir();
+            if !context.rules.is_empty()
+                && (context.rules.is_excluded(&path) || !is_dir && !context.rules.is_included(&path))
+            {
+                batch_excluded += 1;
+                batch_excluded_bytes += if is_dir { 0 } else { stat.len };
+                continue;
+            }
             
             if is_dir {
-                dir_count += 1;
+                if context.options.one_file_system && !context.root_devices.contains(&stat.inode.map(|(dev, _)| dev)) {
+                    continue;
+                }
+                summary.dir_count += 1;
+                summary.allocated_size += stat.allocated;
+                subdirs.push(path);
             } else {
-                file_count += 1;
-                total_size += size;
-                
-                if let Some(mut prog) = progress.try_lock() {
-                    prog.bytes_scanned += size;
+                summary.file_count += 1;
+                let extension = path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                *summary.extensions.entry(extension).or_default() += 1;
+                match stat.inode {
+                    Some(inode) if stat.nlink > 1 => links.push((inode, stat.len, stat.allocated)),
+                    _ => {
+                        summary.size += stat.len;
+                        summary.allocated_size += stat.allocated;
+                    }
                 }
             }
             
-            entries.push(file_entry);
+            entry_count += 1;
+            summary.newest_modified = summary.newest_modified.max(stat.modified);
         }
         
-        // Build parent-child relationships
-        for i in 0..entries.len() {
-            if let Some(parent) = &entries[i].parent {
-                if let Some(&parent_idx) = path_to_index.get(parent) {
-                    // Use cloned path to avoid double borrowing
-                    let child_path = entries[i].path.clone();
-                    entries[parent_idx].children.push(child_path);
+        context.progress.add(&dir, entry_count, summary.size);
+        
+        if batch_excluded > 0 {
+            context.excluded_count.fetch_add(batch_excluded, Ordering::Relaxed);
+            context.excluded_size.fetch_add(batch_excluded_bytes, Ordering::Relaxed);
+        }
+        
+        {
+            let mut tree = context.tree.lock();
+            for (inode, size, allocated) in links {
+                if tree.summarized_links.insert(inode) {
+                    summary.size += size;
+                    summary.allocated_size += allocated;
                 }
             }
+            let owner_entry = tree.result.entry_mut(owner);
+            owner_entry.read_error |= read_error;
+            owner_entry.unfinished |= stopped;
+            owner_entry.summary.get_or_insert_with(Box::default).merge(summary);
+            
+            let pending = &mut tree.pending[owner.index()];
+            *pending = *pending + subdirs.len() as u32 - 1;
+            if *pending == 0 {
+                tree.finish_dir(owner);
+            }
         }
         
-        // Calculate directory sizes
-        Self::calculate_directory_sizes(&mut entries, &path_to_index);
+        for path in subdirs {
+            scope.spawn(move |scope| Self::summarize_dir_parallel(scope, context, owner, path));
+        }
+        
+        context.publish_snapshot();
+        context.write_checkpoint();
+    }
+    
+    /// Measure a symlink by what it points to, `target` being the followed metadata
+    fn follow_link(entry: &mut FileEntry, target: &EntryStat) {
+        if target.is_dir() {
+            // Directory blocks are counted at the real directory, if it is part of the scan
+            entry.size = 0;
+            entry.allocated_size = 0;
+        } else {
+            entry.size = target.len;
+            entry.allocated_size = target.allocated;
+        }
+        entry.own_allocated = entry.allocated_size;
+        entry.nlink = target.nlink;
+        entry.inode = target.inode;
+        entry.shared = false;
+        entry.via_link = true;
+    }
+    
+    /// Build a FileEntry from the metadata of `path` in `source`, the name is
+    /// filled in when it is added to a tree. Symlinks are read and followed,
+    /// each time after calling `pace`.
+    pub(crate) fn make_entry(source: &dyn ScanSource, path: &Path, stat: &EntryStat, pace: &dyn Fn()) -> FileEntry {
+        let kind = stat.kind;
+        let is_dir = kind == FileKind::Directory;
+        // A symlink's own length is the length of its target path, like `du` reports it
+        let size = if is_dir { 0 } else { stat.len };
         
-        let scan_duration = start_time.elapsed();
+        let link_target = if kind == FileKind::Symlink {
+            pace();
+            source.read_link(path).ok().map(|target| {
+                // Following resolves the whole chain, so links to links and loops are caught too
+                pace();
+                let broken = matches!(
+                    source.metadata(path),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound || is_loop_error(&e)
+                );
+                Box::new(LinkTarget { path: target, broken, cycle: false })
+            })
+        } else {
+            None
+        };
         
-        Ok(ScanResult {
-            root_path: root.to_path_buf(),
-            total_size,
-            file_count,
-            dir_count,
-            entries,
-            scan_duration,
-            scan_time: Utc::now(),
-        })
+        // Directories keep the blocks of their own listing, like `du` counts them
+        let allocated_size = stat.allocated;
+        let nlink = stat.nlink;
+        
+        FileEntry {
+            name: NameId::default(),
+            extension: None,
+            parent: None,
+            children: Vec::new(),
+            size,
+            allocated_size,
+            own_allocated: allocated_size,
+            kind,
+            link_target,
+            modified: stat.modified,
+            accessed: stat.accessed,
+            changed: stat.changed,
+            created: stat.created,
+            uid: stat.uid,
+            gid: stat.gid,
+            mode: stat.mode,
+            nlink,
+            shared: !is_dir && nlink > 1,
+            via_link: false,
+            inode: stat.inode,
+            excluded: None,
+            read_error: false,
+            unfinished: false,
+            incomplete: false,
+            scanning: false,
+            summary: None,
+            in_archive: false,
+        }
+    }
+}
+
+/// Name of this machine, empty when it cannot be determined
+pub fn local_hostname() -> &'static str {
+    &LOCAL_HOSTNAME
+}
+
+/// Login name of a uid from the user database
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let rc = unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || found.is_null() {
+            return None;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
+}
+
+#[cfg(not(unix))]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// Name of a gid from the group database
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let rc = unsafe { libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut found) };
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || found.is_null() {
+            return None;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(group.gr_name) };
+        return Some(name.to_string_lossy().into_owned());
     }
+}
+
+#[cfg(not(unix))]
+fn group_name(_gid: u32) -> Option<String> {
+    None
+}
+
+/// Whether an I/O error is ELOOP, "too many levels of symbolic links"
+#[cfg(unix)]
+fn is_loop_error(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(libc::ELOOP)
+}
+
+#[cfg(not(unix))]
+fn is_loop_error(_error: &std::io::Error) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{PatternKind, RuleAction};
+    use crate::source::testing::{listing, scan};
+    use crate::source::{DirItem, MemoryTree};
+    use std::io;
     
-    /// Calculate directory sizes by summing child sizes
-    fn calculate_directory_sizes(
-        entries: &mut [FileEntry],
-        path_to_index: &HashMap<PathBuf, usize>,
-    ) {
-        let mut dir_indices: Vec<usize> = entries
-            .iter()
-            .enumerate()
-            .filter(|(_, e)| e.is_directory)
-            .map(|(idx, _)| idx)
-            .collect();
+    /// Source that stops the scan as soon as a directory other than the root
+    /// is listed, leaving the checkpoint written after the root's listing
+    struct StopAfterRoot {
+        tree: MemoryTree,
+        stop: Arc<AtomicBool>,
+    }
+    
+    impl ScanSource for StopAfterRoot {
+        fn metadata(&self, path: &Path) -> io::Result<EntryStat> {
+            self.tree.metadata(path)
+        }
         
-        dir_indices.sort_by(|&a, &b| {
-            let depth_a = entries[a].path.components().count();
-            let depth_b = entries[b].path.components().count();
-            depth_b.cmp(&depth_a)
-        });
+        fn symlink_metadata(&self, path: &Path) -> io::Result<EntryStat> {
+            self.tree.symlink_metadata(path)
+        }
+        
+        fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<DirItem>> + 'a>> {
+            if path != Path::new("/data") {
+                self.stop.store(true, Ordering::SeqCst);
+            }
+            self.tree.read_dir(path)
+        }
+        
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            self.tree.read_link(path)
+        }
+    }
+    
+    /// Source that slows listings down and keeps every snapshot published
+    /// before one, as the UI would take them
+    struct CollectSnapshots<'a> {
+        tree: &'a MemoryTree,
+        snapshot: &'a parking_lot::Mutex<Option<ScanResult>>,
+        seen: parking_lot::Mutex<Vec<ScanResult>>,
+    }
+    
+    impl ScanSource for CollectSnapshots<'_> {
+        fn metadata(&self, path: &Path) -> io::Result<EntryStat> {
+            self.tree.metadata(path)
+        }
+        
+        fn symlink_metadata(&self, path: &Path) -> io::Result<EntryStat> {
+            self.tree.symlink_metadata(path)
+        }
+        
+        fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<DirItem>> + 'a>> {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            if let Some(snapshot) = self.snapshot.lock().take() {
+                self.seen.lock().push(snapshot);
+            }
+            self.tree.read_dir(path)
+        }
         
-        for idx in dir_indices {
-            let mut dir_size = 0;
-            for child_path in &entries[idx].children {
-                if let Some(&child_idx) = path_to_index.get(child_path) {
-                    dir_size += entries[child_idx].size;
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            self.tree.read_link(path)
+        }
+    }
+    
+    fn options() -> ScanOptions {
+        // One thread lists the root, and checkpoints it, before any subdirectory
+        ScanOptions { threads: 1, ..Default::default() }
+    }
+    
+    fn sample_tree() -> MemoryTree {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        tree.file(docs, "a.txt", 100);
+        let deep = tree.dir(docs, "deep");
+        tree.file(deep, "b.txt", 2000);
+        let media = tree.dir(MemoryTree::ROOT, "media");
+        tree.file(media, "c.mkv", 30000);
+        tree.file(MemoryTree::ROOT, "top.txt", 4);
+        tree
+    }
+    
+    /// Scan `tree` with a checkpoint in `file`, stopped right after the root is listed
+    fn interrupted_scan(tree: &MemoryTree, file: &Path) -> ScanResult {
+        let stop = Arc::new(AtomicBool::new(false));
+        let source = StopAfterRoot { tree: tree.clone(), stop: stop.clone() };
+        let snapshot = parking_lot::Mutex::new(None);
+        FileSystemScanner::scan_directory(
+            &source,
+            &[PathBuf::from("/data")],
+            &options(),
+            &stop,
+            &LiveProgress::new(),
+            &snapshot,
+            Some((file, false)),
+        )
+        .unwrap()
+    }
+    
+    fn resumed_scan(tree: &MemoryTree, file: &Path) -> ScanResult {
+        let snapshot = parking_lot::Mutex::new(None);
+        FileSystemScanner::scan_directory(
+            tree,
+            &[PathBuf::from("/data")],
+            &options(),
+            &AtomicBool::new(false),
+            &LiveProgress::new(),
+            &snapshot,
+            Some((file, true)),
+        )
+        .unwrap()
+    }
+    
+    #[test]
+    fn parallel_scans_match_sequential_ones() {
+        let mut tree = MemoryTree::new("/data");
+        let mut first = None;
+        for i in 0..8 {
+            let top = tree.dir(MemoryTree::ROOT, &format!("top{}", i));
+            for j in 0..5 {
+                let dir = tree.dir(top, &format!("dir{}", j));
+                for k in 0..10 {
+                    let file = tree.file(dir, &format!("file{}.bin", k), (i * 1000 + j * 100 + k) as u64);
+                    first.get_or_insert(file);
                 }
+                tree.file(dir, "scratch.tmp", 77);
             }
-            entries[idx].size = dir_size;
+            tree.hard_link(top, "shared.bin", first.unwrap());
+        }
+        tree.symlink(MemoryTree::ROOT, "alias", "top3/dir2");
+        let options = ScanOptions {
+            threads: 1,
+            follow_symlinks: true,
+            rules: vec![PathRule::new(RuleAction::Exclude, PatternKind::Glob, "*.tmp")],
+            ..Default::default()
+        };
+        
+        let sequential = scan(&tree, &options);
+        assert_eq!(sequential.file_count, 8 * 5 * 10 + 8 + 1 + 10);
+        for threads in [0, 4] {
+            let parallel = scan(&tree, &ScanOptions { threads, ..options.clone() });
+            assert_eq!(listing(&parallel), listing(&sequential), "{} threads", threads);
+            assert_eq!(
+                (parallel.total_size, parallel.total_allocated, parallel.file_count, parallel.dir_count),
+                (sequential.total_size, sequential.total_allocated, sequential.file_count, sequential.dir_count)
+            );
+            assert_eq!(
+                (parallel.excluded_count, parallel.excluded_size),
+                (sequential.excluded_count, (8 * 5 + 1) * 77)
+            );
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn remembered_root_counts_add_up_to_the_progress() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        let deep = tree.dir(docs, "deep");
+        tree.file(deep, "notes.txt", 10);
+        tree.file(deep, "scratch.tmp", 20);
+        tree.file(docs, "a.txt", 30);
+        let media = tree.dir(MemoryTree::ROOT, "media");
+        let shows = tree.dir(media, "shows");
+        let season = tree.dir(shows, "season1");
+        tree.file(season, "e1.mkv", 4000);
+        tree.file(season, "e2.mkv", 5000);
+        tree.symlink(media, "docs", "../docs");
+        let options = ScanOptions {
+            threads: 1,
+            follow_symlinks: true,
+            max_depth: Some(2),
+            rules: vec![PathRule::new(RuleAction::Exclude, PatternKind::Glob, "*.tmp")],
+            ..Default::default()
+        };
+        
+        let progress = LiveProgress::new();
+        let roots = [PathBuf::from("/data/docs"), PathBuf::from("/data/media")];
+        let snapshot = parking_lot::Mutex::new(None);
+        let result = FileSystemScanner::scan_directory(
+            &tree,
+            &roots,
+            &options,
+            &AtomicBool::new(false),
+            &progress,
+            &snapshot,
+            None,
+        )
+        .unwrap();
+        
+        let counts = FileSystemScanner::root_entry_counts(&result);
+        let paths: Vec<&Path> = counts.iter().map(|(path, _)| path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/data/docs"), Path::new("/data/media")]);
+        assert!(result.iter().any(|(_, entry)| entry.summary.is_some()));
+        assert!(result.iter().any(|(_, entry)| entry.excluded.is_some()));
+        assert_eq!(counts.iter().map(|(_, entries)| entries).sum::<u64>(), progress.snapshot().files_scanned);
+    }
+    
+    #[test]
+    fn cancelled_scans_keep_a_partial_result_and_their_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("scan.checkpoint");
+        let tree = sample_tree();
+        let mut scanner = FileSystemScanner::with_source(PathBuf::from("/data"), options(), Arc::new(tree.clone()));
+        scanner.source = Arc::new(StopAfterRoot { tree, stop: scanner.should_stop.clone() });
+        scanner.use_checkpoint(file.clone(), false);
+        scanner.start();
+        while !scanner.is_finished() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        
+        assert_eq!(scanner.state(), ScanState::Cancelled);
+        let result = scanner.take_result().unwrap();
+        assert!(result.partial);
+        // Only the root was listed to the end, the folders in it stay unfinished
+        assert_eq!((result.total_size, result.file_count, result.dir_count), (4, 1, 3));
+        for path in ["/data/docs", "/data/media"] {
+            let entry = result.entry(result.find(Path::new(path)).unwrap());
+            assert!(entry.unfinished && !entry.scanning, "{}", path);
+        }
+        assert!(!result.root().unwrap().unfinished);
+        
+        // The checkpoint of the root's listing is left to resume from
+        let saved = checkpoint::load(&file).unwrap();
+        assert_eq!(saved.info.root_path, Path::new("/data"));
+        assert_eq!(saved.queue.len(), 2);
+    }
+    
+    #[test]
+    fn snapshots_show_finished_folders_with_their_final_sizes() {
+        let mut tree = MemoryTree::new("/data");
+        for i in 0..4 {
+            let top = tree.dir(MemoryTree::ROOT, &format!("top{}", i));
+            for j in 0..3 {
+                let dir = tree.dir(top, &format!("dir{}", j));
+                for k in 0..5 {
+                    tree.file(dir, &format!("file{}", k), (i * 100 + j * 10 + k + 1) as u64);
+                }
+            }
+        }
+        let snapshot = parking_lot::Mutex::new(None);
+        let source = CollectSnapshots {
+            tree: &tree,
+            snapshot: &snapshot,
+            seen: parking_lot::Mutex::new(Vec::new()),
+        };
+        let result = FileSystemScanner::scan_directory(
+            &source,
+            &[PathBuf::from("/data")],
+            &options(),
+            &AtomicBool::new(false),
+            &LiveProgress::new(),
+            &snapshot,
+            None,
+        )
+        .unwrap();
+        
+        let snapshots = source.seen.into_inner();
+        assert!(snapshots.len() >= 3, "{} snapshots", snapshots.len());
+        let mut previous_total = 0;
+        for snapshot in &snapshots {
+            assert!(snapshot.root().unwrap().scanning);
+            assert!(snapshot.total_size >= previous_total);
+            previous_total = snapshot.total_size;
+            for (id, entry) in snapshot.iter() {
+                // Folders add up what is listed in them so far
+                if entry.is_directory() {
+                    let listed: u64 = snapshot.children(id).iter().map(|&child| snapshot.entry(child).size).sum();
+                    assert_eq!(entry.size, listed);
+                }
+                // Anything not scanning any more is final
+                if !entry.scanning {
+                    let path = snapshot.path_of(id);
+                    assert_eq!(entry.size, result.entry(result.find(&path).unwrap()).size, "{}", path.display());
+                }
+            }
+        }
+        let finished_dir = |snapshot: &ScanResult| {
+            snapshot
+                .iter()
+                .any(|(_, entry)| entry.is_directory() && !entry.scanning && !entry.children.is_empty())
+        };
+        assert!(snapshots.iter().any(finished_dir));
+        
+        assert!(result.iter().all(|(_, entry)| !entry.scanning));
+        assert_eq!(listing(&result), listing(&scan(&tree, &options())));
+    }
+    
+    #[test]
+    fn include_rules_keep_followed_directory_links() {
+        let mut tree = MemoryTree::new("/data");
+        let store = tree.dir(MemoryTree::ROOT, "store");
+        let app = tree.dir(store, "app-2.0");
+        tree.file(app, "app.log", 300);
+        tree.file(app, "app.bin", 5000);
+        tree.symlink(MemoryTree::ROOT, "current", "store/app-2.0");
+        tree.symlink(MemoryTree::ROOT, "latest.bin", "store/app-2.0/app.bin");
+        let options = ScanOptions {
+            threads: 1,
+            follow_symlinks: true,
+            rules: vec![PathRule::new(RuleAction::Include, PatternKind::Glob, "*.log")],
+            ..Default::default()
+        };
+        
+        let result = scan(&tree, &options);
+        let current = result.find(Path::new("/data/current")).unwrap();
+        assert_eq!(result.entry(current).kind, FileKind::Symlink);
+        assert!(result.entry(current).is_container());
+        assert!(result.find(Path::new("/data/current/app.log")).is_some());
+        // What the link leads to is matched like everything else
+        assert!(result.find(Path::new("/data/current/app.bin")).is_none());
+        assert!(result.find(Path::new("/data/latest.bin")).is_none());
+        assert_eq!(result.total_size, 300);
+    }
+    
+    #[test]
+    fn depth_limited_summaries_match_the_full_scan() {
+        let mut tree = MemoryTree::new("/data");
+        let projects = tree.dir(MemoryTree::ROOT, "projects");
+        for i in 0..3 {
+            let project = tree.dir(projects, &format!("p{}", i));
+            let src = tree.dir(project, "src");
+            for j in 0..=i {
+                tree.file(src, &format!("m{}.rs", j), 1000 + j as u64);
+            }
+            tree.file(project, "Cargo.TOML", 50);
+            tree.file(project, "README", 10);
+        }
+        let media = tree.dir(MemoryTree::ROOT, "media");
+        let movie = tree.file(media, "a.mkv", 70000);
+        tree.file(media, "b.MKV", 80000);
+        tree.hard_link(media, "a-copy.mkv", movie);
+        let newest = Utc::now();
+        tree.stat_mut(movie).modified = Some(newest);
+        tree.file(MemoryTree::ROOT, "top.txt", 4);
+        
+        let full = scan(&tree, &options());
+        let limited = scan(&tree, &ScanOptions { max_depth: Some(1), ..options() });
+        assert_eq!(
+            (limited.total_size, limited.total_allocated, limited.file_count, limited.dir_count),
+            (full.total_size, full.total_allocated, full.file_count, full.dir_count)
+        );
+        
+        for path in ["/data/projects", "/data/media"] {
+            let summarized = limited.entry(limited.find(Path::new(path)).unwrap());
+            let summary = summarized.summary.as_ref().unwrap();
+            assert!(summarized.children.is_empty());
+            
+            // Everything the full scan found below the folder
+            let top = full.find(Path::new(path)).unwrap();
+            let (mut files, mut dirs) = (0, 0);
+            let mut extensions: HashMap<String, u64> = HashMap::new();
+            let mut stack = full.children(top).to_vec();
+            while let Some(id) = stack.pop() {
+                if full.entry(id).is_directory() {
+                    dirs += 1;
+                } else {
+                    files += 1;
+                    *extensions.entry(full.extension(id).unwrap_or("").to_string()).or_default() += 1;
+                }
+                stack.extend_from_slice(full.children(id));
+            }
+            let expected = SubtreeSummary { extensions, ..Default::default() };
+            
+            assert_eq!((summary.file_count, summary.dir_count), (files, dirs), "{}", path);
+            assert_eq!(summary.top_extensions(10), expected.top_extensions(10), "{}", path);
+            assert_eq!(
+                (summarized.size, summarized.allocated_size),
+                (full.entry(top).size, full.entry(top).allocated_size),
+                "{}",
+                path
+            );
+        }
+        let media = limited.entry(limited.find(Path::new("/data/media")).unwrap());
+        let summary = media.summary.as_ref().unwrap();
+        assert_eq!(summary.top_extensions(1), [("mkv", 3)]);
+        assert_eq!(summary.newest_modified.map(|time| time.timestamp()), Some(newest.timestamp()));
+    }
+    
+    #[test]
+    fn interrupted_scans_resume_from_their_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("scan.checkpoint");
+        let tree = sample_tree();
+        
+        let partial = interrupted_scan(&tree, &file);
+        assert!(partial.partial);
+        let saved = checkpoint::load(&file).unwrap();
+        assert_eq!(saved.info.root_path, Path::new("/data"));
+        assert_eq!(saved.info.entries, 4);
+        assert!(saved.info.options.same_walk(&options()));
+        let mut queued: Vec<PathBuf> = saved.queue.iter().map(|&id| saved.result.path_of(id)).collect();
+        queued.sort();
+        assert_eq!(queued, [Path::new("/data/docs"), Path::new("/data/media")]);
+        
+        let resumed = resumed_scan(&tree, &file);
+        let fresh = scan(&tree, &options());
+        assert!(!resumed.partial);
+        assert_eq!(listing(&resumed), listing(&fresh));
+        assert_eq!(
+            (resumed.total_size, resumed.file_count, resumed.dir_count),
+            (fresh.total_size, fresh.file_count, fresh.dir_count)
+        );
+        assert_eq!((resumed.total_size, resumed.file_count, resumed.dir_count), (32104, 4, 4));
+        // A finished scan has no use for its checkpoint
+        assert!(!file.exists());
+    }
+    
+    #[test]
+    fn directories_changed_since_the_checkpoint_are_listed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("scan.checkpoint");
+        let kept = dir.path().join("kept.checkpoint");
+        let mut tree = sample_tree();
+        interrupted_scan(&tree, &file);
+        std::fs::copy(&file, &kept).unwrap();
+        
+        // Without an mtime change the root's listing in the checkpoint is trusted
+        tree.file(MemoryTree::ROOT, "late.txt", 10);
+        let stale = resumed_scan(&tree, &file);
+        assert!(stale.find(Path::new("/data/late.txt")).is_none());
+        assert_eq!(stale.total_size, 32104);
+        
+        tree.stat_mut(MemoryTree::ROOT).modified = Some(Utc::now());
+        let resumed = resumed_scan(&tree, &kept);
+        let fresh = scan(&tree, &options());
+        assert!(resumed.find(Path::new("/data/late.txt")).is_some());
+        assert_eq!(listing(&resumed), listing(&fresh));
+        assert_eq!((resumed.total_size, resumed.file_count), (32114, 5));
+    }
+    
+    #[test]
+    fn nested_and_repeated_roots_are_scanned_once() {
+        let roots = ["/data/media/shows", "/data/docs", "/data/media", "/data/docs/", "/data/./docs/deep", "/data/doc"];
+        let distinct = FileSystemScanner::distinct_roots(roots.iter().map(PathBuf::from).collect());
+        // Ancestors win wherever they are given, names are compared by component
+        assert_eq!(distinct, [Path::new("/data/docs"), Path::new("/data/media"), Path::new("/data/doc")]);
+        
+        let relative = FileSystemScanner::distinct_roots(vec![PathBuf::from("docs")]);
+        assert_eq!(relative, [std::env::current_dir().unwrap().join("docs")]);
+    }
+    
+    #[test]
+    fn several_roots_are_found_and_totalled_apart() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        let deep = tree.dir(docs, "deep");
+        tree.file(deep, "b.txt", 20);
+        tree.file(docs, "a.txt", 100);
+        let media = tree.dir(MemoryTree::ROOT, "media");
+        tree.dir(media, "shows");
+        tree.file(media, "e1.mkv", 4000);
+        tree.file(MemoryTree::ROOT, "top.bin", 5);
+        
+        let roots = [PathBuf::from("/data/docs"), PathBuf::from("/data/media")];
+        let snapshot = parking_lot::Mutex::new(None);
+        let progress = LiveProgress::new();
+        let result = FileSystemScanner::scan_directory(
+            &tree,
+            &roots,
+            &options(),
+            &AtomicBool::new(false),
+            &progress,
+            &snapshot,
+            None,
+        )
+        .unwrap();
+        
+        let totals = result.root_totals();
+        let summed: Vec<_> = totals.iter().map(|t| (t.path.as_path(), t.size, t.file_count, t.dir_count)).collect();
+        assert_eq!(summed, [(Path::new("/data/docs"), 120, 2, 2), (Path::new("/data/media"), 4000, 1, 2)]);
+        assert_eq!(totals.iter().map(|t| t.size).sum::<u64>(), result.total_size);
+        assert_eq!(totals.iter().map(|t| t.file_count).sum::<u64>(), result.file_count);
+        
+        for path in ["/data/docs", "/data/docs/deep/b.txt", "/data/media/shows"] {
+            let id = result.find(Path::new(path)).unwrap_or_else(|| panic!("{} is missing", path));
+            assert_eq!(result.path_of(id), Path::new(path));
+        }
+        assert_eq!(result.find(&result.root_path), Some(NodeId::ROOT));
+        assert_eq!(result.find(Path::new("/data/top.bin")), None);
+        assert_eq!(result.find(Path::new("/data/docs/missing.txt")), None);
+        
+        // A single root is totalled as a whole
+        let single = scan(&tree, &options());
+        let totals = single.root_totals();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(
+            (totals[0].path.as_path(), totals[0].size, totals[0].file_count, totals[0].dir_count),
+            (Path::new("/data"), single.total_size, single.file_count, single.dir_count)
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::scanner::ScanResult;
+use crate::tree::{legacy, FORMAT_VERSION, OLDEST_FORMAT_VERSION};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// File extension of saved scans
+pub const EXTENSION: &str = "diskray";
+
+/// First bytes of every snapshot file
+const MAGIC: &[u8; 8] = b"DISKRAY\0";
+
+/// zstd level; higher levels shrink a big tree only a little more but save much slower
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Errors while saving or loading a snapshot
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("not a DiskRay snapshot")]
+    NotASnapshot,
+    #[error("format {0} is not supported, this version reads formats {OLDEST_FORMAT_VERSION} to {FORMAT_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("damaged snapshot: {0}")]
+    Corrupt(#[from] bincode::Error),
+    #[error("damaged scan of an older version: {0}")]
+    CorruptLegacy(#[from] serde_json::Error),
+}
+
+/// Varint encoding keeps the many small numbers of a tree (ids, counts) short
+pub(crate) fn encoding() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Write `magic` and the `ScanResult` format version as a little-endian u32
+pub(crate) fn write_header<W: Write>(writer: &mut W, magic: &[u8; 8]) -> io::Result<()> {
+    writer.write_all(magic)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())
+}
+
+/// Check a header written by `write_header`, which must be of the current format version
+pub(crate) fn read_header<R: Read>(reader: &mut R, magic: &[u8; 8]) -> Result<(), SnapshotError> {
+    match read_version(reader, magic)? {
+        FORMAT_VERSION => Ok(()),
+        version => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+/// Check the magic bytes of a header written by `write_header` and return its format version
+fn read_version<R: Read>(reader: &mut R, magic: &[u8; 8]) -> Result<u32, SnapshotError> {
+    let mut found = [0; 8];
+    let mut version = [0; 4];
+    match reader.read_exact(&mut found).and_then(|_| reader.read_exact(&mut version)) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(SnapshotError::NotASnapshot),
+        result => result?,
+    }
+    if &found != magic {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    Ok(u32::from_le_bytes(version))
+}
+
+/// Write `value` as zstd-compressed bincode, up to the end of `writer`
+pub(crate) fn write_compressed<W: Write, T: Serialize>(value: &T, writer: W) -> Result<(), SnapshotError> {
+    // bincode writes field by field, the buffer hands zstd larger blocks
+    let mut encoder = BufWriter::new(zstd::Encoder::new(writer, COMPRESSION_LEVEL)?);
+    encoding().serialize_into(&mut encoder, value)?;
+    let encoder = encoder.into_inner().map_err(|e| e.into_error())?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+/// Read a value written by `write_compressed`
+pub(crate) fn read_compressed<R: Read, T: DeserializeOwned>(reader: R) -> Result<T, SnapshotError> {
+    let decoder = BufReader::new(zstd::Decoder::new(reader)?);
+    Ok(encoding().deserialize_from(decoder)?)
+}
+
+/// Write a scan as a snapshot.
+///
+/// The layout is the magic bytes, the `ScanResult` format version as a
+/// little-endian u32, then the result as zstd-compressed bincode.
+pub fn write_snapshot<W: Write>(result: &ScanResult, mut writer: W) -> Result<(), SnapshotError> {
+    write_header(&mut writer, MAGIC)?;
+    write_compressed(result, writer)
+}
+
+/// Read a snapshot written by `write_snapshot`. Snapshots of older format
+/// versions down to `OLDEST_FORMAT_VERSION` are migrated to the current one,
+/// and so are scans saved as JSON before there were snapshots.
+pub fn read_snapshot<R: Read>(reader: R) -> Result<ScanResult, SnapshotError> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.first() == Some(&b'{') {
+        return Ok(legacy::decode_json(reader)?);
+    }
+    
+    let version = read_version(&mut reader, MAGIC)?;
+    if version == FORMAT_VERSION {
+        return read_compressed(reader);
+    }
+    if !(OLDEST_FORMAT_VERSION..FORMAT_VERSION).contains(&version) {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    
+    let mut body = Vec::new();
+    zstd::Decoder::new(reader)?.read_to_end(&mut body)?;
+    match legacy::decode(version, &body) {
+        Some(result) => Ok(result?),
+        None => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+/// Save a scan to `path`
+pub fn save(result: &ScanResult, path: &Path) -> Result<(), SnapshotError> {
+    write_snapshot(result, BufWriter::new(File::create(path)?))
+}
+
+/// Load a scan saved with `save`
+pub fn load(path: &Path) -> Result<ScanResult, SnapshotError> {
+    read_snapshot(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ExcludeReason, FileKind, ScanOptions};
+    use crate::source::testing::scan;
+    use crate::source::MemoryTree;
+    
+    /// Scan of the same folder saved by the versions writing format 3 and 4:
+    /// docs/{readme.txt, notes.md}, src/{main.rs, lib.rs, main-link.rs} with
+    /// main-link.rs a hard link, an empty folder, a symlink `link` to docs and
+    /// build.tmp left out by an exclude rule
+    const FIXTURES: [(u32, &[u8]); 2] = [
+        (3, include_bytes!("../tests/data/snapshot-v3.diskray")),
+        (4, include_bytes!("../tests/data/snapshot-v4.diskray")),
+    ];
+    
+    #[test]
+    fn older_formats_are_migrated() {
+        let root = Path::new("/tmp/diskray-fixture");
+        for (version, data) in FIXTURES {
+            assert_eq!(u32::from_le_bytes(data[8..12].try_into().unwrap()), version);
+            let result = read_snapshot(data).unwrap_or_else(|e| panic!("format {}: {}", version, e));
+            
+            assert_eq!(result.format_version, FORMAT_VERSION);
+            assert_eq!(result.root_path, root);
+            assert!(result.roots.is_empty());
+            // The hard link is counted once, the symlink by the length of its target
+            assert_eq!(result.total_size, 1200 + 300 + 4000 + 10 + 4);
+            assert_eq!((result.file_count, result.dir_count), (6, 4));
+            assert_eq!((result.excluded_count, result.excluded_size), (1, 500));
+            assert_eq!(result.entries.len(), 11);
+            assert!(result.entries.iter().all(|entry| !entry.in_archive));
+            
+            let main = result.find(&root.join("src/main.rs")).unwrap();
+            assert_eq!(result.entry(main).size, 4000);
+            assert!(result.entry(main).shared);
+            let docs = result.find(&root.join("docs")).unwrap();
+            assert_eq!(result.entry(docs).size, 1500);
+            let link = result.entry(result.find(&root.join("link")).unwrap());
+            assert_eq!(link.kind, FileKind::Symlink);
+            assert_eq!(link.link_target.as_ref().unwrap().path, Path::new("docs"));
+            
+            assert!(result.options.one_file_system);
+            assert_eq!(result.options.rules.len(), 1);
+            assert!(!result.options.expand_archives);
+        }
+    }
+    
+    #[test]
+    fn path_based_scans_are_migrated() {
+        let root = Path::new("/tmp/diskray-fixture");
+        
+        // The first version only knew apparent sizes, so the hard link counts twice like it did then
+        let first = read_snapshot(&include_bytes!("../tests/data/scan-v1.json")[..]).unwrap();
+        assert_eq!(first.format_version, FORMAT_VERSION);
+        assert_eq!(first.root_path, root);
+        assert_eq!(first.entries.len(), 11);
+        assert_eq!((first.total_size, first.total_allocated), (10014, 10014));
+        assert_eq!((first.file_count, first.dir_count), (7, 4));
+        let readme = first.find(&root.join("docs/readme.txt")).unwrap();
+        assert_eq!(first.path_of(readme), root.join("docs/readme.txt"));
+        assert_eq!(first.entry(readme).size, 1200);
+        assert_eq!(first.extension(readme), Some("txt"));
+        
+        // The last one saved allocated sizes, hard links and exclusions as well
+        let last = read_snapshot(&include_bytes!("../tests/data/scan-v1-allocated.json")[..]).unwrap();
+        assert_eq!(last.entries.len(), 11);
+        assert_eq!((last.total_size, last.total_allocated), (1200 + 300 + 4000 + 10 + 4, 32768));
+        assert_eq!((last.file_count, last.dir_count), (6, 4));
+        assert_eq!((last.excluded_count, last.excluded_size), (1, 500));
+        let src = last.entry(last.find(&root.join("src")).unwrap());
+        assert_eq!((src.size, src.allocated_size, src.own_allocated), (4010, 12288, 4096));
+        assert_eq!(last.root().unwrap().own_allocated, 4096);
+        assert!(last.entry(last.find(&root.join("src/main.rs")).unwrap()).shared);
+        let excluded = last.entry(last.find(&root.join("build.tmp")).unwrap());
+        assert_eq!(excluded.excluded, Some(ExcludeReason::Pattern));
+    }
+    
+    #[test]
+    fn current_format_round_trips() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        tree.file(docs, "a.txt", 1234);
+        tree.file(MemoryTree::ROOT, "b.bin", 99);
+        let result = scan(&tree, &ScanOptions::default());
+        
+        let mut data = Vec::new();
+        write_snapshot(&result, &mut data).unwrap();
+        let loaded = read_snapshot(data.as_slice()).unwrap();
+        assert_eq!(loaded.total_size, 1333);
+        assert_eq!(loaded.entries.len(), result.entries.len());
+        assert_eq!(loaded.path_of(loaded.find(Path::new("/data/docs/a.txt")).unwrap()), Path::new("/data/docs/a.txt"));
+    }
+    
+    #[test]
+    fn unknown_versions_and_other_files_are_rejected() {
+        for version in [OLDEST_FORMAT_VERSION - 1, FORMAT_VERSION + 1] {
+            let mut data = MAGIC.to_vec();
+            data.extend_from_slice(&version.to_le_bytes());
+            assert!(matches!(read_snapshot(data.as_slice()), Err(SnapshotError::UnsupportedVersion(v)) if v == version));
+        }
+        assert!(matches!(read_snapshot(&b"DISKRAYC\x05\0\0\0"[..]), Err(SnapshotError::NotASnapshot)));
+        assert!(matches!(read_snapshot(&b"DISK"[..]), Err(SnapshotError::NotASnapshot)));
+        assert!(matches!(read_snapshot(&b"{\"root_path\": 1}"[..]), Err(SnapshotError::CorruptLegacy(_))));
+    }
+}
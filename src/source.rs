@@ -0,0 +1,631 @@
+use crate::scanner::FileKind;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+/// Metadata of one entry, as far as a scan uses it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryStat {
+    pub kind: FileKind,
+    /// Length in bytes, for symlinks the length of the target path
+    pub len: u64,
+    /// Bytes allocated on disk
+    pub allocated: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub accessed: Option<DateTime<Utc>>,
+    /// Last inode change, the Unix `ctime`
+    pub changed: Option<DateTime<Utc>>,
+    pub created: Option<DateTime<Utc>>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// File type and permission bits of `st_mode`
+    pub mode: Option<u32>,
+    /// Number of hard links to the inode
+    pub nlink: u64,
+    /// (device, inode) pair, where the source provides one
+    pub inode: Option<(u64, u64)>,
+}
+
+impl EntryStat {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+}
+
+impl From<&std::fs::Metadata> for EntryStat {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        // std reads the birth time through statx where the kernel and filesystem support it
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        let accessed = metadata.accessed().ok().map(DateTime::<Utc>::from);
+        // Some filesystems answer statx with a zero birth time instead of leaving it out
+        let created = metadata
+            .created()
+            .ok()
+            .filter(|&time| time != std::time::UNIX_EPOCH)
+            .map(DateTime::<Utc>::from);
+        let (uid, gid, mode, changed) = unix_metadata(metadata);
+        let (nlink, inode) = link_info(metadata);
+        
+        Self {
+            kind: FileKind::from_file_type(metadata.file_type()),
+            len: metadata.len(),
+            allocated: allocated_size(metadata),
+            modified,
+            accessed,
+            changed,
+            created,
+            uid,
+            gid,
+            mode,
+            nlink,
+            inode,
+        }
+    }
+}
+
+/// One entry of a directory listing
+#[derive(Debug)]
+pub struct DirItem {
+    pub name: OsString,
+    /// Metadata of the entry itself, symlinks are not followed
+    pub stat: io::Result<EntryStat>,
+}
+
+/// Contents of a file opened through `ScanSource::open`
+pub trait FileContents: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> FileContents for T {}
+
+/// Where a scan reads directories and metadata from.
+///
+/// Paths are always absolute paths below the scan root, built from the root
+/// and the names returned by `read_dir`.
+pub trait ScanSource: Send + Sync {
+    /// Metadata of `path`, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<EntryStat>;
+    
+    /// Metadata of `path` itself, like `lstat`
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryStat>;
+    
+    /// Entries of the directory `path`, in no particular order. An error on
+    /// a single entry does not end the listing.
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<DirItem>> + 'a>>;
+    
+    /// Contents of the symlink `path`
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+    
+    /// Number of entries a scan of `root` can expect, when the source knows it cheaply
+    fn used_inodes(&self, _root: &Path) -> Option<u64> {
+        None
+    }
+    
+    /// Open the file `path` for reading, for sources that hold file contents
+    fn open<'a>(&'a self, _path: &Path) -> io::Result<Box<dyn FileContents + 'a>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The mounted filesystems, through `std::fs`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl ScanSource for RealFs {
+    fn metadata(&self, path: &Path) -> io::Result<EntryStat> {
+        std::fs::metadata(path).map(|metadata| EntryStat::from(&metadata))
+    }
+    
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryStat> {
+        std::fs::symlink_metadata(path).map(|metadata| EntryStat::from(&metadata))
+    }
+    
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<DirItem>> + 'a>> {
+        let entries = std::fs::read_dir(path)?.map(|entry| {
+            entry.map(|entry| DirItem {
+                name: entry.file_name(),
+                // DirEntry::metadata does not traverse symlinks
+                stat: entry.metadata().map(|metadata| EntryStat::from(&metadata)),
+            })
+        });
+        Ok(Box::new(entries))
+    }
+    
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+    
+    fn used_inodes(&self, root: &Path) -> Option<u64> {
+        filesystem_used_inodes(root)
+    }
+    
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn FileContents + 'a>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+}
+
+/// Device id of every entry of a `MemoryTree`
+const MEMORY_DEVICE: u64 = 1;
+
+/// Symlinks followed while resolving one path before giving up, like Linux's limit
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Entry of a `MemoryTree`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryId(u32);
+
+#[derive(Debug, Clone)]
+struct MemoryNode {
+    name: OsString,
+    parent: u32,
+    children: Vec<u32>,
+    /// Index into `MemoryTree::inodes`, shared by hard links
+    inode: u32,
+    /// Contents of a symlink
+    target: Option<PathBuf>,
+    /// Listing the directory fails with "permission denied"
+    denied: bool,
+}
+
+/// Directory tree held in memory, to scan synthetic trees without touching disk.
+///
+/// Built from the root down with `dir`, `file`, `symlink` and `hard_link`.
+/// Entries get sizes, inode numbers and link counts like on a Unix
+/// filesystem, timestamps and owners are unset unless given with `stat_mut`.
+#[derive(Debug, Clone)]
+pub struct MemoryTree {
+    root_path: PathBuf,
+    nodes: Vec<MemoryNode>,
+    inodes: Vec<EntryStat>,
+    /// (parent, name) of every entry, so paths resolve quickly in huge directories
+    lookup: HashMap<(u32, OsString), u32>,
+}
+
+impl MemoryTree {
+    pub const ROOT: MemoryId = MemoryId(0);
+    
+    /// Tree with only an empty root directory at `root_path`
+    pub fn new(root_path: impl Into<PathBuf>) -> Self {
+        let root_path = root_path.into();
+        let name = root_path.file_name().unwrap_or(root_path.as_os_str()).to_os_string();
+        let mut root = Self::new_stat(FileKind::Directory, 4096, 4096);
+        root.inode = Some((MEMORY_DEVICE, 1));
+        Self {
+            root_path,
+            nodes: vec![MemoryNode {
+                name,
+                parent: 0,
+                children: Vec::new(),
+                inode: 0,
+                target: None,
+                denied: false,
+            }],
+            inodes: vec![root],
+            lookup: HashMap::new(),
+        }
+    }
+    
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+    
+    /// Number of entries, the root included
+    pub fn entry_count(&self) -> usize {
+        self.nodes.len()
+    }
+    
+    /// Add an empty directory
+    pub fn dir(&mut self, parent: MemoryId, name: &str) -> MemoryId {
+        self.add(parent, name, Self::new_stat(FileKind::Directory, 4096, 4096), None)
+    }
+    
+    /// Add a regular file of `size` bytes, taking up whole 4 KiB blocks
+    pub fn file(&mut self, parent: MemoryId, name: &str, size: u64) -> MemoryId {
+        self.add(parent, name, Self::new_stat(FileKind::File, size, size.div_ceil(4096) * 4096), None)
+    }
+    
+    /// Add a symlink; relative targets are resolved from `parent`, absolute
+    /// ones must lie below the root to lead anywhere
+    pub fn symlink(&mut self, parent: MemoryId, name: &str, target: impl Into<PathBuf>) -> MemoryId {
+        let target = target.into();
+        let len = target.as_os_str().len() as u64;
+        self.add(parent, name, Self::new_stat(FileKind::Symlink, len, 0), Some(target))
+    }
+    
+    /// Add another name for the inode of `to`
+    pub fn hard_link(&mut self, parent: MemoryId, name: &str, to: MemoryId) -> MemoryId {
+        let inode = self.nodes[to.0 as usize].inode;
+        self.inodes[inode as usize].nlink += 1;
+        let target = self.nodes[to.0 as usize].target.clone();
+        self.insert(parent, name, inode, target)
+    }
+    
+    /// Metadata of an entry, shared with its hard links, for setting times, owners or sizes
+    pub fn stat_mut(&mut self, id: MemoryId) -> &mut EntryStat {
+        let inode = self.nodes[id.0 as usize].inode;
+        &mut self.inodes[inode as usize]
+    }
+    
+    /// Make listing the directory `id` fail with "permission denied"
+    pub fn deny(&mut self, id: MemoryId) {
+        self.nodes[id.0 as usize].denied = true;
+    }
+    
+    /// Full path of an entry
+    pub fn path_of(&self, id: MemoryId) -> PathBuf {
+        let mut names = Vec::new();
+        let mut current = id.0;
+        while current != 0 {
+            let node = &self.nodes[current as usize];
+            names.push(node.name.as_os_str());
+            current = node.parent;
+        }
+        let mut path = self.root_path.clone();
+        path.extend(names.iter().rev());
+        path
+    }
+    
+    fn new_stat(kind: FileKind, len: u64, allocated: u64) -> EntryStat {
+        EntryStat {
+            kind,
+            len,
+            allocated,
+            modified: None,
+            accessed: None,
+            changed: None,
+            created: None,
+            uid: None,
+            gid: None,
+            mode: None,
+            nlink: 1,
+            inode: None,
+        }
+    }
+    
+    fn add(&mut self, parent: MemoryId, name: &str, mut stat: EntryStat, target: Option<PathBuf>) -> MemoryId {
+        let inode = self.inodes.len() as u32;
+        stat.inode = Some((MEMORY_DEVICE, u64::from(inode) + 1));
+        self.inodes.push(stat);
+        self.insert(parent, name, inode, target)
+    }
+    
+    fn insert(&mut self, parent: MemoryId, name: &str, inode: u32, target: Option<PathBuf>) -> MemoryId {
+        let id = self.nodes.len() as u32;
+        let previous = self.lookup.insert((parent.0, name.into()), id);
+        assert!(previous.is_none(), "{} already exists in {}", name, self.path_of(parent).display());
+        self.nodes.push(MemoryNode {
+            name: name.into(),
+            parent: parent.0,
+            children: Vec::new(),
+            inode,
+            target,
+            denied: false,
+        });
+        self.nodes[parent.0 as usize].children.push(id);
+        MemoryId(id)
+    }
+    
+    fn stat_of(&self, id: u32) -> EntryStat {
+        self.inodes[self.nodes[id as usize].inode as usize].clone()
+    }
+    
+    /// Find the entry at `path`, following symlinks on the way and, with
+    /// `follow_last`, the one the path ends in
+    fn resolve(&self, path: &Path, follow_last: bool) -> io::Result<u32> {
+        let relative = path.strip_prefix(&self.root_path).map_err(|_| not_found())?;
+        let mut pending: Vec<OsString> = components(relative);
+        pending.reverse();
+        
+        let mut current = 0;
+        let mut hops = 0;
+        while let Some(name) = pending.pop() {
+            if name == ".." {
+                current = self.nodes[current as usize].parent;
+                continue;
+            }
+            if self.stat_of(current).kind != FileKind::Directory {
+                return Err(not_a_directory());
+            }
+            let child = *self.lookup.get(&(current, name)).ok_or_else(not_found)?;
+            
+            let Some(target) = &self.nodes[child as usize].target else {
+                current = child;
+                continue;
+            };
+            if pending.is_empty() && !follow_last {
+                current = child;
+                continue;
+            }
+            
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Err(loop_error());
+            }
+            let rest = if target.is_absolute() {
+                current = 0;
+                target.strip_prefix(&self.root_path).map_err(|_| not_found())?
+            } else {
+                target.as_path()
+            };
+            pending.extend(components(rest).into_iter().rev());
+        }
+        Ok(current)
+    }
+}
+
+impl ScanSource for MemoryTree {
+    fn metadata(&self, path: &Path) -> io::Result<EntryStat> {
+        self.resolve(path, true).map(|id| self.stat_of(id))
+    }
+    
+    fn symlink_metadata(&self, path: &Path) -> io::Result<EntryStat> {
+        self.resolve(path, false).map(|id| self.stat_of(id))
+    }
+    
+    fn read_dir<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<DirItem>> + 'a>> {
+        let id = self.resolve(path, true)?;
+        let node = &self.nodes[id as usize];
+        if self.stat_of(id).kind != FileKind::Directory {
+            return Err(not_a_directory());
+        }
+        if node.denied {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        }
+        Ok(Box::new(node.children.iter().map(|&child| {
+            Ok(DirItem {
+                name: self.nodes[child as usize].name.clone(),
+                stat: Ok(self.stat_of(child)),
+            })
+        })))
+    }
+    
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let id = self.resolve(path, false)?;
+        self.nodes[id as usize]
+            .target
+            .clone()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))
+    }
+    
+    fn used_inodes(&self, root: &Path) -> Option<u64> {
+        (root == self.root_path).then_some(self.nodes.len() as u64)
+    }
+}
+
+/// Names of the components of a relative path, `.` left out
+fn components(path: &Path) -> Vec<OsString> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsStr::new("..").to_os_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn not_found() -> io::Error {
+    io::Error::from(io::ErrorKind::NotFound)
+}
+
+/// ENOTDIR, which io::ErrorKind only names on recent Rust versions
+#[cfg(unix)]
+fn not_a_directory() -> io::Error {
+    io::Error::from_raw_os_error(libc::ENOTDIR)
+}
+
+#[cfg(not(unix))]
+fn not_a_directory() -> io::Error {
+    io::Error::other("not a directory")
+}
+
+/// ELOOP, so the scanner tells symlink loops of a `MemoryTree` apart like real ones
+#[cfg(unix)]
+fn loop_error() -> io::Error {
+    io::Error::from_raw_os_error(libc::ELOOP)
+}
+
+#[cfg(not(unix))]
+fn loop_error() -> io::Error {
+    io::Error::other("too many levels of symbolic links")
+}
+
+/// Hard link count and (device, inode) identity of an entry
+#[cfg(unix)]
+fn link_info(metadata: &std::fs::Metadata) -> (u64, Option<(u64, u64)>) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink(), Some((metadata.dev(), metadata.ino())))
+}
+
+#[cfg(not(unix))]
+fn link_info(_metadata: &std::fs::Metadata) -> (u64, Option<(u64, u64)>) {
+    (1, None)
+}
+
+/// Owner uid and gid, `st_mode` bits and ctime of an entry
+#[cfg(unix)]
+fn unix_metadata(metadata: &std::fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>, Option<DateTime<Utc>>) {
+    use std::os::unix::fs::MetadataExt;
+    let changed = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32);
+    (Some(metadata.uid()), Some(metadata.gid()), Some(metadata.mode()), changed)
+}
+
+#[cfg(not(unix))]
+fn unix_metadata(_metadata: &std::fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>, Option<DateTime<Utc>>) {
+    (None, None, None, None)
+}
+
+/// Bytes allocated on disk for an entry
+#[cfg(unix)]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always in 512-byte units, whatever the filesystem block size
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &std::fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Number of used inodes on the filesystem mounted at `root`.
+///
+/// The count covers the whole filesystem, so it is only returned when
+/// `root` is the mount point itself.
+#[cfg(unix)]
+fn filesystem_used_inodes(root: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    
+    let dev = std::fs::metadata(root).ok()?.dev();
+    if let Some(parent) = root.parent() {
+        if std::fs::metadata(parent).ok()?.dev() == dev {
+            return None;
+        }
+    }
+    
+    let c_path = std::ffi::CString::new(root.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    
+    // Some filesystems (btrfs, network mounts) report no inode counts
+    let used = (stat.f_files as u64).saturating_sub(stat.f_ffree as u64);
+    (used > 0).then_some(used)
+}
+
+#[cfg(not(unix))]
+fn filesystem_used_inodes(_root: &Path) -> Option<u64> {
+    None
+}
+
+/// Helpers shared by the tests that scan in-memory trees
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::MemoryTree;
+    use crate::scanner::{ExcludeReason, FileKind, FileSystemScanner, ScanOptions, ScanResult};
+    use std::path::PathBuf;
+    
+    /// What a scan found at one path, to compare two scans of the same tree
+    pub(crate) type Listed = (PathBuf, FileKind, u64, u64, bool, Option<ExcludeReason>, bool);
+    
+    /// Scan the whole of `tree` on the calling thread
+    pub(crate) fn scan(tree: &MemoryTree, options: &ScanOptions) -> ScanResult {
+        FileSystemScanner::scan_source_blocking(tree, &tree.root_path, options).unwrap()
+    }
+    
+    /// Kind, sizes, sharing, exclusion and read error of every entry, in path order
+    pub(crate) fn listing(result: &ScanResult) -> Vec<Listed> {
+        let mut listing = Vec::new();
+        result.for_each_path(|id, path| {
+            let entry = result.entry(id);
+            listing.push((
+                path.to_path_buf(),
+                entry.kind,
+                entry.size,
+                entry.allocated_size,
+                entry.shared,
+                entry.excluded,
+                entry.read_error,
+            ));
+        });
+        listing.sort_by(|a, b| a.0.cmp(&b.0));
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ScanErrorKind, ScanOperation, ScanOptions, ScanResult};
+    use testing::scan;
+    
+    fn entry_at<'a>(result: &'a ScanResult, path: &str) -> &'a crate::scanner::FileEntry {
+        result.entry(result.find(Path::new(path)).unwrap_or_else(|| panic!("{} is missing", path)))
+    }
+    
+    #[test]
+    fn sizes_and_counts_roll_up() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        tree.file(docs, "a.txt", 1000);
+        tree.file(docs, "b.txt", 5000);
+        let sub = tree.dir(docs, "sub");
+        tree.file(sub, "c.txt", 1);
+        tree.dir(MemoryTree::ROOT, "empty");
+        tree.file(MemoryTree::ROOT, "top.bin", 4096);
+        assert_eq!(tree.entry_count(), 8);
+        
+        let result = scan(&tree, &ScanOptions::default());
+        assert_eq!(result.total_size, 10097);
+        // Every directory keeps the block of its own listing
+        assert_eq!(result.total_allocated, 5 * 4096 + 4 * 4096);
+        assert_eq!((result.file_count, result.dir_count), (4, 4));
+        assert_eq!(entry_at(&result, "/data/docs").size, 6001);
+        assert_eq!(entry_at(&result, "/data/docs/sub").allocated_size, 2 * 4096);
+        assert_eq!(entry_at(&result, "/data/empty").size, 0);
+        assert!(result.errors.is_empty());
+    }
+    
+    #[test]
+    fn hard_links_are_counted_once() {
+        let mut tree = MemoryTree::new("/data");
+        let first = tree.dir(MemoryTree::ROOT, "first");
+        let big = tree.file(first, "big.iso", 5000);
+        let second = tree.dir(MemoryTree::ROOT, "second");
+        tree.hard_link(second, "same.iso", big);
+        assert_eq!(tree.stat_mut(big).nlink, 2);
+        
+        let result = scan(&tree, &ScanOptions::default());
+        assert_eq!(result.total_size, 5000);
+        assert_eq!(result.total_allocated, 2 * 4096 + 3 * 4096);
+        // Both names are listed, only the space is shared
+        assert_eq!(result.file_count, 2);
+        assert!(entry_at(&result, "/data/first/big.iso").shared);
+        assert!(entry_at(&result, "/data/second/same.iso").shared);
+    }
+    
+    #[test]
+    fn followed_symlink_loops_end() {
+        let mut tree = MemoryTree::new("/data");
+        let a = tree.dir(MemoryTree::ROOT, "a");
+        tree.file(a, "f.txt", 10);
+        tree.symlink(a, "up", "..");
+        tree.symlink(MemoryTree::ROOT, "itself", "itself");
+        assert_eq!(tree.metadata(Path::new("/data/itself")).unwrap_err().kind(), loop_error().kind());
+        
+        let options = ScanOptions { follow_symlinks: true, ..Default::default() };
+        let result = scan(&tree, &options);
+        
+        let up = entry_at(&result, "/data/a/up");
+        assert!(up.link_target.as_ref().unwrap().cycle);
+        assert!(up.children.is_empty());
+        assert!(entry_at(&result, "/data/itself").is_broken_link());
+        // The file is counted once, the link into an ancestor adds nothing
+        assert_eq!(result.total_size, 10 + "itself".len() as u64);
+        assert_eq!(result.find(Path::new("/data/a/up/a")), None);
+    }
+    
+    #[test]
+    fn unreadable_directories_are_scan_errors() {
+        let mut tree = MemoryTree::new("/data");
+        let open = tree.dir(MemoryTree::ROOT, "open");
+        tree.file(open, "x.txt", 100);
+        let secret = tree.dir(MemoryTree::ROOT, "secret");
+        tree.file(secret, "y.txt", 200);
+        tree.deny(secret);
+        
+        let result = scan(&tree, &ScanOptions::default());
+        assert_eq!(result.errors.len(), 1);
+        let error = &result.errors[0];
+        assert_eq!(error.path, Path::new("/data/secret"));
+        assert_eq!(error.operation, ScanOperation::ReadDir);
+        assert_eq!(error.kind, ScanErrorKind::PermissionDenied);
+        
+        let secret = entry_at(&result, "/data/secret");
+        assert!(secret.read_error);
+        assert!(secret.children.is_empty());
+        assert_eq!(result.total_size, 100);
+        assert_eq!(result.file_count, 1);
+    }
+}
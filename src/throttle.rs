@@ -0,0 +1,295 @@
+use crate::progress::LiveProgress;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+/// Share of the last 10 s in which some task waited for I/O, in percent,
+/// above which a gentle scan waits
+const IO_PRESSURE_LIMIT: f32 = 20.0;
+
+/// Share of the last 10 s in which some runnable task waited for a CPU, in percent
+const CPU_PRESSURE_LIMIT: f32 = 50.0;
+
+/// 1-minute load average per CPU
+const LOAD_LIMIT: f32 = 1.5;
+
+/// Requests in flight on the disk holding the scan root
+const DISK_QUEUE_LIMIT: u64 = 16;
+
+/// How often the system is checked for being busy
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a gentle scan waits before checking a busy system again
+const BACKOFF_STEP: Duration = Duration::from_millis(250);
+
+/// Why a gentle scan is waiting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusyReason {
+    /// Percentage of time tasks were stalled on I/O, from /proc/pressure/io
+    IoPressure(f32),
+    /// Percentage of time tasks waited for a CPU, from /proc/pressure/cpu
+    CpuPressure(f32),
+    /// 1-minute load average per CPU, from /proc/loadavg
+    LoadAverage(f32),
+    /// Requests in flight on the disk, from /proc/diskstats
+    DiskQueue(u64),
+}
+
+impl std::fmt::Display for BusyReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BusyReason::IoPressure(percent) => write!(f, "I/O pressure {:.0}%", percent),
+            BusyReason::CpuPressure(percent) => write!(f, "CPU pressure {:.0}%", percent),
+            BusyReason::LoadAverage(load) => write!(f, "load {:.1} per CPU", load),
+            BusyReason::DiskQueue(requests) => write!(f, "{} disk requests queued", requests),
+        }
+    }
+}
+
+/// How much a scan is holding back, see `ScanOptions::gentle`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ThrottleState {
+    #[default]
+    FullSpeed,
+    /// Running at low priority, without waiting
+    LowPriority,
+    /// Waiting to stay under `ScanOptions::max_ops_per_sec`
+    RateLimited,
+    /// Paused while the system is busy
+    BackingOff(BusyReason),
+}
+
+impl std::fmt::Display for ThrottleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThrottleState::FullSpeed => write!(f, "full speed"),
+            ThrottleState::LowPriority => write!(f, "low priority"),
+            ThrottleState::RateLimited => write!(f, "rate limited"),
+            ThrottleState::BackingOff(reason) => write!(f, "backing off, {}", reason),
+        }
+    }
+}
+
+/// Pacing of the metadata operations of one scan, shared by its workers
+pub(crate) struct Throttle {
+    gentle: bool,
+    /// Time per operation under the rate limit
+    op_interval: Option<Duration>,
+    /// Earliest start of the next operation under the rate limit
+    next_op: parking_lot::Mutex<Instant>,
+    /// (major, minor) of the disk holding the root
+    disk: Option<(u64, u64)>,
+    /// When the system is checked next, and what the last check found
+    sample: parking_lot::Mutex<(Instant, Option<BusyReason>)>,
+    /// 0 when running freely, 1 while rate limited, 2 while backing off
+    waiting: AtomicU8,
+}
+
+impl Throttle {
+    pub fn new(gentle: bool, max_ops_per_sec: Option<u32>, root_device: Option<u64>) -> Self {
+        let now = Instant::now();
+        Self {
+            gentle,
+            op_interval: max_ops_per_sec
+                .filter(|&ops| ops > 0)
+                .map(|ops| Duration::from_secs(1) / ops),
+            next_op: parking_lot::Mutex::new(now),
+            disk: root_device.map(split_device),
+            sample: parking_lot::Mutex::new((now, None)),
+            waiting: AtomicU8::new(0),
+        }
+    }
+    
+    /// State of a scan that is not waiting
+    pub fn base_state(&self) -> ThrottleState {
+        if self.gentle {
+            ThrottleState::LowPriority
+        } else {
+            ThrottleState::FullSpeed
+        }
+    }
+    
+    /// Hold back before one metadata operation: wait while the system is
+    /// busy in gentle mode, then for a slot under the rate limit
+    pub fn wait(&self, should_stop: &AtomicBool, progress: &LiveProgress) {
+        if !self.gentle && self.op_interval.is_none() {
+            return;
+        }
+        
+        if self.gentle {
+            while let Some(reason) = self.busy() {
+                if should_stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                self.waiting.store(2, Ordering::Relaxed);
+                progress.set_throttle(ThrottleState::BackingOff(reason));
+                std::thread::sleep(BACKOFF_STEP);
+            }
+        }
+        
+        let mut limited = false;
+        if let Some(interval) = self.op_interval {
+            let slot = {
+                // Slots are handed out in turn, so the limit holds across all workers
+                let mut next_op = self.next_op.lock();
+                let slot = (*next_op).max(Instant::now());
+                *next_op = slot + interval;
+                slot
+            };
+            let now = Instant::now();
+            if slot > now {
+                limited = true;
+                std::thread::sleep(slot - now);
+            }
+        }
+        
+        // Only report changes, most operations leave the state as it is
+        let waiting = u8::from(limited);
+        if self.waiting.swap(waiting, Ordering::Relaxed) != waiting {
+            progress.set_throttle(if limited {
+                ThrottleState::RateLimited
+            } else {
+                self.base_state()
+            });
+        }
+    }
+    
+    /// Why the system is too busy for a gentle scan, checked at most once per `SAMPLE_INTERVAL`
+    fn busy(&self) -> Option<BusyReason> {
+        let mut sample = self.sample.lock();
+        let now = Instant::now();
+        if now >= sample.0 {
+            *sample = (now + SAMPLE_INTERVAL, system_busy(self.disk));
+        }
+        sample.1
+    }
+}
+
+/// Split a `st_dev` into (major, minor) the way glibc encodes it
+fn split_device(dev: u64) -> (u64, u64) {
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0x0fff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0x00ff);
+    (major, minor)
+}
+
+/// First sign of the system being busy, from the kernel's pressure stall
+/// information, the load average and the request queue of `disk`
+#[cfg(target_os = "linux")]
+fn system_busy(disk: Option<(u64, u64)>) -> Option<BusyReason> {
+    if let Some(percent) = pressure("/proc/pressure/io").filter(|&p| p > IO_PRESSURE_LIMIT) {
+        return Some(BusyReason::IoPressure(percent));
+    }
+    if let Some(percent) = pressure("/proc/pressure/cpu").filter(|&p| p > CPU_PRESSURE_LIMIT) {
+        return Some(BusyReason::CpuPressure(percent));
+    }
+    
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get()) as f32;
+    let load = std::fs::read_to_string("/proc/loadavg")
+        .ok()
+        .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f32>().ok())
+        .map(|load| load / cpus);
+    if let Some(load) = load.filter(|&load| load > LOAD_LIMIT) {
+        return Some(BusyReason::LoadAverage(load));
+    }
+    
+    disk.and_then(disk_queue)
+        .filter(|&requests| requests > DISK_QUEUE_LIMIT)
+        .map(BusyReason::DiskQueue)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn system_busy(_disk: Option<(u64, u64)>) -> Option<BusyReason> {
+    None
+}
+
+/// The "some avg10" value of a pressure file, missing on kernels without PSI
+#[cfg(target_os = "linux")]
+fn pressure(path: &str) -> Option<f32> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let some = contents.lines().find(|line| line.starts_with("some "))?;
+    some.split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Requests in flight on a block device, missing for network and virtual filesystems
+#[cfg(target_os = "linux")]
+fn disk_queue((major, minor): (u64, u64)) -> Option<u64> {
+    let diskstats = std::fs::read_to_string("/proc/diskstats").ok()?;
+    diskstats.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 11 && fields[0].parse() == Ok(major) && fields[1].parse() == Ok(minor) {
+            fields[11].parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Lower the I/O and CPU priority of the calling thread
+#[cfg(target_os = "linux")]
+pub(crate) fn lower_thread_priority() {
+    // Lowest best-effort level; the idle class could stall a scan for good on a disk that is never idle
+    const IOPRIO_CLASS_BE: libc::c_long = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7;
+    
+    // Both calls take a thread id on Linux, 0 being the calling thread.
+    // Failures are ignored, the scan then just runs at normal priority.
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0 as libc::c_long, ioprio);
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn lower_thread_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn the_rate_limit_holds_across_workers() {
+        let throttle = Throttle::new(false, Some(200), None);
+        let progress = LiveProgress::new();
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        throttle.wait(&stop, &progress);
+                    }
+                });
+            }
+        });
+        
+        // The first operation starts right away, each of the other 39 gets its own 5 ms slot
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(39 * 5), "{:?}", elapsed);
+        assert_eq!(progress.snapshot().throttle, ThrottleState::RateLimited);
+        
+        // Operations slower than the limit do not wait, and the scan shows as running freely again
+        std::thread::sleep(Duration::from_millis(20));
+        throttle.wait(&stop, &progress);
+        assert_eq!(progress.snapshot().throttle, ThrottleState::FullSpeed);
+    }
+    
+    #[test]
+    fn without_a_limit_nothing_waits() {
+        let progress = LiveProgress::new();
+        let stop = AtomicBool::new(false);
+        for throttle in [Throttle::new(false, None, None), Throttle::new(false, Some(0), None)] {
+            let start = Instant::now();
+            for _ in 0..1000 {
+                throttle.wait(&stop, &progress);
+            }
+            assert!(start.elapsed() < Duration::from_millis(500));
+            assert_eq!(throttle.base_state(), ThrottleState::FullSpeed);
+        }
+        assert_eq!(progress.snapshot().throttle, ThrottleState::FullSpeed);
+        assert_eq!(Throttle::new(true, None, None).base_state(), ThrottleState::LowPriority);
+    }
+}
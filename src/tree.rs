@@ -0,0 +1,1467 @@
+use crate::scanner::{ScanError, ScanOptions};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Version of the `ScanResult` layout, bumped on incompatible changes
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Oldest layout that saved snapshots are still migrated from
+pub const OLDEST_FORMAT_VERSION: u32 = 3;
+
+/// Index of an entry in the `ScanResult` arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    /// The scan root is always the first entry
+    pub const ROOT: NodeId = NodeId(0);
+    
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Index of an interned string in a `NameTable`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NameId(pub u32);
+
+/// Interned file names and extensions.
+///
+/// Names like `index.js` or `.git` repeat across a tree, so each distinct
+/// string is stored once and entries refer to it by `NameId`.
+#[derive(Debug, Clone, Default)]
+pub struct NameTable {
+    names: Vec<Arc<str>>,
+    lookup: HashMap<Arc<str>, NameId>,
+}
+
+impl NameTable {
+    /// Intern a string, returning the id of an equal string if there is one
+    pub fn intern(&mut self, name: &str) -> NameId {
+        if self.lookup.len() < self.names.len() {
+            self.rebuild_lookup();
+        }
+        if let Some(&id) = self.lookup.get(name) {
+            return id;
+        }
+        let id = NameId(self.names.len() as u32);
+        let name: Arc<str> = Arc::from(name);
+        self.names.push(name.clone());
+        self.lookup.insert(name, id);
+        id
+    }
+    
+    pub fn get(&self, id: NameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+    
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    
+    /// Drop the lookup index once no more names are added; it is rebuilt on demand
+    pub fn release_lookup(&mut self) {
+        self.lookup = HashMap::new();
+    }
+    
+    fn rebuild_lookup(&mut self) {
+        self.lookup = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), NameId(i as u32)))
+            .collect();
+    }
+}
+
+impl Serialize for NameTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names.iter().map(|name| &**name))
+    }
+}
+
+impl<'de> Deserialize<'de> for NameTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names: Vec<String> = Vec::deserialize(deserializer)?;
+        Ok(Self {
+            names: names.into_iter().map(Arc::from).collect(),
+            lookup: HashMap::new(),
+        })
+    }
+}
+
+/// Represents a file or directory in the scan result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: NameId,
+    /// Lowercase extension, interned in the same table as names
+    pub extension: Option<NameId>,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    /// Apparent size in bytes, like `du --apparent-size`
+    pub size: u64,
+    /// Bytes allocated on disk, like `du`
+    pub allocated_size: u64,
+    /// Bytes allocated for the entry itself, without children
+    pub own_allocated: u64,
+    pub kind: FileKind,
+    /// Where a symbolic link points, only set for `FileKind::Symlink`
+    pub link_target: Option<Box<LinkTarget>>,
+    /// Last modification, `None` where the platform does not report it
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub modified: Option<DateTime<Utc>>,
+    /// Last access, only as fresh as the mount's atime policy (often `relatime`)
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub accessed: Option<DateTime<Utc>>,
+    /// Last inode change, the Unix `ctime`
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub changed: Option<DateTime<Utc>>,
+    /// Birth time, from `statx` on Linux where the filesystem records it
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub created: Option<DateTime<Utc>>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// File type and permission bits of `st_mode`
+    pub mode: Option<u32>,
+    /// Number of hard links to the entry's inode
+    pub nlink: u64,
+    /// File whose inode has other hard links, its size is counted once per inode
+    pub shared: bool,
+    /// Reached through a followed symbolic link, either the link itself or
+    /// something below it. Its files are counted once per inode, so nothing
+    /// is counted both at the link and at the real location.
+    pub via_link: bool,
+    /// (device, inode) pair, where the platform provides one
+    pub inode: Option<(u64, u64)>,
+    /// Set on placeholder entries that were deliberately not scanned
+    pub excluded: Option<ExcludeReason>,
+    /// The directory could not be listed completely
+    pub read_error: bool,
+    /// The scan was stopped before the directory was listed completely
+    pub unfinished: bool,
+    /// The directory or something below it could not be read, so its total is too low
+    pub incomplete: bool,
+    /// The directory is still being scanned, only set in snapshots of a running scan
+    #[serde(skip)]
+    pub scanning: bool,
+    /// Contents of a directory at the depth limit, folded into one record
+    /// instead of being kept as children
+    pub summary: Option<Box<SubtreeSummary>>,
+    /// Virtual entry listed from inside an archive, see `ScanOptions::expand_archives`.
+    /// Its allocated size is the space it takes up in the archive. Virtual
+    /// entries are not counted anywhere above the archive, which keeps its own size.
+    #[serde(default)]
+    pub in_archive: bool,
+}
+
+/// Aggregate of everything below a directory whose contents were not kept,
+/// see `ScanOptions::max_depth`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubtreeSummary {
+    /// Apparent size of all files below the directory
+    pub size: u64,
+    /// Bytes allocated for everything below the directory
+    pub allocated_size: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    /// Most recent modification of anything below the directory
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub newest_modified: Option<DateTime<Utc>>,
+    /// Number of files per lowercase extension, files without one are counted under ""
+    pub extensions: HashMap<String, u64>,
+}
+
+impl SubtreeSummary {
+    /// Fold another summary into this one
+    pub fn merge(&mut self, other: SubtreeSummary) {
+        self.size += other.size;
+        self.allocated_size += other.allocated_size;
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+        self.newest_modified = self.newest_modified.max(other.newest_modified);
+        for (extension, count) in other.extensions {
+            *self.extensions.entry(extension).or_default() += count;
+        }
+    }
+    
+    /// Extensions sorted by file count, most common first
+    pub fn top_extensions(&self, count: usize) -> Vec<(&str, u64)> {
+        let mut extensions: Vec<(&str, u64)> = self
+            .extensions
+            .iter()
+            .map(|(extension, &files)| (extension.as_str(), files))
+            .collect();
+        extensions.sort_by_key(|&(extension, files)| (std::cmp::Reverse(files), extension));
+        extensions.truncate(count);
+        extensions
+    }
+}
+
+/// Type of a filesystem entry, as reported by `lstat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    /// Named pipe
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl FileKind {
+    pub const ALL: [FileKind; 7] = [
+        FileKind::File,
+        FileKind::Directory,
+        FileKind::Symlink,
+        FileKind::Fifo,
+        FileKind::Socket,
+        FileKind::BlockDevice,
+        FileKind::CharDevice,
+    ];
+    
+    /// Kind of the entry described by `lstat`-style metadata
+    pub fn from_file_type(file_type: std::fs::FileType) -> Self {
+        if file_type.is_dir() {
+            return FileKind::Directory;
+        }
+        if file_type.is_symlink() {
+            return FileKind::Symlink;
+        }
+        
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_fifo() {
+                return FileKind::Fifo;
+            }
+            if file_type.is_socket() {
+                return FileKind::Socket;
+            }
+            if file_type.is_block_device() {
+                return FileKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return FileKind::CharDevice;
+            }
+        }
+        
+        FileKind::File
+    }
+    
+    /// Lowercase name used in exports
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::Fifo => "fifo",
+            FileKind::Socket => "socket",
+            FileKind::BlockDevice => "block_device",
+            FileKind::CharDevice => "char_device",
+        }
+    }
+}
+
+impl std::fmt::Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileKind::File => write!(f, "file"),
+            FileKind::Directory => write!(f, "directory"),
+            FileKind::Symlink => write!(f, "symbolic link"),
+            FileKind::Fifo => write!(f, "named pipe"),
+            FileKind::Socket => write!(f, "socket"),
+            FileKind::BlockDevice => write!(f, "block device"),
+            FileKind::CharDevice => write!(f, "character device"),
+        }
+    }
+}
+
+/// Target of a symbolic link
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkTarget {
+    /// The link's contents, relative targets are kept relative
+    pub path: PathBuf,
+    /// Nothing exists at the target
+    pub broken: bool,
+    /// The target is a directory above the link, so it was not followed
+    pub cycle: bool,
+}
+
+/// Why an entry was kept as a placeholder instead of being scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExcludeReason {
+    /// Mount point of another filesystem, skipped by `ScanOptions::one_file_system`
+    OtherFilesystem,
+    /// Matched an exclude rule of `ScanOptions::rules`
+    Pattern,
+}
+
+/// Which size of an entry to display and sort by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeMode {
+    /// Logical file length
+    #[default]
+    Apparent,
+    /// Space actually allocated on disk
+    Allocated,
+}
+
+impl FileEntry {
+    pub fn is_directory(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+    
+    /// Symbolic link whose target does not exist
+    pub fn is_broken_link(&self) -> bool {
+        self.link_target.as_ref().is_some_and(|target| target.broken)
+    }
+    
+    /// Device id of the filesystem holding the entry
+    pub fn device(&self) -> Option<u64> {
+        self.inode.map(|(dev, _)| dev)
+    }
+    
+    /// Size of the entry in the given mode
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Allocated => self.allocated_size,
+        }
+    }
+    
+    /// Whether the entry has (or may have) children of its own
+    pub fn is_container(&self) -> bool {
+        self.is_directory() || !self.children.is_empty()
+    }
+    
+    /// Whether the entry's size is the sum of its children: a directory or
+    /// a followed symlink to one, but not an archive with its members listed
+    pub fn sums_children(&self) -> bool {
+        self.is_container() && self.kind != FileKind::File
+    }
+    
+    /// Whether the entry's size is added once per inode instead of to its parent directly.
+    ///
+    /// That is the case for hard-linked files, files reached through followed
+    /// symlinks, and the real files those links lead to (`linked_inodes`).
+    fn counted_per_inode(&self, linked_inodes: &HashSet<(u64, u64)>) -> bool {
+        match self.inode {
+            Some(inode) if !self.is_container() => {
+                self.shared || self.via_link || linked_inodes.contains(&inode)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Entries affected by a change to the tree, so caches can be updated
+/// without going over the whole tree
+#[derive(Debug, Clone, Default)]
+pub struct SubtreeChange {
+    /// Entries that were detached, or updated and listed in `added` again
+    pub removed: Vec<NodeId>,
+    /// Entries that were added or updated
+    pub added: Vec<NodeId>,
+}
+
+impl SubtreeChange {
+    /// Add the entries of a later change
+    pub fn merge(&mut self, other: SubtreeChange) {
+        self.removed.extend(other.removed);
+        self.added.extend(other.added);
+    }
+}
+
+/// Size and entry counts of one root of a scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootTotals {
+    pub path: PathBuf,
+    pub size: u64,
+    pub allocated_size: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+}
+
+/// Sizes and entry counts a node adds to its ancestors
+#[derive(Debug, Clone, Copy, Default)]
+struct Footprint {
+    size: u64,
+    allocated: u64,
+    files: u64,
+    dirs: u64,
+}
+
+/// Result of a file system scan.
+///
+/// Entries live in one arena indexed by `NodeId`, with the root first and
+/// every child stored after its parent. Full paths are not stored, they are
+/// rebuilt from the names on demand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanResult {
+    pub format_version: u32,
+    /// Scanned folder, empty for a scan of several roots
+    pub root_path: PathBuf,
+    /// Folders of a scan of several roots. Each is a child of a synthetic top
+    /// entry and named after its full path. Empty for a scan of one folder.
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    pub total_size: u64,
+    pub total_allocated: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    /// Entries left out by include/exclude rules
+    pub excluded_count: u64,
+    /// Apparent size of the excluded files; excluded directories are not measured
+    pub excluded_size: u64,
+    pub entries: Vec<FileEntry>,
+    pub names: NameTable,
+    /// User names of the uids found in the scan, resolved once per id
+    pub user_names: HashMap<u32, String>,
+    /// Group names of the gids found in the scan, resolved once per id
+    pub group_names: HashMap<u32, String>,
+    /// Everything that could not be read, in no particular order
+    pub errors: Vec<ScanError>,
+    /// The scan was cancelled, totals only cover what was visited
+    pub partial: bool,
+    pub scan_duration: std::time::Duration,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub scan_time: DateTime<Utc>,
+    /// Options the scan was run with
+    #[serde(default)]
+    pub options: ScanOptions,
+    /// Name of the machine that was scanned, empty when unknown
+    #[serde(default)]
+    pub hostname: String,
+    /// Bumped on every change made through the methods below, see `generation`
+    #[serde(skip)]
+    generation: u64,
+    /// Entries in the tree reached through a followed symlink, counted on
+    /// first use and kept up to date by the methods changing the tree
+    #[serde(skip)]
+    linked_entries: Option<usize>,
+}
+
+impl ScanResult {
+    /// Empty result for `root_path`, without even a root entry
+    pub fn new(root_path: PathBuf) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            root_path,
+            roots: Vec::new(),
+            total_size: 0,
+            total_allocated: 0,
+            file_count: 0,
+            dir_count: 0,
+            excluded_count: 0,
+            excluded_size: 0,
+            entries: Vec::new(),
+            names: NameTable::default(),
+            user_names: HashMap::new(),
+            group_names: HashMap::new(),
+            errors: Vec::new(),
+            partial: false,
+            scan_duration: std::time::Duration::ZERO,
+            scan_time: Utc::now(),
+            options: ScanOptions::default(),
+            hostname: String::new(),
+            generation: 0,
+            linked_entries: None,
+        }
+    }
+    
+    /// Total size of the scan in the given mode
+    pub fn total_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.total_size,
+            SizeMode::Allocated => self.total_allocated,
+        }
+    }
+    
+    /// Totals of every root, in scan order. A file hard-linked into several
+    /// roots counts in each of them, but only once in the scan's totals.
+    pub fn root_totals(&self) -> Vec<RootTotals> {
+        if self.roots.is_empty() {
+            return self
+                .root()
+                .map(|_| RootTotals {
+                    path: self.root_path.clone(),
+                    size: self.total_size,
+                    allocated_size: self.total_allocated,
+                    file_count: self.file_count,
+                    dir_count: self.dir_count,
+                })
+                .into_iter()
+                .collect();
+        }
+        
+        let tops = self.children(NodeId::ROOT);
+        let mut totals: Vec<RootTotals> = tops
+            .iter()
+            .map(|&id| RootTotals {
+                path: self.path_of(id),
+                size: self.entry(id).size,
+                allocated_size: self.entry(id).allocated_size,
+                file_count: 0,
+                dir_count: 0,
+            })
+            .collect();
+        
+        // Children come after their parents, so one forward pass finds the root of every entry
+        let mut root_of: Vec<Option<usize>> = vec![None; self.entries.len()];
+        for (position, &id) in tops.iter().enumerate() {
+            root_of[id.index()] = Some(position);
+        }
+        for (id, entry) in self.iter().skip(1) {
+            let Some(parent) = entry.parent else {
+                continue;
+            };
+            if parent != NodeId::ROOT {
+                root_of[id.index()] = root_of[parent.index()];
+            }
+            let Some(root) = root_of[id.index()] else {
+                continue;
+            };
+            if entry.excluded.is_some() || entry.in_archive {
+                continue;
+            }
+            let totals = &mut totals[root];
+            if entry.is_directory() {
+                totals.dir_count += 1;
+            } else {
+                totals.file_count += 1;
+            }
+            if let Some(summary) = &entry.summary {
+                totals.file_count += summary.file_count;
+                totals.dir_count += summary.dir_count;
+            }
+        }
+        totals
+    }
+    
+    /// Changes whenever an entry is added, changed or removed, so a view
+    /// built from the tree can tell that it is out of date
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    
+    pub fn root(&self) -> Option<&FileEntry> {
+        self.entries.first()
+    }
+    
+    pub fn entry(&self, id: NodeId) -> &FileEntry {
+        &self.entries[id.index()]
+    }
+    
+    pub fn entry_mut(&mut self, id: NodeId) -> &mut FileEntry {
+        self.generation += 1;
+        &mut self.entries[id.index()]
+    }
+    
+    /// All entries with their ids, in arena order, skipping detached ones
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &FileEntry)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (NodeId(i as u32), entry))
+            .filter(|&(id, _)| !self.is_detached(id))
+    }
+    
+    /// Whether an entry was cut out of the tree by a `graft` and only
+    /// remains in the arena so that other ids stay valid
+    pub fn is_detached(&self, id: NodeId) -> bool {
+        id != NodeId::ROOT && self.entry(id).parent.is_none()
+    }
+    
+    pub fn name(&self, id: NodeId) -> &str {
+        self.names.get(self.entry(id).name)
+    }
+    
+    pub fn extension(&self, id: NodeId) -> Option<&str> {
+        self.entry(id).extension.map(|ext| self.names.get(ext))
+    }
+    
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.entry(id).children
+    }
+    
+    /// Name of the user owning an entry, if it could be resolved
+    pub fn user_name(&self, id: NodeId) -> Option<&str> {
+        let uid = self.entry(id).uid?;
+        self.user_names.get(&uid).map(String::as_str)
+    }
+    
+    /// Name of the group owning an entry, if it could be resolved
+    pub fn group_name(&self, id: NodeId) -> Option<&str> {
+        let gid = self.entry(id).gid?;
+        self.group_names.get(&gid).map(String::as_str)
+    }
+    
+    /// Rebuild the full path of an entry from the names of its ancestors
+    pub fn path_of(&self, id: NodeId) -> PathBuf {
+        let mut chain = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.entry(current).parent {
+            chain.push(current);
+            current = parent;
+        }
+        
+        let mut path = self.root_path.clone();
+        for &node in chain.iter().rev() {
+            path.push(self.name(node));
+        }
+        path
+    }
+    
+    /// Find the entry for a path below the root
+    pub fn find(&self, path: &Path) -> Option<NodeId> {
+        self.root()?;
+        let (mut current, relative) = if self.roots.is_empty() {
+            (NodeId::ROOT, path.strip_prefix(&self.root_path).ok()?)
+        } else if path == self.root_path {
+            return Some(NodeId::ROOT);
+        } else {
+            // The roots below the synthetic top entry are named after their full path
+            self.children(NodeId::ROOT)
+                .iter()
+                .find_map(|&child| Some((child, path.strip_prefix(self.name(child)).ok()?)))?
+        };
+        for component in relative.components() {
+            let name = component.as_os_str().to_string_lossy();
+            current = *self
+                .children(current)
+                .iter()
+                .find(|&&child| self.name(child) == name)?;
+        }
+        Some(current)
+    }
+    
+    /// Visit every entry depth-first together with its full path
+    pub fn for_each_path(&self, mut visit: impl FnMut(NodeId, &Path)) {
+        if self.entries.is_empty() {
+            return;
+        }
+        
+        let mut path = self.root_path.clone();
+        visit(NodeId::ROOT, &path);
+        
+        // (children of a directory, position of the next child to visit)
+        let mut stack: Vec<(&[NodeId], usize)> = vec![(self.children(NodeId::ROOT), 0)];
+        while let Some((children, next)) = stack.last_mut() {
+            if *next == children.len() {
+                stack.pop();
+                path.pop();
+                continue;
+            }
+            let child = children[*next];
+            *next += 1;
+            
+            path.push(self.name(child));
+            visit(child, &path);
+            if self.entry(child).children.is_empty() {
+                path.pop();
+            } else {
+                stack.push((self.children(child), 0));
+            }
+        }
+    }
+    
+    /// Add the root entry, named after the last component of `root_path`
+    pub fn push_root(&mut self, entry: FileEntry) -> NodeId {
+        let name = self
+            .root_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.root_path.to_string_lossy().to_string());
+        self.push_entry(None, &name, entry)
+    }
+    
+    /// Append an entry under `parent`, interning its name and extension.
+    ///
+    /// Names that are not valid UTF-8 are stored lossily.
+    pub fn push_child(&mut self, parent: NodeId, name: &str, entry: FileEntry) -> NodeId {
+        let id = self.push_entry(Some(parent), name, entry);
+        self.entries[parent.index()].children.push(id);
+        id
+    }
+    
+    /// Put a fresh scan of the directory `at` in place of its summary or its
+    /// previous contents.
+    ///
+    /// The old descendants are detached and the subtree's entries appended, so
+    /// children still come after their parents. The size difference is applied
+    /// to every ancestor of `at`, the whole tree is only recomputed when hard
+    /// links or followed symlinks could make it count differently.
+    pub fn graft(&mut self, at: NodeId, subtree: ScanResult) -> SubtreeChange {
+        let ScanResult {
+            root_path,
+            entries,
+            names,
+            user_names,
+            group_names,
+            errors,
+            ..
+        } = subtree;
+        if entries.is_empty() {
+            return SubtreeChange::default();
+        }
+        
+        let removed = self.detach_descendants(at);
+        let old = self.footprint(at, &removed);
+        let removed_linked = self.linked_in(removed.iter().chain([&at]));
+        
+        // The subtree root becomes `at`, every other entry moves to the end of the arena
+        let offset = self.entries.len() as u32 - 1;
+        let remap = |id: NodeId| if id == NodeId::ROOT { at } else { NodeId(id.0 + offset) };
+        
+        let added: Vec<NodeId> = (1..entries.len() as u32).map(|i| NodeId(i + offset)).collect();
+        for (i, mut entry) in entries.into_iter().enumerate() {
+            entry.children = entry.children.iter().map(|&child| remap(child)).collect();
+            if i == 0 {
+                let old = &self.entries[at.index()];
+                entry.name = old.name;
+                entry.extension = old.extension;
+                entry.parent = old.parent;
+                self.entries[at.index()] = entry;
+            } else {
+                entry.name = self.names.intern(names.get(entry.name));
+                entry.extension = entry.extension.map(|ext| self.names.intern(names.get(ext)));
+                entry.parent = entry.parent.map(remap);
+                self.entries.push(entry);
+            }
+        }
+        self.names.release_lookup();
+        let added_linked = self.linked_in(added.iter().chain([&at]));
+        self.count_linked(removed_linked, added_linked);
+        
+        self.user_names.extend(user_names);
+        self.group_names.extend(group_names);
+        self.errors.retain(|error| !error.path.starts_with(&root_path));
+        self.errors.extend(errors);
+        // Exclusions below `at` were already counted by the original scan and
+        // are not tracked per directory, so the totals are left as they are
+        
+        let new = self.footprint(at, &added);
+        let change = SubtreeChange { removed, added };
+        self.propagate(self.entry(at).parent, old, new, &change);
+        change
+    }
+    
+    /// Add a new entry under `parent` and count it in every ancestor.
+    ///
+    /// Directories are added empty, a scan of them can be grafted afterwards.
+    pub fn insert(&mut self, parent: NodeId, name: &str, entry: FileEntry) -> SubtreeChange {
+        let id = self.push_child(parent, name, entry);
+        self.names.release_lookup();
+        let new = self.footprint(id, &[]);
+        let change = SubtreeChange { removed: Vec::new(), added: vec![id] };
+        self.propagate(Some(parent), Footprint::default(), new, &change);
+        change
+    }
+    
+    /// Cut an entry and everything below it out of the tree
+    pub fn remove(&mut self, id: NodeId) -> SubtreeChange {
+        let Some(parent) = self.entry(id).parent else {
+            return SubtreeChange::default();
+        };
+        let mut removed = self.detach_descendants(id);
+        let old = self.footprint(id, &removed);
+        removed.push(id);
+        self.entry_mut(parent).children.retain(|&child| child != id);
+        self.entry_mut(id).parent = None;
+        self.count_linked(self.linked_in(&removed), 0);
+        
+        let change = SubtreeChange { removed, added: Vec::new() };
+        self.propagate(Some(parent), old, Footprint::default(), &change);
+        change
+    }
+    
+    /// Replace the metadata of an entry with a fresh stat of the same path.
+    ///
+    /// Directories keep their contents and only take over their own fields.
+    /// An expanded archive that changed drops its members, which may be stale.
+    pub fn restat(&mut self, id: NodeId, fresh: FileEntry) -> SubtreeChange {
+        let old = self.footprint(id, &[]);
+        let entry = self.entry(id);
+        let stale = if !entry.sums_children()
+            && (entry.size != fresh.size || entry.modified != fresh.modified || entry.kind != fresh.kind)
+        {
+            self.detach_descendants(id)
+        } else {
+            Vec::new()
+        };
+        let entry = self.entry_mut(id);
+        if entry.sums_children() {
+            entry.allocated_size = entry.allocated_size - entry.own_allocated + fresh.own_allocated;
+            entry.own_allocated = fresh.own_allocated;
+        } else {
+            entry.size = fresh.size;
+            entry.allocated_size = fresh.allocated_size;
+            entry.own_allocated = fresh.own_allocated;
+            entry.kind = fresh.kind;
+            entry.link_target = fresh.link_target;
+            entry.nlink = fresh.nlink;
+            entry.shared = fresh.shared;
+            entry.inode = fresh.inode;
+        }
+        entry.modified = fresh.modified;
+        entry.accessed = fresh.accessed;
+        entry.changed = fresh.changed;
+        entry.created = fresh.created;
+        entry.uid = fresh.uid;
+        entry.gid = fresh.gid;
+        entry.mode = fresh.mode;
+        
+        let new = self.footprint(id, &[]);
+        let mut removed = vec![id];
+        removed.extend(stale);
+        let change = SubtreeChange { removed, added: vec![id] };
+        self.propagate(self.entry(id).parent, old, new, &change);
+        change
+    }
+    
+    /// Cut an entry and everything below it out of the tree without updating
+    /// any sizes, for trees whose totals are recalculated afterwards
+    pub(crate) fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.entry(id).parent {
+            self.entry_mut(parent).children.retain(|&child| child != id);
+        }
+        let removed = self.detach_descendants(id);
+        self.count_linked(self.linked_in(removed.iter().chain([&id])), 0);
+        self.entry_mut(id).parent = None;
+    }
+    
+    /// Detach every entry below `at`, returning their ids
+    fn detach_descendants(&mut self, at: NodeId) -> Vec<NodeId> {
+        let mut removed = Vec::new();
+        let mut stack = std::mem::take(&mut self.entry_mut(at).children);
+        while let Some(id) = stack.pop() {
+            let entry = self.entry_mut(id);
+            entry.parent = None;
+            stack.append(&mut entry.children);
+            removed.push(id);
+        }
+        removed
+    }
+    
+    /// How many of `ids` were reached through a followed symlink
+    fn linked_in<'a>(&self, ids: impl IntoIterator<Item = &'a NodeId>) -> usize {
+        ids.into_iter().filter(|&&id| self.entry(id).via_link).count()
+    }
+    
+    /// Update the count of linked entries, if it was taken already
+    fn count_linked(&mut self, removed: usize, added: usize) {
+        if let Some(count) = &mut self.linked_entries {
+            *count = *count + added - removed;
+        }
+    }
+    
+    /// Entries in the tree reached through a followed symlink
+    fn linked_entries(&mut self) -> usize {
+        if let Some(count) = self.linked_entries {
+            return count;
+        }
+        let count = self.iter().filter(|(_, entry)| entry.via_link).count();
+        self.linked_entries = Some(count);
+        count
+    }
+    
+    /// What `top` adds to its ancestors, counting `top` and `descendants`
+    fn footprint(&self, top: NodeId, descendants: &[NodeId]) -> Footprint {
+        let mut footprint = Footprint::default();
+        let entry = self.entry(top);
+        if entry.excluded.is_none() {
+            footprint.size = entry.size;
+            footprint.allocated = entry.allocated_size;
+        }
+        let ids = std::iter::once(top).chain(descendants.iter().copied());
+        for entry in ids.map(|id| self.entry(id)).filter(|e| e.excluded.is_none() && !e.in_archive) {
+            if entry.is_directory() {
+                footprint.dirs += 1;
+            } else {
+                footprint.files += 1;
+            }
+            if let Some(summary) = &entry.summary {
+                footprint.files += summary.file_count;
+                footprint.dirs += summary.dir_count;
+            }
+        }
+        footprint
+    }
+    
+    /// Apply the difference between what a changed node used to add to its
+    /// ancestors and what it adds now, from its parent `from` up to the root totals.
+    ///
+    /// Hard links and followed symlinks are counted once per inode across
+    /// directories, so any change involving them recomputes the whole tree.
+    fn propagate(&mut self, from: Option<NodeId>, old: Footprint, new: Footprint, change: &SubtreeChange) {
+        self.generation += 1;
+        let linked = self.linked_entries() > 0;
+        let per_inode = |id: &NodeId| {
+            let entry = self.entry(*id);
+            entry.shared || entry.via_link
+        };
+        if linked || change.removed.iter().any(per_inode) || change.added.iter().any(per_inode) {
+            self.recalculate_totals();
+            return;
+        }
+        
+        let mut parent = from;
+        while let Some(id) = parent {
+            let incomplete = self.entry(id).read_error
+                || self.entry(id).unfinished
+                || self.children(id).iter().any(|&child| self.entry(child).incomplete);
+            let entry = self.entry_mut(id);
+            entry.size = entry.size + new.size - old.size;
+            entry.allocated_size = entry.allocated_size + new.allocated - old.allocated;
+            entry.incomplete = incomplete;
+            parent = entry.parent;
+        }
+        
+        self.file_count = self.file_count + new.files - old.files;
+        self.dir_count = self.dir_count + new.dirs - old.dirs;
+        let (total_size, total_allocated) = self
+            .root()
+            .map_or((0, 0), |root| (root.size, root.allocated_size));
+        self.total_size = total_size;
+        self.total_allocated = total_allocated;
+    }
+    
+    fn push_entry(&mut self, parent: Option<NodeId>, name: &str, mut entry: FileEntry) -> NodeId {
+        let id = NodeId(self.entries.len() as u32);
+        entry.name = self.names.intern(name);
+        entry.extension = if entry.is_directory() {
+            None
+        } else {
+            Path::new(name)
+                .extension()
+                .map(|ext| self.names.intern(&ext.to_string_lossy().to_lowercase()))
+        };
+        entry.parent = parent;
+        self.count_linked(0, usize::from(entry.via_link));
+        self.entries.push(entry);
+        self.generation += 1;
+        id
+    }
+    
+    /// Recompute directory sizes, incomplete flags and the totals from the leaves up
+    pub fn recalculate_totals(&mut self) {
+        self.generation += 1;
+        let linked_inodes: HashSet<(u64, u64)> = self
+            .iter()
+            .map(|(_, e)| e)
+            .filter(|e| e.via_link && !e.is_container())
+            .filter_map(|e| e.inode)
+            .collect();
+        
+        // Children are always stored after their parent, so a reverse pass
+        // sees every directory after all of its descendants
+        for i in (0..self.entries.len()).rev() {
+            if !self.entries[i].sums_children()
+                || self.entries[i].excluded.is_some()
+                || self.is_detached(NodeId(i as u32))
+            {
+                continue;
+            }
+            let mut dir_size = 0;
+            let mut dir_allocated = 0;
+            let mut incomplete = self.entries[i].read_error || self.entries[i].unfinished;
+            for &child in &self.entries[i].children {
+                let child = &self.entries[child.index()];
+                incomplete |= child.incomplete;
+                // Placeholders were not scanned, per-inode files are added below
+                if child.excluded.is_none() && !child.counted_per_inode(&linked_inodes) {
+                    dir_size += child.size;
+                    dir_allocated += child.allocated_size;
+                }
+            }
+            if let Some(summary) = &self.entries[i].summary {
+                dir_size += summary.size;
+                dir_allocated += summary.allocated_size;
+            }
+            let entry = &mut self.entries[i];
+            entry.size = dir_size;
+            entry.allocated_size = entry.own_allocated + dir_allocated;
+            entry.incomplete = incomplete;
+        }
+        
+        // Add each per-inode file once to every directory holding one of its links
+        let mut link_groups: HashMap<(u64, u64), Vec<NodeId>> = HashMap::new();
+        for (id, entry) in self.iter() {
+            if let (true, None, Some(inode)) = (entry.counted_per_inode(&linked_inodes), entry.excluded, entry.inode) {
+                link_groups.entry(inode).or_default().push(id);
+            }
+        }
+        
+        for links in link_groups.values() {
+            let size = self.entry(links[0]).size;
+            let allocated = self.entry(links[0]).allocated_size;
+            let mut ancestors = HashSet::new();
+            for &id in links {
+                let mut parent = self.entry(id).parent;
+                while let Some(parent_id) = parent {
+                    // Everything above an already visited directory is visited too
+                    if !ancestors.insert(parent_id) {
+                        break;
+                    }
+                    parent = self.entry(parent_id).parent;
+                }
+            }
+            for id in ancestors {
+                let entry = self.entry_mut(id);
+                entry.size += size;
+                entry.allocated_size += allocated;
+            }
+        }
+        
+        // The top entry of several roots is not a folder on disk
+        let synthetic_root = (!self.roots.is_empty()).then_some(NodeId::ROOT);
+        let (mut file_count, mut dir_count) = (0, 0);
+        for (_, entry) in self
+            .iter()
+            .filter(|&(id, e)| e.excluded.is_none() && !e.in_archive && Some(id) != synthetic_root)
+        {
+            if entry.is_directory() {
+                dir_count += 1;
+            } else {
+                file_count += 1;
+            }
+            if let Some(summary) = &entry.summary {
+                file_count += summary.file_count;
+                dir_count += summary.dir_count;
+            }
+        }
+        self.file_count = file_count;
+        self.dir_count = dir_count;
+        
+        let (total_size, total_allocated) = self
+            .root()
+            .map_or((0, 0), |root| (root.size, root.allocated_size));
+        self.total_size = total_size;
+        self.total_allocated = total_allocated;
+    }
+}
+
+/// Layouts of older snapshot formats, migrated to the current one on load.
+///
+/// Snapshots are bincode, which is not self-describing, so every older
+/// layout is spelled out in full. Scans from before the arena were saved
+/// as JSON of the path-based layout.
+pub(crate) mod legacy {
+    use super::*;
+    use crate::rules::PathRule;
+    use bincode::Options;
+    use std::io::Read;
+    
+    /// `ScanResult` of the path-based layout, before format 3. Totals are
+    /// recomputed, so only what the entries cannot tell is read.
+    #[derive(Deserialize)]
+    struct ScanResultV1 {
+        root_path: PathBuf,
+        #[serde(default)]
+        excluded_count: u64,
+        #[serde(default)]
+        excluded_size: u64,
+        entries: Vec<FileEntryV1>,
+        #[serde(default)]
+        errors: Vec<ScanError>,
+        #[serde(default)]
+        partial: bool,
+        scan_duration: std::time::Duration,
+        #[serde(with = "chrono::serde::ts_seconds")]
+        scan_time: DateTime<Utc>,
+    }
+    
+    /// `FileEntry` of the path-based layout, where every entry stored its full
+    /// path and the path of its parent. Fields added while that layout was in
+    /// use are missing from the earliest scans.
+    #[derive(Deserialize)]
+    struct FileEntryV1 {
+        path: PathBuf,
+        size: u64,
+        #[serde(default)]
+        allocated_size: Option<u64>,
+        is_directory: bool,
+        #[serde(with = "chrono::serde::ts_seconds")]
+        modified: DateTime<Utc>,
+        parent: Option<PathBuf>,
+        #[serde(default)]
+        nlink: Option<u64>,
+        #[serde(default)]
+        shared: bool,
+        #[serde(default)]
+        inode: Option<(u64, u64)>,
+        #[serde(default)]
+        excluded: Option<ExcludeReason>,
+        #[serde(default)]
+        read_error: bool,
+        #[serde(default)]
+        incomplete: bool,
+    }
+    
+    /// `ScanResult` of formats 3 and 4, before scans of several roots
+    #[derive(Deserialize)]
+    struct ScanResultV4<E, O> {
+        /// Repeats the version in the snapshot header
+        _format_version: u32,
+        root_path: PathBuf,
+        total_size: u64,
+        total_allocated: u64,
+        file_count: u64,
+        dir_count: u64,
+        excluded_count: u64,
+        excluded_size: u64,
+        entries: Vec<E>,
+        names: NameTable,
+        user_names: HashMap<u32, String>,
+        group_names: HashMap<u32, String>,
+        errors: Vec<ScanError>,
+        partial: bool,
+        scan_duration: std::time::Duration,
+        #[serde(with = "chrono::serde::ts_seconds")]
+        scan_time: DateTime<Utc>,
+        options: O,
+        hostname: String,
+    }
+    
+    /// `FileEntry` of format 3, before archive members
+    #[derive(Deserialize)]
+    struct FileEntryV3 {
+        name: NameId,
+        extension: Option<NameId>,
+        parent: Option<NodeId>,
+        children: Vec<NodeId>,
+        size: u64,
+        allocated_size: u64,
+        own_allocated: u64,
+        kind: FileKind,
+        link_target: Option<Box<LinkTarget>>,
+        #[serde(with = "chrono::serde::ts_seconds_option")]
+        modified: Option<DateTime<Utc>>,
+        #[serde(with = "chrono::serde::ts_seconds_option")]
+        accessed: Option<DateTime<Utc>>,
+        #[serde(with = "chrono::serde::ts_seconds_option")]
+        changed: Option<DateTime<Utc>>,
+        #[serde(with = "chrono::serde::ts_seconds_option")]
+        created: Option<DateTime<Utc>>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        mode: Option<u32>,
+        nlink: u64,
+        shared: bool,
+        via_link: bool,
+        inode: Option<(u64, u64)>,
+        excluded: Option<ExcludeReason>,
+        read_error: bool,
+        unfinished: bool,
+        incomplete: bool,
+        summary: Option<Box<SubtreeSummary>>,
+    }
+    
+    /// `ScanOptions` of format 3, before archive listing
+    #[derive(Deserialize)]
+    struct ScanOptionsV3 {
+        threads: usize,
+        one_file_system: bool,
+        rules: Vec<PathRule>,
+        follow_symlinks: bool,
+        max_depth: Option<usize>,
+    }
+    
+    impl From<FileEntryV3> for FileEntry {
+        fn from(old: FileEntryV3) -> Self {
+            FileEntry {
+                name: old.name,
+                extension: old.extension,
+                parent: old.parent,
+                children: old.children,
+                size: old.size,
+                allocated_size: old.allocated_size,
+                own_allocated: old.own_allocated,
+                kind: old.kind,
+                link_target: old.link_target,
+                modified: old.modified,
+                accessed: old.accessed,
+                changed: old.changed,
+                created: old.created,
+                uid: old.uid,
+                gid: old.gid,
+                mode: old.mode,
+                nlink: old.nlink,
+                shared: old.shared,
+                via_link: old.via_link,
+                inode: old.inode,
+                excluded: old.excluded,
+                read_error: old.read_error,
+                unfinished: old.unfinished,
+                incomplete: old.incomplete,
+                scanning: false,
+                summary: old.summary,
+                in_archive: false,
+            }
+        }
+    }
+    
+    impl From<ScanOptionsV3> for ScanOptions {
+        fn from(old: ScanOptionsV3) -> Self {
+            ScanOptions {
+                threads: old.threads,
+                one_file_system: old.one_file_system,
+                rules: old.rules,
+                follow_symlinks: old.follow_symlinks,
+                max_depth: old.max_depth,
+                ..ScanOptions::default()
+            }
+        }
+    }
+    
+    impl<E: Into<FileEntry>, O: Into<ScanOptions>> From<ScanResultV4<E, O>> for ScanResult {
+        fn from(old: ScanResultV4<E, O>) -> Self {
+            ScanResult {
+                format_version: FORMAT_VERSION,
+                root_path: old.root_path,
+                roots: Vec::new(),
+                total_size: old.total_size,
+                total_allocated: old.total_allocated,
+                file_count: old.file_count,
+                dir_count: old.dir_count,
+                excluded_count: old.excluded_count,
+                excluded_size: old.excluded_size,
+                entries: old.entries.into_iter().map(Into::into).collect(),
+                names: old.names,
+                user_names: old.user_names,
+                group_names: old.group_names,
+                errors: old.errors,
+                partial: old.partial,
+                scan_duration: old.scan_duration,
+                scan_time: old.scan_time,
+                options: old.options.into(),
+                hostname: old.hostname,
+                generation: 0,
+                linked_entries: None,
+            }
+        }
+    }
+    
+    impl From<ScanResultV1> for ScanResult {
+        fn from(old: ScanResultV1) -> Self {
+            let mut result = ScanResult::new(old.root_path.clone());
+            result.excluded_count = old.excluded_count;
+            result.excluded_size = old.excluded_size;
+            result.errors = old.errors;
+            result.partial = old.partial;
+            result.scan_duration = old.scan_duration;
+            result.scan_time = old.scan_time;
+            
+            // Parents sort before their children
+            let mut entries = old.entries;
+            entries.sort_by_key(|entry| entry.path.components().count());
+            
+            let mut ids: HashMap<PathBuf, NodeId> = HashMap::with_capacity(entries.len());
+            // Saved allocated size of every directory, which included its own blocks
+            let mut saved_allocated = Vec::new();
+            for entry in entries {
+                let parent = entry.parent.as_ref().and_then(|parent| ids.get(parent)).copied();
+                let is_root = entry.path == old.root_path;
+                let allocated = entry.allocated_size.unwrap_or(entry.size);
+                let new = FileEntry {
+                    name: NameId::default(),
+                    extension: None,
+                    parent: None,
+                    children: Vec::new(),
+                    size: entry.size,
+                    allocated_size: allocated,
+                    own_allocated: if entry.is_directory { 0 } else { allocated },
+                    kind: if entry.is_directory { FileKind::Directory } else { FileKind::File },
+                    link_target: None,
+                    modified: Some(entry.modified),
+                    accessed: None,
+                    changed: None,
+                    created: None,
+                    uid: None,
+                    gid: None,
+                    mode: None,
+                    nlink: entry.nlink.unwrap_or(1),
+                    shared: entry.shared,
+                    via_link: false,
+                    inode: entry.inode,
+                    excluded: entry.excluded,
+                    read_error: entry.read_error,
+                    // Only a cancelled scan leaves directories incomplete without an error below them
+                    unfinished: old.partial && entry.incomplete,
+                    incomplete: entry.incomplete,
+                    scanning: false,
+                    summary: None,
+                    in_archive: false,
+                };
+                
+                let id = match parent {
+                    _ if is_root && result.entries.is_empty() => result.push_root(new),
+                    Some(parent) if !is_root => {
+                        let name = entry
+                            .path
+                            .file_name()
+                            .map_or(String::new(), |name| name.to_string_lossy().to_string());
+                        result.push_child(parent, &name, new)
+                    }
+                    // Orphans cannot be placed in the tree
+                    _ => continue,
+                };
+                if let (true, Some(allocated)) = (entry.is_directory, entry.allocated_size) {
+                    saved_allocated.push((id, allocated));
+                }
+                ids.insert(entry.path, id);
+            }
+            result.recalculate_totals();
+            
+            // What a directory held beyond its recomputed contents are the
+            // own blocks of the directories in it, its own included
+            if !saved_allocated.is_empty() {
+                let beyond: HashMap<NodeId, u64> = saved_allocated
+                    .iter()
+                    .map(|&(id, allocated)| (id, allocated.saturating_sub(result.entry(id).allocated_size)))
+                    .collect();
+                for (&id, &own_and_below) in &beyond {
+                    let below: u64 = result.children(id).iter().filter_map(|child| beyond.get(child)).sum();
+                    result.entry_mut(id).own_allocated = own_and_below.saturating_sub(below);
+                }
+                result.recalculate_totals();
+            }
+            result.names.release_lookup();
+            result
+        }
+    }
+    
+    /// Read a scan saved as JSON of the path-based layout
+    pub(crate) fn decode_json<R: Read>(reader: R) -> serde_json::Result<ScanResult> {
+        serde_json::from_reader::<_, ScanResultV1>(reader).map(ScanResult::from)
+    }
+    
+    /// Decode the uncompressed body of a snapshot in format `version`,
+    /// `None` when there is no migration from it
+    pub(crate) fn decode(version: u32, body: &[u8]) -> Option<Result<ScanResult, bincode::Error>> {
+        let encoding = crate::snapshot::encoding();
+        let result = match version {
+            3 => encoding.deserialize::<ScanResultV4<FileEntryV3, ScanOptionsV3>>(body).map(ScanResult::from),
+            4 => encoding.deserialize::<ScanResultV4<FileEntry, ScanOptions>>(body).map(ScanResult::from),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::ScanOptions;
+    use crate::source::testing::scan;
+    use crate::source::MemoryTree;
+    
+    #[test]
+    fn every_change_moves_the_generation_on() {
+        let mut tree = MemoryTree::new("/data");
+        let docs = tree.dir(MemoryTree::ROOT, "docs");
+        let notes = tree.file(docs, "notes.txt", 100);
+        tree.file(MemoryTree::ROOT, "top.txt", 4);
+        let mut result = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        let notes_id = result.find(Path::new("/data/docs/notes.txt")).unwrap();
+        let docs_id = result.find(Path::new("/data/docs")).unwrap();
+        
+        let mut seen = vec![result.generation()];
+        let mut changed = |result: &ScanResult| {
+            assert!(!seen.contains(&result.generation()));
+            seen.push(result.generation());
+        };
+        
+        // Same number of entries and the same scan time, only the generation tells
+        tree.stat_mut(notes).len = 5000;
+        let restatted = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        let fresh = restatted.entry(restatted.find(Path::new("/data/docs/notes.txt")).unwrap()).clone();
+        let entries = result.entries.len();
+        result.restat(notes_id, fresh.clone());
+        assert_eq!(result.entries.len(), entries);
+        changed(&result);
+        
+        result.insert(docs_id, "more.txt", fresh);
+        changed(&result);
+        let top = result.find(Path::new("/data/top.txt")).unwrap();
+        result.remove(top);
+        changed(&result);
+        result.recalculate_totals();
+        changed(&result);
+        
+        let generation = result.generation();
+        let _ = (result.find(Path::new("/data/docs")), result.root_totals(), result.path_of(docs_id));
+        assert_eq!(result.generation(), generation);
+    }
+    
+    #[test]
+    fn a_changed_archive_drops_its_members() {
+        let mut tree = MemoryTree::new("/data");
+        let pack = tree.file(MemoryTree::ROOT, "pack.zip", 300);
+        let mut result = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        let pack_id = result.find(Path::new("/data/pack.zip")).unwrap();
+        let member = |path: &str| crate::archive::ArchiveMember {
+            path: PathBuf::from(path),
+            kind: FileKind::File,
+            size: 1000,
+            compressed_size: 100,
+            modified: None,
+            mode: None,
+        };
+        let members = crate::archive::attach(&mut result, pack_id, vec![member("a.txt"), member("sub/b.txt")]);
+        let totals = (result.total_size, result.file_count);
+        
+        // The same archive, stat again
+        let unchanged = result.entry(pack_id).clone();
+        result.restat(pack_id, unchanged);
+        assert_eq!(result.children(pack_id).len(), 2);
+        
+        tree.stat_mut(pack).len = 500;
+        let rescanned = scan(&tree, &ScanOptions { threads: 1, ..Default::default() });
+        let fresh = rescanned.entry(rescanned.find(Path::new("/data/pack.zip")).unwrap()).clone();
+        let change = result.restat(pack_id, fresh);
+        assert!(result.children(pack_id).is_empty());
+        assert!(members.iter().all(|id| change.removed.contains(id)));
+        assert_eq!(result.find(Path::new("/data/pack.zip/a.txt")), None);
+        assert_eq!(result.entry(pack_id).size, 500);
+        assert_eq!((result.total_size, result.file_count), (totals.0 + 200, totals.1));
+    }
+    
+    #[test]
+    fn linked_entries_follow_changes() {
+        let mut tree = MemoryTree::new("/data");
+        let real = tree.dir(MemoryTree::ROOT, "real");
+        tree.file(real, "a.bin", 1000);
+        tree.file(MemoryTree::ROOT, "b.bin", 10);
+        tree.symlink(MemoryTree::ROOT, "alias", "real");
+        let options = ScanOptions { follow_symlinks: true, ..Default::default() };
+        let mut result = scan(&tree, &options);
+        
+        // The link and the file seen through it
+        assert_eq!(result.linked_entries(), 2);
+        assert_eq!(result.total_size, 1010);
+        
+        let b = result.find(Path::new("/data/b.bin")).unwrap();
+        result.remove(b);
+        assert_eq!(result.linked_entries(), 2);
+        assert_eq!(result.total_size, 1000);
+        
+        let alias = result.find(Path::new("/data/alias")).unwrap();
+        result.remove(alias);
+        assert_eq!(result.linked_entries(), 0);
+        assert_eq!(result.linked_entries, Some(0));
+        assert_eq!(result.total_size, 1000);
+        assert_eq!(result.file_count, 1);
+    }
+}
@@ -0,0 +1,121 @@
+use eframe::egui;
+use crate::analyzer::DiskAnalyzer;
+use crate::scanner::{NodeId, ScanResult, SizeMode};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use parking_lot::RwLock;
+
+/// Number of files listed, the totals still cover all of them
+const MAX_ROWS: usize = 1000;
+
+/// "Not accessed since" report of the last scan
+#[derive(Default)]
+pub struct AccessPanel {
+    days: u32,
+    report: Option<AccessReport>,
+}
+
+/// Files matching the current cutoff, rebuilt when the scan or the cutoff changes
+struct AccessReport {
+    scan_time: DateTime<Utc>,
+    /// `ScanResult::generation` the report was built from, moved on by watcher updates
+    generation: u64,
+    days: u32,
+    files: Vec<NodeId>,
+    total_size: u64,
+}
+
+impl AccessPanel {
+    pub fn new() -> Self {
+        Self {
+            days: 365,
+            report: None,
+        }
+    }
+    
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        scan_result: Arc<RwLock<Option<ScanResult>>>,
+        analyzer: &DiskAnalyzer,
+        size_mode: SizeMode,
+    ) {
+        ui.heading("🕰 Not Accessed Since");
+        ui.add_space(10.0);
+        
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            ui.label("No scan data yet.");
+            return;
+        };
+        
+        ui.horizontal(|ui| {
+            ui.label("Files not read in the last");
+            ui.add(egui::DragValue::new(&mut self.days).range(1..=36500));
+            ui.label("days");
+        })
+        .response
+        .on_hover_text("Based on access times, which mounts with relatime only update once a day");
+        
+        let is_current = self.report.as_ref().is_some_and(|report| {
+            report.scan_time == scan_result.scan_time
+                && report.generation == scan_result.generation()
+                && report.days == self.days
+        });
+        if !is_current {
+            let cutoff = Utc::now() - chrono::Duration::days(self.days as i64);
+            let files = analyzer.not_accessed_since(scan_result, cutoff);
+            let total_size = files.iter().map(|&id| scan_result.entry(id).size_in(size_mode)).sum();
+            self.report = Some(AccessReport {
+                scan_time: scan_result.scan_time,
+                generation: scan_result.generation(),
+                days: self.days,
+                files,
+                total_size,
+            });
+        }
+        let Some(report) = &self.report else {
+            return;
+        };
+        
+        ui.label(format!(
+            "{} files, {} in total",
+            report.files.len(),
+            humansize::format_size(report.total_size, humansize::DECIMAL)
+        ));
+        if report.files.len() > MAX_ROWS {
+            ui.label(egui::RichText::new(format!("Showing the {} least recently accessed", MAX_ROWS)).weak());
+        }
+        ui.separator();
+        
+        egui::ScrollArea::both()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                egui::Grid::new("access_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .spacing([20.0, 5.0])
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new("Last access").strong());
+                        ui.label(egui::RichText::new("Size").strong());
+                        ui.label(egui::RichText::new("Owner").strong());
+                        ui.label(egui::RichText::new("Path").strong());
+                        ui.end_row();
+                        
+                        for &id in report.files.iter().take(MAX_ROWS) {
+                            let entry = scan_result.entry(id);
+                            let owner = match (scan_result.user_name(id), entry.uid) {
+                                (Some(name), _) => name.to_string(),
+                                (None, Some(uid)) => uid.to_string(),
+                                (None, None) => String::new(),
+                            };
+                            ui.label(entry.accessed.map_or(String::new(), |time| time.format("%Y-%m-%d").to_string()));
+                            ui.label(humansize::format_size(entry.size_in(size_mode), humansize::DECIMAL));
+                            ui.label(owner);
+                            ui.label(scan_result.path_of(id).display().to_string());
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
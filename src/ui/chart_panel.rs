@@ -1,4 +1,10 @@
 use eframe::egui;
+use crate::scanner::{NodeId, ScanResult, SizeMode};
+use std::sync::Arc;
+use parking_lot::RwLock;
+
+/// Number of bars shown in the chart
+const MAX_BARS: usize = 25;
 
 /// Chart view panel
 pub struct ChartPanel {}
@@ -8,12 +14,54 @@ impl ChartPanel {
         Self {}
     }
     
-    pub fn render(&mut self, ui: &mut egui::Ui) {
-        ui.vertical_centered(|ui| {
-            ui.heading("📊 Chart View");
-            ui.label("This feature is under development");
-            ui.add_space(20.0);
-            ui.label("Coming soon: Treemap visualization of disk usage");
-        });
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        scan_result: Arc<RwLock<Option<ScanResult>>>,
+        size_mode: SizeMode,
+    ) {
+        ui.heading("📊 Chart View");
+        ui.add_space(10.0);
+        
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            ui.vertical_centered(|ui| {
+                ui.label("Scan a directory to see which items take the most space");
+            });
+            return;
+        };
+        
+        let mut items: Vec<NodeId> = scan_result
+            .root()
+            .map(|root| root.children.clone())
+            .unwrap_or_default();
+        items.sort_by_key(|&id| std::cmp::Reverse(scan_result.entry(id).size_in(size_mode)));
+        
+        let total = scan_result.total_in(size_mode).max(1);
+        
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                egui::Grid::new("chart_grid")
+                    .num_columns(3)
+                    .spacing([20.0, 5.0])
+                    .show(ui, |ui| {
+                        for &id in items.iter().take(MAX_BARS) {
+                            let entry = scan_result.entry(id);
+                            let size = entry.size_in(size_mode);
+                            let fraction = size as f32 / total as f32;
+                            let icon = if entry.is_directory() { "📁" } else { "📄" };
+                            
+                            ui.label(format!("{} {}", icon, scan_result.name(id)));
+                            ui.add(
+                                egui::ProgressBar::new(fraction)
+                                    .desired_width(300.0)
+                                    .text(format!("{:.1}%", fraction * 100.0)),
+                            );
+                            ui.label(humansize::format_size(size, humansize::DECIMAL));
+                            ui.end_row();
+                        }
+                    });
+            });
     }
-}
\ No newline at end of file
+}
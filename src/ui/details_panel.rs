@@ -1,4 +1,8 @@
 use eframe::egui;
+use crate::scanner::ScanResult;
+use chrono::{DateTime, Local, Utc};
+use std::sync::Arc;
+use parking_lot::RwLock;
 
 /// Details view panel
 pub struct DetailsPanel {}
@@ -8,12 +12,95 @@ impl DetailsPanel {
         Self {}
     }
     
-    pub fn render(&mut self, ui: &mut egui::Ui) {
-        ui.vertical_centered(|ui| {
-            ui.heading("📋 Details View");
-            ui.label("This feature is under development");
-            ui.add_space(20.0);
-            ui.label("Coming soon: Detailed file information and statistics");
-        });
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        scan_result: Arc<RwLock<Option<ScanResult>>>,
+        selected_path: &Option<std::path::PathBuf>,
+    ) {
+        ui.heading("📋 Details View");
+        ui.add_space(10.0);
+        
+        let scan_result = scan_result.read();
+        let Some((scan_result, path)) = scan_result.as_ref().zip(selected_path.as_ref()) else {
+            ui.label("Select an item in the tree to see its details.");
+            return;
+        };
+        let Some(id) = scan_result.find(path) else {
+            ui.label("The selected item is not part of the current scan.");
+            return;
+        };
+        let entry = scan_result.entry(id);
+        
+        egui::Grid::new("details_grid")
+            .num_columns(2)
+            .striped(true)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                let mut row = |label: &str, value: String| {
+                    ui.label(egui::RichText::new(label).strong());
+                    ui.label(value);
+                    ui.end_row();
+                };
+                
+                row("Path", path.display().to_string());
+                row("Type", entry.kind.to_string());
+                if let Some(target) = &entry.link_target {
+                    let broken = if target.broken { " (broken)" } else { "" };
+                    row("Link target", format!("{}{}", target.path.display(), broken));
+                }
+                row("Size", humansize::format_size(entry.size, humansize::DECIMAL));
+                if entry.in_archive {
+                    row("Packed", humansize::format_size(entry.allocated_size, humansize::DECIMAL));
+                    row("Stored", "inside an archive, not on disk".to_string());
+                } else {
+                    row("On disk", humansize::format_size(entry.allocated_size, humansize::DECIMAL));
+                }
+                row("Owner", owner(entry.uid, scan_result.user_name(id)));
+                row("Group", owner(entry.gid, scan_result.group_name(id)));
+                row("Permissions", entry.mode.map_or_else(unknown, format_mode));
+                row("Modified", format_time(entry.modified));
+                row("Accessed", format_time(entry.accessed));
+                row("Changed", format_time(entry.changed));
+                row("Created", format_time(entry.created));
+                row("Hard links", entry.nlink.to_string());
+            });
     }
-}
\ No newline at end of file
+}
+
+fn unknown() -> String {
+    "unknown".to_string()
+}
+
+/// "name (id)", or just the id when it has no name
+fn owner(id: Option<u32>, name: Option<&str>) -> String {
+    match (id, name) {
+        (Some(id), Some(name)) => format!("{} ({})", name, id),
+        (Some(id), None) => id.to_string(),
+        (None, _) => unknown(),
+    }
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or_else(unknown, |time| {
+        time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()
+    })
+}
+
+/// Permission bits like `ls -l` shows them, followed by the octal value
+fn format_mode(mode: u32) -> String {
+    let mut bits: Vec<char> = "rwxrwxrwx"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if mode & (0o400 >> i) != 0 { c } else { '-' })
+        .collect();
+    
+    // setuid, setgid and sticky replace the matching execute bit
+    for (flag, index, set) in [(0o4000, 2, 's'), (0o2000, 5, 's'), (0o1000, 8, 't')] {
+        if mode & flag != 0 {
+            bits[index] = if bits[index] == 'x' { set } else { set.to_ascii_uppercase() };
+        }
+    }
+    
+    format!("{} ({:04o})", bits.into_iter().collect::<String>(), mode & 0o7777)
+}
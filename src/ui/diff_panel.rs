@@ -0,0 +1,247 @@
+use eframe::egui;
+use crate::analyzer::{compare_scans, ChangeKind, ScanDiff};
+use crate::scanner::{ScanResult, SizeMode};
+use crate::snapshot;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::sync::Arc;
+use parking_lot::RwLock;
+
+/// Number of paths in the biggest growth list
+const GROWTH_ROWS: usize = 100;
+
+/// Changes of the current scan against a saved snapshot
+#[derive(Default)]
+pub struct DiffPanel {
+    /// Older scan loaded from a snapshot
+    baseline: Option<ScanResult>,
+    diff: Option<ScanDiff>,
+    /// Current scan the diff was computed for
+    diff_key: Option<(DateTime<Utc>, usize, u64, SizeMode)>,
+    error: Option<String>,
+    /// Counts of each kind of change, worked out once per diff
+    summary: String,
+    /// Biggest growth list and whether it holds folders
+    growth: Option<(bool, Vec<usize>)>,
+    expanded: HashSet<usize>,
+    hide_unchanged: bool,
+    growth_of_folders: bool,
+}
+
+impl DiffPanel {
+    pub fn new() -> Self {
+        Self {
+            baseline: None,
+            diff: None,
+            diff_key: None,
+            error: None,
+            summary: String::new(),
+            growth: None,
+            expanded: HashSet::new(),
+            hide_unchanged: true,
+            growth_of_folders: false,
+        }
+    }
+    
+    pub fn render(&mut self, ui: &mut egui::Ui, scan_result: Arc<RwLock<Option<ScanResult>>>, size_mode: SizeMode) {
+        ui.horizontal(|ui| {
+            ui.heading("📈 Changes Since Snapshot");
+            if ui.button("📂 Compare with Snapshot...").clicked() {
+                self.pick_baseline();
+            }
+            ui.checkbox(&mut self.hide_unchanged, "Hide unchanged");
+        });
+        ui.add_space(10.0);
+        
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+            return;
+        }
+        
+        let scan_result = scan_result.read();
+        let (Some(current), Some(baseline)) = (scan_result.as_ref(), &self.baseline) else {
+            ui.label("Open a snapshot saved with File > Save Scan to see what changed since then.");
+            return;
+        };
+        
+        let key = (current.scan_time, current.entries.len(), current.total_size, size_mode);
+        if self.diff_key != Some(key) {
+            self.diff = compare_scans(baseline, current, size_mode);
+            self.diff_key = Some(key);
+            self.growth = None;
+            self.summary = self.diff.as_ref().map_or(String::new(), |diff| {
+                format!(
+                    "{} added, {} removed, {} grown, {} shrunk",
+                    diff.count(ChangeKind::Added),
+                    diff.count(ChangeKind::Removed),
+                    diff.count(ChangeKind::Grown),
+                    diff.count(ChangeKind::Shrunk),
+                )
+            });
+        }
+        // Taken out while rendering, the tree state is updated along the way
+        let Some(diff) = self.diff.take() else {
+            ui.label(format!(
+                "The snapshot is of {}, the current scan of {}.",
+                baseline.root_path.display(),
+                current.root_path.display()
+            ));
+            return;
+        };
+        
+        let root = diff.root();
+        ui.label(format!(
+            "{} → {}: {} ({} → {}), {} files",
+            diff.old_time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            diff.new_time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            format_delta(root.size_delta()),
+            humansize::format_size(root.old_size, humansize::DECIMAL),
+            humansize::format_size(root.new_size, humansize::DECIMAL),
+            format_count_delta(root.file_delta()),
+        ));
+        ui.label(&self.summary);
+        ui.separator();
+        
+        if self.growth.as_ref().is_none_or(|(folders, _)| *folders != self.growth_of_folders) {
+            let growth = diff.biggest_growth(GROWTH_ROWS, self.growth_of_folders);
+            self.growth = Some((self.growth_of_folders, growth));
+        }
+        
+        egui::SidePanel::right("diff_growth")
+            .resizable(true)
+            .default_width(350.0)
+            .show_inside(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("Biggest growth");
+                    ui.radio_value(&mut self.growth_of_folders, false, "Files");
+                    ui.radio_value(&mut self.growth_of_folders, true, "Folders");
+                });
+                egui::ScrollArea::vertical()
+                    .id_salt("diff_growth_scroll")
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        for &id in self.growth.iter().flat_map(|(_, growth)| growth) {
+                            let node = &diff.nodes[id];
+                            ui.horizontal(|ui| {
+                                ui.colored_label(change_color(node.change), format_delta(node.size_delta()));
+                                ui.label(diff.path_of(id).display().to_string());
+                            });
+                        }
+                    });
+            });
+        
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt("diff_tree_scroll")
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    for id in self.sorted_children(&diff, 0) {
+                        self.render_node(ui, &diff, id);
+                    }
+                });
+        });
+        self.diff = Some(diff);
+    }
+    
+    fn pick_baseline(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("DiskRay scan", &[snapshot::EXTENSION])
+            .add_filter("Scan of an older version", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        
+        self.diff = None;
+        self.diff_key = None;
+        self.expanded.clear();
+        match snapshot::load(&path) {
+            Ok(baseline) => {
+                self.baseline = Some(baseline);
+                self.error = None;
+            }
+            Err(e) => {
+                self.baseline = None;
+                self.error = Some(format!("Could not open {}: {}", path.display(), e));
+            }
+        }
+    }
+    
+    /// Children with the biggest changes first
+    fn sorted_children(&self, diff: &ScanDiff, id: usize) -> Vec<usize> {
+        let mut children: Vec<usize> = diff.nodes[id]
+            .children
+            .iter()
+            .copied()
+            .filter(|&child| !self.hide_unchanged || diff.nodes[child].change != ChangeKind::Unchanged)
+            .collect();
+        children.sort_by_key(|&child| std::cmp::Reverse(diff.nodes[child].size_delta().unsigned_abs()));
+        children
+    }
+    
+    fn render_node(&mut self, ui: &mut egui::Ui, diff: &ScanDiff, id: usize) {
+        let node = &diff.nodes[id];
+        let icon = match node.change {
+            ChangeKind::Added => "➕",
+            ChangeKind::Removed => "➖",
+            ChangeKind::Grown => "▲",
+            ChangeKind::Shrunk => "▼",
+            ChangeKind::Unchanged => "•",
+        };
+        let folder = if node.is_container { "📁 " } else { "" };
+        let mut text = format!("{} {}{}  {}", icon, folder, diff.name(id), format_delta(node.size_delta()));
+        if node.file_delta() != 0 {
+            text.push_str(&format!(" ({} files)", format_count_delta(node.file_delta())));
+        }
+        
+        let is_expanded = self.expanded.contains(&id);
+        let response = ui
+            .selectable_label(is_expanded, egui::RichText::new(text).color(change_color(node.change)))
+            .on_hover_text(format!(
+                "{} → {}\n{} → {} files",
+                humansize::format_size(node.old_size, humansize::DECIMAL),
+                humansize::format_size(node.new_size, humansize::DECIMAL),
+                node.old_files,
+                node.new_files
+            ));
+        if response.clicked() && !node.children.is_empty() {
+            if is_expanded {
+                self.expanded.remove(&id);
+            } else {
+                self.expanded.insert(id);
+            }
+        }
+        
+        if is_expanded {
+            ui.indent(egui::Id::new(("diff_node", id)), |ui| {
+                for child in self.sorted_children(diff, id) {
+                    self.render_node(ui, diff, child);
+                }
+            });
+        }
+    }
+}
+
+/// Growth in red, shrinking in green
+fn change_color(change: ChangeKind) -> egui::Color32 {
+    match change {
+        ChangeKind::Added => egui::Color32::RED,
+        ChangeKind::Grown => egui::Color32::LIGHT_RED,
+        ChangeKind::Removed => egui::Color32::GREEN,
+        ChangeKind::Shrunk => egui::Color32::LIGHT_GREEN,
+        ChangeKind::Unchanged => egui::Color32::GRAY,
+    }
+}
+
+fn format_delta(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, humansize::format_size(bytes.unsigned_abs(), humansize::DECIMAL))
+}
+
+fn format_count_delta(count: i64) -> String {
+    if count < 0 {
+        count.to_string()
+    } else {
+        format!("+{}", count)
+    }
+}
@@ -0,0 +1,95 @@
+use eframe::egui;
+use crate::scanner::{ScanErrorKind, ScanResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::RwLock;
+
+/// Panel listing every path the last scan could not read
+#[derive(Default)]
+pub struct ErrorsPanel {
+    kind_filter: Option<ScanErrorKind>,
+}
+
+impl ErrorsPanel {
+    pub fn new() -> Self {
+        Self { kind_filter: None }
+    }
+    
+    pub fn render(&mut self, ui: &mut egui::Ui, scan_result: Arc<RwLock<Option<ScanResult>>>) {
+        ui.heading("⚠️ Scan Errors");
+        ui.add_space(10.0);
+        
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            ui.label("No scan data yet.");
+            return;
+        };
+        
+        if scan_result.errors.is_empty() {
+            ui.label("Every path was read successfully, all totals are complete.");
+            return;
+        }
+        
+        let mut counts: HashMap<ScanErrorKind, usize> = HashMap::new();
+        for error in &scan_result.errors {
+            *counts.entry(error.kind).or_default() += 1;
+        }
+        
+        ui.horizontal(|ui| {
+            ui.selectable_value(
+                &mut self.kind_filter,
+                None,
+                format!("All ({})", scan_result.errors.len()),
+            );
+            for kind in [
+                ScanErrorKind::PermissionDenied,
+                ScanErrorKind::Vanished,
+                ScanErrorKind::Loop,
+                ScanErrorKind::Io,
+            ] {
+                if let Some(count) = counts.get(&kind) {
+                    ui.selectable_value(&mut self.kind_filter, Some(kind), format!("{} ({})", kind, count));
+                }
+            }
+        });
+        
+        ui.label(
+            egui::RichText::new("Folders containing these paths are marked ⚠ in the tree, their totals are too low.")
+                .weak(),
+        );
+        ui.separator();
+        
+        egui::ScrollArea::both()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                egui::Grid::new("errors_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .spacing([20.0, 5.0])
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new("Error").strong());
+                        ui.label(egui::RichText::new("Operation").strong());
+                        ui.label(egui::RichText::new("Path").strong());
+                        ui.label(egui::RichText::new("Details").strong());
+                        ui.end_row();
+                        
+                        for error in scan_result
+                            .errors
+                            .iter()
+                            .filter(|e| self.kind_filter.is_none_or(|kind| e.kind == kind))
+                        {
+                            let color = match error.kind {
+                                ScanErrorKind::PermissionDenied => egui::Color32::YELLOW,
+                                ScanErrorKind::Vanished => egui::Color32::from_gray(160),
+                                ScanErrorKind::Loop | ScanErrorKind::Io => egui::Color32::from_rgb(255, 100, 100),
+                            };
+                            ui.colored_label(color, error.kind.to_string());
+                            ui.label(error.operation.to_string());
+                            ui.label(error.path.display().to_string());
+                            ui.label(&error.message);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
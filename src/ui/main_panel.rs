@@ -1,16 +1,32 @@
 use eframe::egui;
 use super::super::app::ViewMode;
-use crate::scanner::FileSystemScanner;
+use crate::scanner::{EstimateSource, FileSystemScanner, ScanOptions, ScanState, SizeMode, ThrottleState};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use crate::scanner::ScanResult;
+use crate::rules::{PathRule, PatternKind, RuleAction};
+use crate::ncdu;
+use crate::snapshot;
+use crate::checkpoint::{self, CheckpointInfo};
+use crate::watcher::{WatchStatus, POLL_INTERVAL};
 
 /// Main panel with menu and controls
 #[derive(Default)]
 pub struct MainPanel {
     pub show_settings: bool,
     pub show_about: bool,
+    pub show_rules: bool,
     pub dark_mode: bool,
+    pub size_mode: SizeMode,
+    pub scan_options: ScanOptions,
+    /// Keep the finished scan up to date with changes on disk
+    pub watch_changes: bool,
+    /// Snapshot loaded from File > Open Scan, taken over by the app
+    pub opened_scan: Option<ScanResult>,
+    /// Save checkpoints of long scans, so an interrupted one can be resumed
+    pub save_checkpoints: bool,
+    /// Interrupted scan of the path about to be scanned, waiting for the user to resume or discard it
+    resume_offer: Option<CheckpointInfo>,
     scan_path_input: String,
 }
 
@@ -19,7 +35,14 @@ impl MainPanel {
         Self {
             show_settings: false,
             show_about: false,
+            show_rules: false,
             dark_mode: true,
+            size_mode: SizeMode::Apparent,
+            scan_options: ScanOptions::default(),
+            watch_changes: false,
+            opened_scan: None,
+            save_checkpoints: true,
+            resume_offer: None,
             scan_path_input: std::env::current_dir()
                 .unwrap_or_else(|_| std::path::PathBuf::from("/"))
                 .to_string_lossy()
@@ -35,7 +58,7 @@ impl MainPanel {
         scanner: &mut Option<FileSystemScanner>,
         _selected_path: &mut Option<std::path::PathBuf>,
         current_path: &mut std::path::PathBuf,
-        _scan_result: Arc<RwLock<Option<ScanResult>>>, // Добавили подчеркивание
+        scan_result: Arc<RwLock<Option<ScanResult>>>,
     ) {
         ui.horizontal(|ui| {
             // File menu
@@ -52,15 +75,40 @@ impl MainPanel {
                     let path = std::path::PathBuf::from(&self.scan_path_input);
                     if path.exists() {
                         *current_path = path.clone();
-                        *scanner = Some(FileSystemScanner::new(path));
-                        scanner.as_mut().unwrap().start();
-                        *is_scanning = true;
+                        self.start_scan(path, scanner, is_scanning);
                     }
                     ui.close();
                 }
                 
+                if ui.button("🚫 Scan Rules...").clicked() {
+                    self.show_rules = true;
+                    ui.close();
+                }
+                
                 if ui.button("📊 Export Report...").clicked() {
-                    self.export_report();
+                    self.export_report(&scan_result);
+                    ui.close();
+                }
+                
+                ui.separator();
+                
+                if ui.button("📂 Open Scan...").clicked() {
+                    self.open_scan();
+                    ui.close();
+                }
+                
+                if ui.add_enabled(!*is_scanning, egui::Button::new("💾 Save Scan...")).clicked() {
+                    self.save_scan(&scan_result);
+                    ui.close();
+                }
+                
+                if ui.button("📥 Import ncdu Export...").clicked() {
+                    self.import_ncdu();
+                    ui.close();
+                }
+                
+                if ui.add_enabled(!*is_scanning, egui::Button::new("📤 Export for ncdu...")).clicked() {
+                    self.export_ncdu(&scan_result);
                     ui.close();
                 }
                 
@@ -88,6 +136,28 @@ impl MainPanel {
                     *view_mode = ViewMode::Disks;
                     ui.close();
                 }
+                if ui.button("⚠️ Scan Errors").clicked() {
+                    *view_mode = ViewMode::Errors;
+                    ui.close();
+                }
+                if ui.button("🕰 Not Accessed Since").clicked() {
+                    *view_mode = ViewMode::Access;
+                    ui.close();
+                }
+                if ui.button("📈 Changes Since Snapshot").clicked() {
+                    *view_mode = ViewMode::Diff;
+                    ui.close();
+                }
+                if ui.button("⏱ Scan Progress").clicked() {
+                    *view_mode = ViewMode::Progress;
+                    ui.close();
+                }
+                
+                ui.separator();
+                
+                ui.label("Sizes:");
+                ui.radio_value(&mut self.size_mode, SizeMode::Apparent, "Apparent size");
+                ui.radio_value(&mut self.size_mode, SizeMode::Allocated, "Size on disk");
                 
                 ui.separator();
                 
@@ -128,7 +198,42 @@ impl MainPanel {
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                 if *is_scanning {
                     ui.spinner();
-                    ui.label("Scanning...");
+                    match scanner.as_ref().map(|s| (s.get_progress(), s.is_stopping())) {
+                        Some((_, true)) => {
+                            ui.label("Stopping...");
+                        }
+                        Some((progress, false)) => {
+                            match progress.estimated_total {
+                                Some(estimate) => {
+                                    let source = match estimate.source {
+                                        EstimateSource::FilesystemInodes => "used inodes on this filesystem",
+                                        EstimateSource::PreviousScan => "the previous scan of this path",
+                                    };
+                                    ui.label(format!(
+                                        "Scanning... {} of ~{} entries",
+                                        progress.files_scanned,
+                                        progress.files_scanned.max(estimate.entries)
+                                    ))
+                                    .on_hover_text(format!("Total is an estimate based on {}", source));
+                                }
+                                None => {
+                                    ui.label(format!("Scanning... {} entries", progress.files_scanned));
+                                }
+                            }
+                            match progress.throttle {
+                                ThrottleState::FullSpeed => {}
+                                ThrottleState::LowPriority => {
+                                    ui.label("🐢").on_hover_text("Gentle scan, running at low priority");
+                                }
+                                state => {
+                                    ui.label(format!("🐢 {}", state));
+                                }
+                            }
+                        }
+                        None => {
+                            ui.label("Scanning...");
+                        }
+                    }
                 }
                 
                 if ui.button("⚙️").clicked() {
@@ -155,16 +260,14 @@ impl MainPanel {
                 let path = std::path::PathBuf::from(&self.scan_path_input);
                 if path.exists() {
                     *current_path = path.clone();
-                    *scanner = Some(FileSystemScanner::new(path));
-                    scanner.as_mut().unwrap().start();
-                    *is_scanning = true;
+                    self.start_scan(path, scanner, is_scanning);
                 }
             }
             
             if ui.button("⏹️ Stop").clicked() && *is_scanning {
+                // The app picks up the partial result once the workers have wound down
                 if let Some(scanner) = scanner {
                     scanner.stop();
-                    *is_scanning = false;
                 }
             }
         });
@@ -184,9 +287,7 @@ impl MainPanel {
                     if path.exists() {
                         self.scan_path_input = drive_path.clone();
                         *current_path = path.clone();
-                        *scanner = Some(FileSystemScanner::new(path));
-                        scanner.as_mut().unwrap().start();
-                        *is_scanning = true;
+                        self.start_scan(path, scanner, is_scanning);
                     }
                 }
             }
@@ -196,9 +297,7 @@ impl MainPanel {
                 if ui.button("🏠 Home").clicked() {
                     self.scan_path_input = home.to_string_lossy().to_string();
                     *current_path = home.clone();
-                    *scanner = Some(FileSystemScanner::new(home));
-                    scanner.as_mut().unwrap().start();
-                    *is_scanning = true;
+                    self.start_scan(home, scanner, is_scanning);
                 }
             }
             
@@ -207,9 +306,23 @@ impl MainPanel {
                 if ui.button("🖥️ Desktop").clicked() {
                     self.scan_path_input = desktop.to_string_lossy().to_string();
                     *current_path = desktop.clone();
-                    *scanner = Some(FileSystemScanner::new(desktop));
-                    scanner.as_mut().unwrap().start();
-                    *is_scanning = true;
+                    self.start_scan(desktop, scanner, is_scanning);
+                }
+            }
+            
+            if ui.button("🗂 Several Folders...")
+                .on_hover_text("Scan several folders side by side, in one tree")
+                .clicked()
+            {
+                if let Some(mut roots) = rfd::FileDialog::new().pick_folders() {
+                    if roots.len() == 1 {
+                        let path = roots.remove(0);
+                        self.scan_path_input = path.to_string_lossy().to_string();
+                        *current_path = path.clone();
+                        self.start_scan(path, scanner, is_scanning);
+                    } else if !roots.is_empty() {
+                        self.launch_roots_scan(roots, current_path, scanner, is_scanning);
+                    }
                 }
             }
         });
@@ -222,6 +335,107 @@ impl MainPanel {
         if self.show_about {
             self.render_about(ui.ctx());
         }
+        
+        if self.show_rules {
+            self.render_rules(ui.ctx());
+        }
+        
+        if self.resume_offer.is_some() {
+            self.render_resume_offer(ui.ctx(), scanner, is_scanning);
+        }
+    }
+    
+    /// Scan `path`, or first offer to resume an interrupted scan of it
+    fn start_scan(&mut self, path: std::path::PathBuf, scanner: &mut Option<FileSystemScanner>, is_scanning: &mut bool) {
+        if self.save_checkpoints {
+            let info = checkpoint::file_for(&path)
+                .and_then(|file| checkpoint::load_info(&file).ok())
+                .filter(|info| info.root_path == path && info.options.same_walk(&self.scan_options));
+            if info.is_some() {
+                self.resume_offer = info;
+                return;
+            }
+        }
+        self.launch_scan(path, false, scanner, is_scanning);
+    }
+    
+    fn launch_scan(&self, path: std::path::PathBuf, resume: bool, scanner: &mut Option<FileSystemScanner>, is_scanning: &mut bool) {
+        let mut new_scanner = FileSystemScanner::with_options(path.clone(), self.scan_options.clone());
+        if self.save_checkpoints {
+            if let Some(file) = checkpoint::file_for(&path) {
+                new_scanner.use_checkpoint(file, resume);
+            }
+        }
+        new_scanner.start();
+        *scanner = Some(new_scanner);
+        *is_scanning = true;
+    }
+    
+    /// Scan several folders into one result, see `FileSystemScanner::with_roots`.
+    /// Checkpoints are kept per folder, so these scans always start fresh.
+    fn launch_roots_scan(
+        &self,
+        roots: Vec<std::path::PathBuf>,
+        current_path: &mut std::path::PathBuf,
+        scanner: &mut Option<FileSystemScanner>,
+        is_scanning: &mut bool,
+    ) {
+        let mut new_scanner = FileSystemScanner::with_roots(roots, self.scan_options.clone());
+        new_scanner.start();
+        *current_path = new_scanner.root_path().to_path_buf();
+        *scanner = Some(new_scanner);
+        *is_scanning = true;
+    }
+    
+    fn render_resume_offer(&mut self, ctx: &egui::Context, scanner: &mut Option<FileSystemScanner>, is_scanning: &mut bool) {
+        let Some(info) = &self.resume_offer else {
+            return;
+        };
+        
+        let mut choice = None;
+        let mut cancelled = false;
+        egui::Window::new("Resume Scan?")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "A scan of {} was interrupted after {} entries and {:.0} minutes.",
+                    info.root_path.display(),
+                    info.entries,
+                    info.elapsed.as_secs_f32() / 60.0
+                ));
+                ui.label(format!(
+                    "Last checkpoint: {}",
+                    info.saved.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                ));
+                ui.label("Folders changed since then are listed again.");
+                
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    if ui.button("▶️ Resume").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("🔄 Start Over").clicked() {
+                        choice = Some(false);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+        
+        if cancelled {
+            self.resume_offer = None;
+        } else if let Some(resume) = choice {
+            let path = self.resume_offer.take().unwrap().root_path;
+            if !resume {
+                if let Some(file) = checkpoint::file_for(&path) {
+                    checkpoint::remove(&file);
+                }
+            }
+            self.launch_scan(path, resume, scanner, is_scanning);
+        }
     }
     
     pub fn render_status(
@@ -229,22 +443,120 @@ impl MainPanel {
         ui: &mut egui::Ui, 
         current_path: &std::path::PathBuf,
         scan_result: Arc<RwLock<Option<ScanResult>>>,
+        scan_state: Option<ScanState>,
+        watch_status: Option<WatchStatus>,
     ) {
         ui.horizontal(|ui| {
-            ui.label(format!("📁 Current path: {}", current_path.display()));
+            let roots = scan_result
+                .read()
+                .as_ref()
+                .map(|result| result.roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            if roots.is_empty() {
+                ui.label(format!("📁 Current path: {}", current_path.display()));
+            } else {
+                ui.label(format!("📁 Roots: {}", roots.join(", ")));
+            }
             ui.separator();
             
+            if let Some(ScanState::Failed(message)) = &scan_state {
+                ui.colored_label(egui::Color32::RED, format!("❌ Scan failed: {}", message));
+                ui.separator();
+            }
+            
             if let Some(scan_result) = &*scan_result.read() {
+                if scan_result.root().is_some_and(|root| root.scanning) {
+                    ui.label("🔄 Partial tree, sizes grow as the scan goes")
+                        .on_hover_text("Folders marked ⏳ are still being scanned, the others are final");
+                    ui.separator();
+                } else if scan_result.partial {
+                    ui.colored_label(egui::Color32::YELLOW, "⏹️ Scan cancelled, partial results")
+                        .on_hover_text("Sizes only cover the folders visited before the scan was stopped");
+                    ui.separator();
+                }
+                let size_label = match self.size_mode {
+                    SizeMode::Apparent => "Size",
+                    SizeMode::Allocated => "On disk",
+                };
                 ui.label(format!(
-                    "📊 Files: {}, Size: {}",
+                    "📊 Files: {}, {}: {}",
                     scan_result.file_count,
-                    humansize::format_size(scan_result.total_size, humansize::DECIMAL)
+                    size_label,
+                    humansize::format_size(scan_result.total_in(self.size_mode), humansize::DECIMAL)
                 ));
                 ui.separator();
+                if !scan_result.roots.is_empty() {
+                    // Totals take a pass over the tree, so only while the tooltip is open
+                    ui.label(format!("🗂 {} roots", scan_result.roots.len())).on_hover_ui(|ui| {
+                        for totals in scan_result.root_totals() {
+                            let size = match self.size_mode {
+                                SizeMode::Apparent => totals.size,
+                                SizeMode::Allocated => totals.allocated_size,
+                            };
+                            ui.label(format!(
+                                "{}: {} files, {} folders, {}",
+                                totals.path.display(),
+                                totals.file_count,
+                                totals.dir_count,
+                                humansize::format_size(size, humansize::DECIMAL)
+                            ));
+                        }
+                    });
+                    ui.separator();
+                }
+                if scan_result.excluded_count > 0 {
+                    ui.label(format!(
+                        "🚫 Excluded: {} ({})",
+                        scan_result.excluded_count,
+                        humansize::format_size(scan_result.excluded_size, humansize::DECIMAL)
+                    ))
+                    .on_hover_text("Items left out by scan rules; excluded folders are not measured");
+                    ui.separator();
+                }
+                if !scan_result.errors.is_empty() {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("⚠️ {} unreadable paths", scan_result.errors.len()),
+                    )
+                    .on_hover_text("Totals are incomplete, see View > Scan Errors");
+                    ui.separator();
+                }
                 ui.label(format!(
                     "⏱️ Scan time: {:.2}s",
                     scan_result.scan_duration.as_secs_f32()
                 ));
+                if !scan_result.hostname.is_empty() && scan_result.hostname != crate::scanner::local_hostname() {
+                    ui.separator();
+                    ui.label(format!("🖥 Scanned on {}", scan_result.hostname))
+                        .on_hover_text(Self::describe_scan(scan_result));
+                }
+            }
+            
+            if let Some(status) = watch_status {
+                ui.separator();
+                let last_update = status
+                    .last_update
+                    .map_or("no changes yet".to_string(), |time| {
+                        format!("last change {}", time.with_timezone(&chrono::Local).format("%H:%M:%S"))
+                    });
+                ui.label(format!("👁 Live: {} folders watched", status.watched_dirs))
+                    .on_hover_text(last_update);
+                if !status.polled_dirs.is_empty() {
+                    let polled: Vec<String> = status
+                        .polled_dirs
+                        .iter()
+                        .take(10)
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("🔁 {} rescanned every {}s", status.polled_dirs.len(), POLL_INTERVAL.as_secs()),
+                    )
+                    .on_hover_text(format!(
+                        "Watch limit reached (fs.inotify.max_user_watches), these folders are polled instead:\n{}",
+                        polled.join("\n")
+                    ));
+                }
             }
             
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -253,8 +565,122 @@ impl MainPanel {
         });
     }
     
-    fn export_report(&self) {
-        println!("Export report");
+    fn export_report(&self, scan_result: &Arc<RwLock<Option<ScanResult>>>) {
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            return;
+        };
+        
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .set_file_name("diskray-report.csv")
+            .save_file()
+        {
+            let written = std::fs::File::create(&path)
+                .and_then(|file| crate::export::write_csv(scan_result, std::io::BufWriter::new(file)));
+            if let Err(e) = written {
+                Self::show_error("Could not export report", format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+    
+    /// When and how a scan was taken, for snapshots opened from disk
+    fn describe_scan(scan_result: &ScanResult) -> String {
+        let options = &scan_result.options;
+        let threads = if options.threads == 0 {
+            "one per core".to_string()
+        } else {
+            options.threads.to_string()
+        };
+        let depth = options
+            .max_depth
+            .map_or("unlimited".to_string(), |depth| format!("{} levels", depth));
+        format!(
+            "Taken {}\nThreads: {}\nStay on one filesystem: {}\nFollow symbolic links: {}\nDepth limit: {}\nScan rules: {}",
+            scan_result.scan_time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            threads,
+            if options.one_file_system { "yes" } else { "no" },
+            if options.follow_symlinks { "yes" } else { "no" },
+            depth,
+            options.rules.len(),
+        )
+    }
+    
+    fn open_scan(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("DiskRay scan", &[snapshot::EXTENSION])
+            .add_filter("Scan of an older version", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        
+        match snapshot::load(&path) {
+            Ok(result) => self.opened_scan = Some(result),
+            Err(e) => Self::show_error("Could not open scan", format!("{}: {}", path.display(), e)),
+        }
+    }
+    
+    fn save_scan(&self, scan_result: &Arc<RwLock<Option<ScanResult>>>) {
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            return;
+        };
+        
+        let name = scan_result
+            .root_path
+            .file_name()
+            .map_or("scan".to_string(), |name| name.to_string_lossy().to_string());
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("DiskRay scan", &[snapshot::EXTENSION])
+            .set_file_name(format!("{}-{}.{}", name, scan_result.scan_time.format("%Y%m%d-%H%M"), snapshot::EXTENSION))
+            .save_file()
+        {
+            if let Err(e) = snapshot::save(scan_result, &path) {
+                Self::show_error("Could not save scan", format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+    
+    /// Open a dump written by `ncdu -o`
+    fn import_ncdu(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("ncdu export", &["json"])
+            .add_filter("All files", &["*"])
+            .pick_file()
+        else {
+            return;
+        };
+        
+        match ncdu::load(&path) {
+            Ok(result) => self.opened_scan = Some(result),
+            Err(e) => Self::show_error("Could not import ncdu export", format!("{}: {}", path.display(), e)),
+        }
+    }
+    
+    fn export_ncdu(&self, scan_result: &Arc<RwLock<Option<ScanResult>>>) {
+        let scan_result = scan_result.read();
+        let Some(scan_result) = scan_result.as_ref() else {
+            return;
+        };
+        
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("ncdu export", &["json"])
+            .set_file_name("diskray-ncdu.json")
+            .save_file()
+        {
+            if let Err(e) = ncdu::save(scan_result, &path) {
+                Self::show_error("Could not export for ncdu", format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+    
+    fn show_error(title: &str, description: String) {
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Error)
+            .set_title(title)
+            .set_description(description)
+            .show();
     }
     
     fn render_settings(&mut self, ctx: &egui::Context) {
@@ -285,6 +711,69 @@ impl MainPanel {
                             }
                         }
                         ui.end_row();
+                        
+                        ui.label("Scan threads:");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut self.scan_options.threads).range(0..=256));
+                            ui.label("(0 = all cores)");
+                        });
+                        ui.end_row();
+                        
+                        ui.label("Filesystems:");
+                        ui.checkbox(&mut self.scan_options.one_file_system, "Stay on one filesystem");
+                        ui.end_row();
+                        
+                        ui.label("Symbolic links:");
+                        ui.checkbox(&mut self.scan_options.follow_symlinks, "Follow symbolic links")
+                            .on_hover_text("Measure what links point to; every target is still counted once");
+                        ui.end_row();
+                        
+                        ui.label("Depth limit:");
+                        ui.horizontal(|ui| {
+                            let mut limited = self.scan_options.max_depth.is_some();
+                            if ui.checkbox(&mut limited, "Summarize folders below").changed() {
+                                self.scan_options.max_depth = limited.then_some(3);
+                            }
+                            if let Some(depth) = &mut self.scan_options.max_depth {
+                                ui.add(egui::DragValue::new(depth).range(0..=64));
+                                ui.label("levels");
+                            }
+                        })
+                        .response
+                        .on_hover_text("Deeper contents are only counted, which keeps huge scans small in memory");
+                        ui.end_row();
+                        
+                        ui.label("Archives:");
+                        ui.checkbox(&mut self.scan_options.expand_archives, "Show the contents of archives")
+                            .on_hover_text("List .zip, .tar, .tar.gz and .tar.zst files like folders. Tarballs are read in full, which slows the scan down.");
+                        ui.end_row();
+                        
+                        ui.label("Gentle scan:");
+                        ui.vertical(|ui| {
+                            ui.checkbox(&mut self.scan_options.gentle, "Low priority, pause while the system is busy")
+                                .on_hover_text("Keeps a production machine or a laptop on battery responsive, at the cost of a slower scan");
+                            ui.horizontal(|ui| {
+                                let mut limited = self.scan_options.max_ops_per_sec.is_some();
+                                if ui.checkbox(&mut limited, "At most").changed() {
+                                    self.scan_options.max_ops_per_sec = limited.then_some(1000);
+                                }
+                                if let Some(ops) = &mut self.scan_options.max_ops_per_sec {
+                                    ui.add(egui::DragValue::new(ops).range(10..=1_000_000));
+                                }
+                                ui.label("files per second");
+                            });
+                        });
+                        ui.end_row();
+                        
+                        ui.label("Live updates:");
+                        ui.checkbox(&mut self.watch_changes, "Watch for changes after the scan")
+                            .on_hover_text("Apply created, deleted, renamed and growing files to the finished scan");
+                        ui.end_row();
+                        
+                        ui.label("Checkpoints:");
+                        ui.checkbox(&mut self.save_checkpoints, "Save checkpoints of long scans")
+                            .on_hover_text("A scan interrupted by a crash or by stopping it can be resumed later");
+                        ui.end_row();
                     });
                 
                 ui.separator();
@@ -314,6 +803,86 @@ impl MainPanel {
         }
     }
     
+    fn render_rules(&mut self, ctx: &egui::Context) {
+        let mut rules_open = self.show_rules;
+        
+        egui::Window::new("Scan Rules")
+            .open(&mut rules_open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label("Excluded folders are not descended into. Once an include rule exists, only matching files are kept. Later rules override earlier ones.");
+                ui.label(
+                    egui::RichText::new(
+                        "Globs without '/' match names, other globs match paths below the scan root. Regexes search the full path.",
+                    )
+                    .small()
+                    .weak(),
+                );
+                ui.separator();
+                
+                let mut remove = None;
+                egui::Grid::new("rules_grid")
+                    .num_columns(4)
+                    .spacing([10.0, 5.0])
+                    .show(ui, |ui| {
+                        for (i, rule) in self.scan_options.rules.iter_mut().enumerate() {
+                            egui::ComboBox::from_id_salt(("rule_action", i))
+                                .selected_text(match rule.action {
+                                    RuleAction::Include => "Include",
+                                    RuleAction::Exclude => "Exclude",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut rule.action, RuleAction::Exclude, "Exclude");
+                                    ui.selectable_value(&mut rule.action, RuleAction::Include, "Include");
+                                });
+                            
+                            egui::ComboBox::from_id_salt(("rule_kind", i))
+                                .selected_text(match rule.kind {
+                                    PatternKind::Glob => "Glob",
+                                    PatternKind::Regex => "Regex",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut rule.kind, PatternKind::Glob, "Glob");
+                                    ui.selectable_value(&mut rule.kind, PatternKind::Regex, "Regex");
+                                });
+                            
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut rule.pattern)
+                                    .desired_width(250.0)
+                                    .hint_text("e.g. node_modules or **/*.log"));
+                                if let Err(e) = rule.validate() {
+                                    ui.colored_label(egui::Color32::RED, "⚠ Invalid")
+                                        .on_hover_text(e.to_string());
+                                }
+                            });
+                            
+                            if ui.button("🗑️").clicked() {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                
+                if let Some(i) = remove {
+                    self.scan_options.rules.remove(i);
+                }
+                
+                ui.horizontal(|ui| {
+                    if ui.button("➕ Exclude Rule").clicked() {
+                        self.scan_options.rules.push(PathRule::new(RuleAction::Exclude, PatternKind::Glob, ""));
+                    }
+                    if ui.button("➕ Include Rule").clicked() {
+                        self.scan_options.rules.push(PathRule::new(RuleAction::Include, PatternKind::Glob, ""));
+                    }
+                });
+                
+                ui.label(egui::RichText::new("Rules apply to the next scan.").weak());
+            });
+        
+        self.show_rules = rules_open;
+    }
+    
     fn render_about(&mut self, ctx: &egui::Context) {
         let mut about_open = self.show_about;
         
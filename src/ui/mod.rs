@@ -3,10 +3,18 @@ pub mod tree_panel;
 pub mod chart_panel;
 pub mod details_panel;
 pub mod disks_panel;  // Новый модуль
+pub mod errors_panel;
+pub mod access_panel;
+pub mod diff_panel;
+pub mod progress_panel;
 
 // Re-export
 pub use main_panel::MainPanel;
-pub use tree_panel::TreePanel;
+pub use tree_panel::{TreeContext, TreePanel};
 pub use chart_panel::ChartPanel;
 pub use details_panel::DetailsPanel;
-pub use disks_panel::DisksPanel;  // Новый экспорт
\ No newline at end of file
+pub use disks_panel::DisksPanel;  // Новый экспорт
+pub use errors_panel::ErrorsPanel;
+pub use access_panel::AccessPanel;
+pub use diff_panel::DiffPanel;
+pub use progress_panel::ProgressPanel;
\ No newline at end of file
@@ -0,0 +1,107 @@
+use eframe::egui;
+use crate::scanner::{EstimateSource, FileSystemScanner, ScanState};
+use humansize::{format_size, DECIMAL};
+use std::time::Duration;
+
+/// Panel following the running scan: speed, time left and the folder being listed
+#[derive(Default)]
+pub struct ProgressPanel {}
+
+impl ProgressPanel {
+    pub fn new() -> Self {
+        Self {}
+    }
+    
+    pub fn render(&mut self, ui: &mut egui::Ui, scanner: &Option<FileSystemScanner>) {
+        ui.heading("⏱ Scan Progress");
+        ui.add_space(10.0);
+        
+        let Some(scanner) = scanner else {
+            ui.label("No scan running. Start one from the File menu.");
+            return;
+        };
+        let progress = scanner.get_progress();
+        let running = progress.state == ScanState::Running;
+        
+        if let Some(fraction) = progress.fraction() {
+            let text = match progress.eta {
+                Some(eta) if running => format!("{:.0}%, about {} left", fraction * 100.0, format_duration(eta)),
+                _ => format!("{:.0}%", fraction * 100.0),
+            };
+            ui.add(egui::ProgressBar::new(fraction).text(text).animate(running));
+            ui.add_space(10.0);
+        }
+        
+        egui::Grid::new("progress_grid")
+            .num_columns(2)
+            .striped(true)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                let mut row = |label: &str, value: String| {
+                    ui.label(egui::RichText::new(label).strong());
+                    ui.label(value);
+                    ui.end_row();
+                };
+                
+                let state = match &progress.state {
+                    ScanState::Running if scanner.is_stopping() => "Stopping".to_string(),
+                    ScanState::Idle => "Not started".to_string(),
+                    ScanState::Running => "Scanning".to_string(),
+                    ScanState::Finished => "Finished".to_string(),
+                    ScanState::Cancelled => "Cancelled, the result is partial".to_string(),
+                    ScanState::Failed(error) => format!("Failed: {}", error),
+                };
+                row("State", state);
+                if running {
+                    row("Current folder", progress.current_path.display().to_string());
+                }
+                let entries = match progress.estimated_total {
+                    Some(estimate) if running => {
+                        let source = match estimate.source {
+                            EstimateSource::FilesystemInodes => "used inodes",
+                            EstimateSource::PreviousScan => "previous scan",
+                        };
+                        format!(
+                            "{} of ~{} ({})",
+                            progress.files_scanned,
+                            progress.files_scanned.max(estimate.entries),
+                            source
+                        )
+                    }
+                    _ => progress.files_scanned.to_string(),
+                };
+                row("Entries", entries);
+                row("Data", format_size(progress.bytes_scanned, DECIMAL));
+                
+                // While running the speed is over the last few seconds, afterwards over the whole scan
+                let speed = if running { "" } else { " (average)" };
+                row(&format!("Entries/s{}", speed), format!("{:.0}", progress.entries_per_sec));
+                row(&format!("Data/s{}", speed), format_size(progress.bytes_per_sec as u64, DECIMAL));
+                row("Elapsed", format_duration(progress.elapsed));
+                if running {
+                    let eta = match (progress.eta, progress.estimated_total) {
+                        (Some(eta), _) => format!("about {}", format_duration(eta)),
+                        // Past the estimate, the scan is somewhere near its end
+                        (None, Some(_)) if progress.entries_per_sec > 0.0 => "any moment".to_string(),
+                        _ => "unknown".to_string(),
+                    };
+                    row("Time left", eta);
+                }
+                row("Errors", progress.error_count.to_string());
+                row("Pace", progress.throttle.to_string());
+            });
+    }
+}
+
+/// "1h 02m 03s", "2m 03s" or "3s"
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
@@ -1,39 +1,82 @@
 use eframe::egui;
-use crate::scanner::FileEntry;
-use crate::scanner::FileSystemScanner;
+use crate::scanner::{NodeId, FileEntry, FileKind};
+use crate::scanner::{ExcludeReason, FileSystemScanner, ScanOptions, SizeMode};
 use std::collections::HashMap;
 use std::sync::Arc;
 use parking_lot::RwLock;
 use crate::scanner::ScanResult;
+use crate::archive::{self, ArchiveKind, ArchiveMember};
+use crate::source::RealFs;
+
+/// Members of an archive being listed in the background, once they are read
+type PendingListing = Arc<parking_lot::Mutex<Option<std::io::Result<Vec<ArchiveMember>>>>>;
+
+/// App state the tree view reads and starts scans through, borrowed for one frame
+pub struct TreeContext<'a> {
+    pub selected_path: &'a mut Option<std::path::PathBuf>,
+    pub scan_result: Arc<RwLock<Option<ScanResult>>>,
+    pub scanner: &'a mut Option<FileSystemScanner>,
+    /// Rescan of a single folder, see `DiskRayApp::subtree_scanner`
+    pub subtree_scanner: &'a mut Option<FileSystemScanner>,
+    pub is_scanning: &'a mut bool,
+    pub current_path: &'a mut std::path::PathBuf,
+    /// Options for scans started from the tree
+    pub scan_options: &'a ScanOptions,
+    pub size_mode: SizeMode,
+}
 
 /// Tree view panel for browsing file system
 #[derive(Default)]
 pub struct TreePanel {
     expanded_dirs: HashMap<std::path::PathBuf, bool>,
+    /// Directory the user asked to scan from the tree
+    scan_request: Option<std::path::PathBuf>,
+    /// Directory the user asked to rescan in place, summarized or not
+    rescan_request: Option<std::path::PathBuf>,
+    /// Directory being rescanned right now
+    rescanning: Option<std::path::PathBuf>,
+    /// Archive the user asked to look into
+    archive_request: Option<std::path::PathBuf>,
+    /// Archive being listed right now
+    archive_listing: Option<(std::path::PathBuf, PendingListing)>,
+    archive_error: Option<String>,
 }
 
 impl TreePanel {
     pub fn new() -> Self {
         Self {
             expanded_dirs: HashMap::new(),
+            scan_request: None,
+            rescan_request: None,
+            rescanning: None,
+            archive_request: None,
+            archive_listing: None,
+            archive_error: None,
         }
     }
     
-    pub fn render(
-        &mut self, 
-        ui: &mut egui::Ui, 
-        selected_path: &mut Option<std::path::PathBuf>,
-        scan_result: Arc<RwLock<Option<ScanResult>>>,
-        scanner: &mut Option<FileSystemScanner>,
-        is_scanning: &mut bool,
-        current_path: &mut std::path::PathBuf,
-    ) {
+    pub fn render(&mut self, ui: &mut egui::Ui, context: TreeContext) {
+        let TreeContext {
+            selected_path,
+            scan_result,
+            scanner,
+            subtree_scanner,
+            is_scanning,
+            current_path,
+            scan_options,
+            size_mode,
+        } = context;
+        self.poll_archive_listing(ui.ctx(), &scan_result);
+        
         egui::TopBottomPanel::top("tree_panel_header")
             .exact_height(40.0)
             .show_inside(ui, |ui| {
                 ui.horizontal(|ui| {
                     ui.heading("🌳 File Tree");
                     ui.label(format!("Path: {}", current_path.display()));
+                    if let Some(error) = &self.archive_error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.add(egui::TextEdit::singleline(&mut String::new())
                             .hint_text("Search...")
@@ -42,22 +85,14 @@ impl TreePanel {
                 });
             });
         
+        self.rescanning = subtree_scanner.as_ref().map(|s| s.root_path().to_path_buf());
+        
         egui::CentralPanel::default()
             .show_inside(ui, |ui| {
                 if let Some(scan_result) = &*scan_result.read() {
-                    let entry_map: HashMap<_, _> = scan_result.entries
-                        .iter()
-                        .map(|e| (e.path.clone(), e))
-                        .collect();
-                    
-                    let root_entries: Vec<&FileEntry> = scan_result.entries
-                        .iter()
-                        .filter(|e| {
-                            e.parent.as_ref().map_or(true, |parent| {
-                                parent == &scan_result.root_path
-                            })
-                        })
-                        .collect();
+                    let root_entries: &[NodeId] = scan_result
+                        .root()
+                        .map_or(&[], |_| scan_result.children(NodeId::ROOT));
                     
                     // Temporary copy of selected_path for use in closure
                     let mut local_selected_path = selected_path.clone();
@@ -65,13 +100,34 @@ impl TreePanel {
                     egui::ScrollArea::vertical()
                         .auto_shrink([false; 2])
                         .show(ui, |ui| {
-                            for entry in root_entries {
-                                self.render_tree_node(ui, entry, &entry_map, &mut local_selected_path);
+                            for &id in root_entries {
+                                let path = scan_result.root_path.join(scan_result.name(id));
+                                self.render_tree_node(ui, scan_result, id, path, &mut local_selected_path, size_mode);
                             }
                         });
                     
                     // Update selected_path back to app
                     *selected_path = local_selected_path;
+                    
+                    if let Some(path) = self.archive_request.take() {
+                        self.start_archive_listing(path);
+                    }
+                    
+                    if let Some(path) = self.scan_request.take() {
+                        *current_path = path.clone();
+                        *scanner = Some(FileSystemScanner::with_options(path, scan_options.clone()));
+                        scanner.as_mut().unwrap().start();
+                        *is_scanning = true;
+                    }
+                    
+                    // One folder at a time, the result is grafted by the app. During a
+                    // full scan the tree is only a snapshot and would be replaced.
+                    if let Some(path) = self.rescan_request.take() {
+                        if subtree_scanner.is_none() && !*is_scanning {
+                            *subtree_scanner = Some(FileSystemScanner::with_options(path, scan_options.clone()));
+                            subtree_scanner.as_mut().unwrap().start();
+                        }
+                    }
                 } else {
                     // No scan data yet
                     ui.vertical_centered(|ui| {
@@ -80,7 +136,7 @@ impl TreePanel {
                         ui.label("Select a directory to start analyzing disk usage");
                         ui.add_space(20.0);
                         if ui.button("📁 Scan Current Directory").clicked() {
-                            *scanner = Some(FileSystemScanner::new(current_path.clone()));
+                            *scanner = Some(FileSystemScanner::with_options(current_path.clone(), scan_options.clone()));
                             scanner.as_mut().unwrap().start();
                             *is_scanning = true;
                         }
@@ -89,56 +145,259 @@ impl TreePanel {
             });
     }
     
+    /// List an archive on a background thread, tarballs can take a while to read
+    fn start_archive_listing(&mut self, path: std::path::PathBuf) {
+        let Some(kind) = path.file_name().and_then(|name| ArchiveKind::from_name(&name.to_string_lossy())) else {
+            return;
+        };
+        if self.archive_listing.is_some() {
+            return;
+        }
+        let pending = PendingListing::default();
+        let done = pending.clone();
+        let archive_path = path.clone();
+        std::thread::spawn(move || {
+            let members = archive::list_members(&RealFs, &archive_path, kind);
+            *done.lock() = Some(members);
+        });
+        self.archive_error = None;
+        self.archive_listing = Some((path, pending));
+    }
+    
+    /// Add the members of a finished archive listing to the tree
+    fn poll_archive_listing(&mut self, ctx: &egui::Context, scan_result: &RwLock<Option<ScanResult>>) {
+        let Some((path, pending)) = &self.archive_listing else {
+            return;
+        };
+        let Some(members) = pending.lock().take() else {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            return;
+        };
+        
+        match members {
+            Ok(members) => {
+                let mut scan_result = scan_result.write();
+                // The scan may have been replaced while the archive was read
+                if let Some(result) = scan_result.as_mut() {
+                    if let Some(id) = result.find(path).filter(|&id| result.entry(id).children.is_empty()) {
+                        archive::attach(result, id, members);
+                        self.expanded_dirs.insert(path.clone(), true);
+                    }
+                }
+            }
+            Err(e) => self.archive_error = Some(format!("Could not read {}: {}", path.display(), e)),
+        }
+        self.archive_listing = None;
+    }
+    
     fn render_tree_node(
         &mut self,
         ui: &mut egui::Ui,
-        entry: &FileEntry,
-        entry_map: &HashMap<std::path::PathBuf, &FileEntry>,
+        scan_result: &ScanResult,
+        id: NodeId,
+        path: std::path::PathBuf,
         selected_path: &mut Option<std::path::PathBuf>,
+        size_mode: SizeMode,
     ) {
+        let entry = scan_result.entry(id);
         let is_expanded = self.expanded_dirs
-            .get(&entry.path)
+            .get(&path)
             .copied()
             .unwrap_or(false);
         
-        let is_selected = Some(&entry.path) == selected_path.as_ref();
+        let is_selected = Some(&path) == selected_path.as_ref();
         
-        let response = ui.selectable_label(is_selected, self.format_entry(entry));
+        let mut response = ui.selectable_label(is_selected, self.format_entry(entry, scan_result.name(id), &path, size_mode));
         
         if response.clicked() {
-            *selected_path = Some(entry.path.clone());
+            *selected_path = Some(path.clone());
+        }
+        
+        if entry.excluded == Some(ExcludeReason::Pattern) {
+            return;
+        }
+        
+        if entry.link_target.as_ref().is_some_and(|target| target.cycle) {
+            response = response.on_hover_text("Points to a folder above itself, not followed");
+        } else if entry.via_link {
+            response = response.on_hover_text("Reached through a symbolic link, counted once with its target");
+        }
+        
+        if entry.in_archive {
+            response = response.on_hover_text("Inside an archive, not on disk. \"packed\" is the space it takes up in the archive.");
         }
         
-        if response.double_clicked() && entry.is_directory {
+        if entry.scanning {
+            response = response.on_hover_text("Still being scanned, the size keeps growing");
+        } else if entry.incomplete {
+            response = response.on_hover_text(if entry.read_error {
+                "This folder could not be read completely, its total is too low"
+            } else {
+                "Not everything inside could be scanned, the total is too low"
+            });
+        }
+        
+        if entry.excluded == Some(ExcludeReason::OtherFilesystem) {
+            response = response.on_hover_text("Separate filesystem, not scanned. Right-click to scan it.");
+            response.context_menu(|ui| {
+                if ui.button("🔍 Scan this filesystem").clicked() {
+                    self.scan_request = Some(path.clone());
+                    ui.close();
+                }
+            });
+            return;
+        }
+        
+        if let Some(summary) = &entry.summary {
+            if self.rescanning.as_ref() == Some(&path) {
+                response.on_hover_text("Scanning this folder in full...");
+                return;
+            }
+            let extensions: Vec<String> = summary
+                .top_extensions(5)
+                .into_iter()
+                .map(|(extension, files)| {
+                    let extension = if extension.is_empty() { "(none)" } else { extension };
+                    format!("{}: {} files", extension, files)
+                })
+                .collect();
+            response = response.on_hover_text(format!(
+                "Contents summarized by the depth limit. Double-click to scan this folder in full.\n{}",
+                extensions.join("\n")
+            ));
+            if response.double_clicked() {
+                self.rescan_request = Some(path.clone());
+            }
+            response.context_menu(|ui| {
+                if ui.button("🔍 Expand (scan this folder)").clicked() {
+                    self.rescan_request = Some(path.clone());
+                    ui.close();
+                }
+            });
+            return;
+        }
+        
+        let is_closed_archive = entry.kind == FileKind::File
+            && !entry.in_archive
+            && entry.children.is_empty()
+            && ArchiveKind::from_name(scan_result.name(id)).is_some();
+        if is_closed_archive {
+            let listing = self.archive_listing.as_ref().is_some_and(|(listed, _)| *listed == path);
+            if listing {
+                response = response.on_hover_text("Reading the archive...");
+            } else {
+                response = response.on_hover_text("Double-click to show what is inside");
+                if response.double_clicked() {
+                    self.archive_request = Some(path.clone());
+                }
+            }
+            response.context_menu(|ui| {
+                if ui.add_enabled(self.archive_listing.is_none(), egui::Button::new("📦 Show archive contents")).clicked() {
+                    self.archive_request = Some(path.clone());
+                    ui.close();
+                }
+            });
+            return;
+        }
+        
+        if entry.is_directory() && !entry.via_link && !entry.scanning && !entry.in_archive {
+            if self.rescanning.as_ref() == Some(&path) {
+                response = response.on_hover_text("Rescanning this folder...");
+            }
+            response.context_menu(|ui| {
+                if ui.add_enabled(self.rescanning.is_none(), egui::Button::new("🔄 Refresh this folder")).clicked() {
+                    self.rescan_request = Some(path.clone());
+                    ui.close();
+                }
+            });
+        }
+        
+        if response.double_clicked() && entry.is_container() {
             let new_state = !is_expanded;
-            self.expanded_dirs.insert(entry.path.clone(), new_state);
+            self.expanded_dirs.insert(path.clone(), new_state);
         }
         
-        if entry.is_directory && is_expanded {
-            ui.indent(egui::Id::new(&entry.path), |ui| {
-                let mut children: Vec<&FileEntry> = entry.children
-                    .iter()
-                    .filter_map(|path| entry_map.get(path))
-                    .copied()
-                    .collect();
+        if entry.is_container() && is_expanded {
+            ui.indent(egui::Id::new(&path), |ui| {
+                let mut children: Vec<NodeId> = entry.children.clone();
                 
-                children.sort_by(|a, b| b.size.cmp(&a.size));
+                children.sort_by(|&a, &b| {
+                    scan_result.entry(b).size_in(size_mode).cmp(&scan_result.entry(a).size_in(size_mode))
+                });
                 
                 for child in children {
-                    self.render_tree_node(ui, child, entry_map, selected_path);
+                    let child_path = path.join(scan_result.name(child));
+                    self.render_tree_node(ui, scan_result, child, child_path, selected_path, size_mode);
                 }
             });
         }
     }
     
-    fn format_entry(&self, entry: &FileEntry) -> String {
-        let size_str = humansize::format_size(entry.size, humansize::DECIMAL);
-        let icon = if entry.is_directory { "📁" } else { "📄" };
+    fn format_entry(&self, entry: &FileEntry, name: &str, path: &std::path::Path, size_mode: SizeMode) -> String {
+        match entry.excluded {
+            Some(ExcludeReason::OtherFilesystem) => {
+                return format!("💽 {} (separate filesystem, not scanned)", name);
+            }
+            Some(ExcludeReason::Pattern) => {
+                return format!("🚫 {} (excluded by scan rules)", name);
+            }
+            None => {}
+        }
+        
+        let size_str = humansize::format_size(entry.size_in(size_mode), humansize::DECIMAL);
+        let rescanning = if self.rescanning.as_deref() == Some(path) { " ⏳" } else { "" };
+        let icon = match entry.kind {
+            _ if entry.scanning => "⏳",
+            FileKind::File if entry.is_container() => "📦",
+            FileKind::Directory => "📁",
+            FileKind::File if entry.shared => "🔗",
+            FileKind::File => "📄",
+            FileKind::Symlink => "↪",
+            FileKind::Fifo => "📨",
+            FileKind::Socket => "🔌",
+            FileKind::BlockDevice => "💾",
+            FileKind::CharDevice => "⌨",
+        };
+        
+        if let Some(summary) = &entry.summary {
+            let newest = summary
+                .newest_modified
+                .map_or(String::new(), |time| format!(", newest {}", time.format("%Y-%m-%d")));
+            return format!(
+                "🗜 {} ({}, {} files, {} folders{}){}",
+                name, size_str, summary.file_count, summary.dir_count, newest, rescanning
+            );
+        }
         
-        if entry.is_directory {
-            format!("{} {} ({})", icon, entry.name, size_str)
+        if entry.in_archive && entry.kind != FileKind::Symlink {
+            let packed = humansize::format_size(entry.allocated_size, humansize::DECIMAL);
+            let size_str = humansize::format_size(entry.size, humansize::DECIMAL);
+            if entry.is_directory() {
+                format!("{} {} ({}, {} packed)", icon, name, size_str, packed)
+            } else {
+                format!("{} {} - {}, {} packed", icon, name, size_str, packed)
+            }
+        } else if entry.is_directory() && (entry.incomplete || entry.scanning) {
+            format!("{} {} (≥ {}) ⚠{}", icon, name, size_str, rescanning)
+        } else if entry.is_directory() {
+            format!("{} {} ({}){}", icon, name, size_str, rescanning)
+        } else if let Some(target) = &entry.link_target {
+            let note = if target.broken {
+                " (broken link)".to_string()
+            } else if target.cycle {
+                " (loop)".to_string()
+            } else if entry.via_link {
+                format!(" ({})", size_str)
+            } else {
+                String::new()
+            };
+            format!("{} {} → {}{}", icon, name, target.path.display(), note)
+        } else if entry.kind != FileKind::File {
+            format!("{} {} ({})", icon, name, entry.kind)
+        } else if entry.shared {
+            format!("{} {} - {} ({} hard links)", icon, name, size_str, entry.nlink)
         } else {
-            format!("{} {} - {}", icon, entry.name, size_str)
+            format!("{} {} - {}", icon, name, size_str)
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,556 @@
+use crate::rules::PathRules;
+use crate::scanner::{FileEntry, FileSystemScanner, ScanOptions, ScanResult};
+use crate::source::{RealFs, ScanSource};
+use crate::tree::SubtreeChange;
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often queued events are applied to the tree
+const BATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often subtrees that could not be watched are rescanned
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the watcher is doing, for the status bar
+#[derive(Debug, Clone, Default)]
+pub struct WatchStatus {
+    /// Directories with an inotify watch
+    pub watched_dirs: usize,
+    /// Subtrees rescanned every `POLL_INTERVAL` instead, because the watch
+    /// limit was reached or events are not available
+    pub polled_dirs: Vec<PathBuf>,
+    /// Paths updated so far
+    pub updates: u64,
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+/// Keeps a finished scan up to date with the changes on disk.
+///
+/// Directories are watched with inotify on Linux. Once the watch limit
+/// (`fs.inotify.max_user_watches`) is reached, the remaining subtrees are
+/// rescanned periodically instead. Summarized folders are not watched.
+pub struct TreeWatcher {
+    root_path: PathBuf,
+    should_stop: Arc<AtomicBool>,
+    status: Arc<Mutex<WatchStatus>>,
+    changes: Arc<Mutex<Option<SubtreeChange>>>,
+}
+
+impl TreeWatcher {
+    /// Start watching the tree held in `scan_result`, scanning new folders with `options`
+    pub fn start(scan_result: Arc<RwLock<Option<ScanResult>>>, options: ScanOptions) -> Option<Self> {
+        let root_path = scan_result.read().as_ref()?.root_path.clone();
+        let watcher = Self {
+            root_path: root_path.clone(),
+            should_stop: Arc::new(AtomicBool::new(false)),
+            status: Arc::new(Mutex::new(WatchStatus::default())),
+            changes: Arc::new(Mutex::new(None)),
+        };
+        
+        let watch = Watch {
+            rules: PathRules::new(&root_path, &options.rules).ok(),
+            root_path,
+            options,
+            scan_result,
+            should_stop: watcher.should_stop.clone(),
+            status: watcher.status.clone(),
+            changes: watcher.changes.clone(),
+            events: events::EventSource::new(),
+            polled: Vec::new(),
+        };
+        std::thread::Builder::new()
+            .name("diskray-watch".to_string())
+            .spawn(move || watch.run())
+            .ok()?;
+        Some(watcher)
+    }
+    
+    /// Directory whose scan is kept up to date
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+    
+    /// Stop watching, the tree keeps the updates applied so far
+    pub fn stop(&self) {
+        self.should_stop.store(true, Ordering::SeqCst);
+    }
+    
+    pub fn status(&self) -> WatchStatus {
+        self.status.lock().clone()
+    }
+    
+    /// Entries changed since the last call, for `DiskAnalyzer::update`
+    pub fn take_changes(&self) -> Option<SubtreeChange> {
+        self.changes.lock().take()
+    }
+}
+
+impl Drop for TreeWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A path as it is on disk now
+enum Fresh {
+    Gone,
+    Entry(FileEntry),
+    /// New directory, as an empty entry and a scan of its contents
+    Directory(FileEntry, Box<ScanResult>),
+}
+
+/// State of the watcher thread
+struct Watch {
+    root_path: PathBuf,
+    options: ScanOptions,
+    rules: Option<PathRules>,
+    scan_result: Arc<RwLock<Option<ScanResult>>>,
+    should_stop: Arc<AtomicBool>,
+    status: Arc<Mutex<WatchStatus>>,
+    changes: Arc<Mutex<Option<SubtreeChange>>>,
+    events: Option<events::EventSource>,
+    polled: Vec<PathBuf>,
+}
+
+impl Watch {
+    fn run(mut self) {
+        let dirs = match &*self.scan_result.read() {
+            Some(result) => Self::watchable_dirs(result),
+            None => return,
+        };
+        self.watch_dirs(dirs);
+        
+        let mut next_poll = Instant::now() + POLL_INTERVAL;
+        while !self.should_stop.load(Ordering::Relaxed) {
+            std::thread::sleep(BATCH_INTERVAL);
+            
+            let mut changed = HashSet::new();
+            let overflowed = self.events.as_mut().is_some_and(|events| events.read(&mut changed));
+            if overflowed {
+                // Events were lost, only a full rescan brings the tree back in sync
+                let root = self.root_path.clone();
+                self.rescan(&root);
+            } else {
+                // Parents first, so a new directory is scanned before the paths inside it
+                let mut changed: Vec<PathBuf> = changed.into_iter().collect();
+                changed.sort();
+                for path in changed {
+                    self.update_path(&path);
+                }
+            }
+            
+            if Instant::now() >= next_poll {
+                for path in self.polled.clone() {
+                    self.rescan(&path);
+                }
+                next_poll = Instant::now() + POLL_INTERVAL;
+            }
+        }
+    }
+    
+    /// Directories of the tree whose contents are known, parents first
+    fn watchable_dirs(result: &ScanResult) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        result.for_each_path(|id, path| {
+            let entry = result.entry(id);
+            if entry.is_directory() && entry.excluded.is_none() && !entry.via_link && entry.summary.is_none() {
+                dirs.push(path.to_path_buf());
+            }
+        });
+        dirs
+    }
+    
+    /// Watch `dirs`, falling back to polling once watches run out
+    fn watch_dirs(&mut self, dirs: Vec<PathBuf>) {
+        for dir in dirs {
+            // Subdirectories of a polled directory are covered by its rescan
+            if self.polled.iter().any(|polled| dir.starts_with(polled)) {
+                continue;
+            }
+            let watched = match &mut self.events {
+                Some(events) => events.watch(&dir).is_ok(),
+                None => false,
+            };
+            if !watched {
+                self.polled.push(dir);
+            }
+        }
+        
+        let mut status = self.status.lock();
+        status.watched_dirs = self.events.as_ref().map_or(0, |events| events.len());
+        status.polled_dirs = self.polled.clone();
+    }
+    
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.rules.as_ref().is_some_and(|rules| {
+            !rules.is_empty() && (rules.is_excluded(path) || !path.is_dir() && !rules.is_included(path))
+        })
+    }
+    
+    /// Bring one path of the tree in line with the disk
+    fn update_path(&mut self, path: &Path) {
+        if !path.starts_with(&self.root_path) || self.is_excluded(path) {
+            return;
+        }
+        
+        // Read the disk before taking the lock, new directories can take a while
+        let fresh = match RealFs.symlink_metadata(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Fresh::Gone,
+            Err(_) => return,
+            Ok(stat) => {
+                let entry = FileSystemScanner::make_entry(&RealFs, path, &stat, &|| ());
+                let known_dir = self.scan_result.read().as_ref().is_some_and(|result| {
+                    result.find(path).is_some_and(|id| result.entry(id).is_directory())
+                });
+                if entry.is_directory() && !known_dir {
+                    match FileSystemScanner::scan_blocking(path, &self.options) {
+                        Ok(subtree) => Fresh::Directory(entry, Box::new(subtree)),
+                        Err(_) => return,
+                    }
+                } else {
+                    Fresh::Entry(entry)
+                }
+            }
+        };
+        
+        if let Fresh::Directory(_, subtree) = &fresh {
+            self.watch_dirs(Self::watchable_dirs(subtree));
+        }
+        
+        let mut guard = self.scan_result.write();
+        let Some(result) = guard.as_mut().filter(|result| result.root_path == self.root_path) else {
+            return;
+        };
+        let existing = result.find(path);
+        let change = match (fresh, existing) {
+            (Fresh::Gone, Some(id)) => result.remove(id),
+            (Fresh::Gone, None) => return,
+            (Fresh::Entry(entry), Some(id)) if entry.is_directory() == result.entry(id).is_directory() => {
+                result.restat(id, entry)
+            }
+            (fresh, existing) => {
+                // New, or replaced by an entry of another kind. Paths below
+                // summarized or unwatched folders have no parent in the tree.
+                let Some(parent) = path.parent().and_then(|parent| result.find(parent)) else {
+                    return;
+                };
+                let name = path
+                    .file_name()
+                    .map_or(String::new(), |name| name.to_string_lossy().to_string());
+                let mut change = existing.map(|id| result.remove(id)).unwrap_or_default();
+                match fresh {
+                    Fresh::Entry(entry) => change.merge(result.insert(parent, &name, entry)),
+                    Fresh::Directory(entry, subtree) => {
+                        let inserted = result.insert(parent, &name, entry);
+                        let at = inserted.added[0];
+                        change.merge(inserted);
+                        change.merge(result.graft(at, *subtree));
+                    }
+                    Fresh::Gone => {}
+                }
+                change
+            }
+        };
+        drop(guard);
+        self.record(change);
+    }
+    
+    /// Replace a whole subtree with a new scan of it
+    fn rescan(&mut self, path: &Path) {
+        let subtree = match FileSystemScanner::scan_blocking(path, &self.options) {
+            Ok(subtree) => subtree,
+            Err(_) => {
+                // Gone, or no longer readable
+                self.polled.retain(|polled| polled != path);
+                self.status.lock().polled_dirs = self.polled.clone();
+                self.update_path(path);
+                return;
+            }
+        };
+        
+        let mut guard = self.scan_result.write();
+        let Some(result) = guard.as_mut().filter(|result| result.root_path == self.root_path) else {
+            return;
+        };
+        let Some(at) = result.find(path).filter(|&at| result.entry(at).is_directory()) else {
+            return;
+        };
+        let dirs = Self::watchable_dirs(&subtree);
+        let change = result.graft(at, subtree);
+        drop(guard);
+        self.record(change);
+        // Folders created while events were lost, polled subtrees stay polled
+        self.watch_dirs(dirs);
+    }
+    
+    fn record(&self, change: SubtreeChange) {
+        self.changes.lock().get_or_insert_with(SubtreeChange::default).merge(change);
+        let mut status = self.status.lock();
+        status.updates += 1;
+        status.last_update = Some(Utc::now());
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod events {
+    use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+    use std::collections::{HashMap, HashSet};
+    use std::path::{Path, PathBuf};
+    
+    /// Adding a watch failed because the per-user limit is reached
+    pub struct LimitReached;
+    
+    /// inotify watches on the directories of the tree
+    pub struct EventSource {
+        inotify: Inotify,
+        watches: HashMap<WatchDescriptor, PathBuf>,
+        buffer: Vec<u8>,
+    }
+    
+    impl EventSource {
+        pub fn new() -> Option<Self> {
+            Some(Self {
+                inotify: Inotify::init().ok()?,
+                watches: HashMap::new(),
+                buffer: vec![0; 64 * 1024],
+            })
+        }
+        
+        pub fn len(&self) -> usize {
+            self.watches.len()
+        }
+        
+        /// Watch the entries of `dir`. Directories that vanished or cannot be
+        /// read are skipped, since there is nothing to poll either.
+        pub fn watch(&mut self, dir: &Path) -> Result<(), LimitReached> {
+            let mask = WatchMask::CREATE
+                | WatchMask::DELETE
+                | WatchMask::MOVED_FROM
+                | WatchMask::MOVED_TO
+                | WatchMask::MODIFY
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::ATTRIB
+                | WatchMask::ONLYDIR
+                | WatchMask::DONT_FOLLOW;
+            match self.inotify.watches().add(dir, mask) {
+                Ok(wd) => {
+                    self.watches.insert(wd, dir.to_path_buf());
+                    Ok(())
+                }
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => Err(LimitReached),
+                Err(_) => Ok(()),
+            }
+        }
+        
+        /// Collect the paths touched by pending events into `changed`.
+        ///
+        /// Returns true when the kernel queue overflowed and events were lost.
+        pub fn read(&mut self, changed: &mut HashSet<PathBuf>) -> bool {
+            let mut overflowed = false;
+            // The descriptor is non-blocking, reading stops once the queue is empty
+            while let Ok(events) = self.inotify.read_events(&mut self.buffer) {
+                let mut moved_dirs = Vec::new();
+                let mut ignored = Vec::new();
+                for event in events {
+                    if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        overflowed = true;
+                        continue;
+                    }
+                    if event.mask.contains(EventMask::IGNORED) {
+                        ignored.push(event.wd);
+                        continue;
+                    }
+                    let Some(dir) = self.watches.get(&event.wd) else {
+                        continue;
+                    };
+                    let path = event.name.map_or_else(|| dir.clone(), |name| dir.join(name));
+                    if event.mask.contains(EventMask::MOVED_FROM | EventMask::ISDIR) {
+                        moved_dirs.push(path.clone());
+                    }
+                    changed.insert(path);
+                }
+                
+                for wd in ignored {
+                    self.watches.remove(&wd);
+                }
+                // Watches follow the moved directory, the new location is watched from scratch
+                for dir in moved_dirs {
+                    self.unwatch_below(&dir);
+                }
+            }
+            overflowed
+        }
+        
+        fn unwatch_below(&mut self, dir: &Path) {
+            let stale: Vec<WatchDescriptor> = self
+                .watches
+                .iter()
+                .filter(|(_, path)| path.starts_with(dir))
+                .map(|(wd, _)| wd.clone())
+                .collect();
+            for wd in stale {
+                self.watches.remove(&wd);
+                let _ = self.inotify.watches().remove(wd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod events {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    
+    /// Adding a watch failed because the per-user limit is reached
+    pub struct LimitReached;
+    
+    /// Without inotify every directory is polled
+    pub struct EventSource;
+    
+    impl EventSource {
+        pub fn new() -> Option<Self> {
+            None
+        }
+        
+        pub fn len(&self) -> usize {
+            0
+        }
+        
+        pub fn watch(&mut self, _dir: &Path) -> Result<(), LimitReached> {
+            Err(LimitReached)
+        }
+        
+        pub fn read(&mut self, _changed: &mut HashSet<PathBuf>) -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::NodeId;
+    use crate::source::testing::listing;
+    use std::fs;
+    
+    /// Watcher state for a fresh scan of `root`, without inotify, as when the
+    /// watch limit is reached or events are not available
+    fn watch(root: &Path) -> Watch {
+        let options = ScanOptions::default();
+        let result = FileSystemScanner::scan_blocking(root, &options).unwrap();
+        Watch {
+            root_path: root.to_path_buf(),
+            rules: PathRules::new(root, &options.rules).ok(),
+            options,
+            scan_result: Arc::new(RwLock::new(Some(result))),
+            should_stop: Arc::new(AtomicBool::new(false)),
+            status: Arc::new(Mutex::new(WatchStatus::default())),
+            changes: Arc::new(Mutex::new(None)),
+            events: None,
+            polled: Vec::new(),
+        }
+    }
+    
+    /// Id of `path` in the watched tree
+    fn id_of(watch: &Watch, path: &Path) -> Option<NodeId> {
+        watch.scan_result.read().as_ref().unwrap().find(path)
+    }
+    
+    /// Check that the updated tree is the one a new scan finds
+    fn assert_in_sync(watch: &Watch) {
+        let fresh = FileSystemScanner::scan_blocking(&watch.root_path, &watch.options).unwrap();
+        let guard = watch.scan_result.read();
+        let result = guard.as_ref().unwrap();
+        assert_eq!(listing(result), listing(&fresh));
+        assert_eq!(
+            (result.total_size, result.total_allocated, result.file_count, result.dir_count),
+            (fresh.total_size, fresh.total_allocated, fresh.file_count, fresh.dir_count)
+        );
+    }
+    
+    fn setup() -> (tempfile::TempDir, Watch) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("docs/a.txt"), vec![0; 100]).unwrap();
+        fs::write(dir.path().join("top.bin"), vec![0; 5000]).unwrap();
+        let watch = watch(dir.path());
+        (dir, watch)
+    }
+    
+    #[test]
+    fn created_and_deleted_files_update_the_totals() {
+        let (dir, mut watch) = setup();
+        let created = dir.path().join("docs/b.txt");
+        fs::write(&created, vec![0; 250]).unwrap();
+        watch.update_path(&created);
+        
+        let id = id_of(&watch, &created).unwrap();
+        let change = watch.changes.lock().take().unwrap();
+        assert_eq!((change.added, change.removed), (vec![id], vec![]));
+        assert_eq!(watch.scan_result.read().as_ref().unwrap().total_size, 5350);
+        assert_in_sync(&watch);
+        
+        let deleted = dir.path().join("top.bin");
+        let id = id_of(&watch, &deleted).unwrap();
+        fs::remove_file(&deleted).unwrap();
+        watch.update_path(&deleted);
+        
+        let change = watch.changes.lock().take().unwrap();
+        assert_eq!((change.added, change.removed), (vec![], vec![id]));
+        assert!(id_of(&watch, &deleted).is_none());
+        assert_eq!(watch.scan_result.read().as_ref().unwrap().total_size, 350);
+        assert_in_sync(&watch);
+        assert_eq!(watch.status.lock().updates, 2);
+    }
+    
+    #[test]
+    fn a_file_replaced_by_a_folder_is_scanned_as_one() {
+        let (dir, mut watch) = setup();
+        let path = dir.path().join("top.bin");
+        let old = id_of(&watch, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("inner.txt"), vec![0; 42]).unwrap();
+        watch.update_path(&path);
+        
+        let change = watch.changes.lock().take().unwrap();
+        let new = id_of(&watch, &path).unwrap();
+        let inner = id_of(&watch, &path.join("inner.txt")).unwrap();
+        assert_eq!(change.removed, [old]);
+        assert!(change.added.contains(&new) && change.added.contains(&inner));
+        {
+            let guard = watch.scan_result.read();
+            let result = guard.as_ref().unwrap();
+            assert!(result.entry(new).is_directory());
+            assert_eq!((result.total_size, result.file_count, result.dir_count), (142, 2, 3));
+        }
+        assert_in_sync(&watch);
+        // The new folder could not be watched, so it is polled
+        assert!(watch.polled.contains(&path));
+    }
+    
+    #[test]
+    fn a_full_rescan_catches_up_with_lost_events() {
+        let (dir, mut watch) = setup();
+        let root = dir.path().to_path_buf();
+        let removed = id_of(&watch, &root.join("docs/a.txt")).unwrap();
+        fs::remove_file(root.join("docs/a.txt")).unwrap();
+        fs::create_dir(root.join("new")).unwrap();
+        fs::write(root.join("new/c.txt"), vec![0; 7]).unwrap();
+        fs::write(root.join("top.bin"), vec![0; 6000]).unwrap();
+        
+        // What the watch thread does once the event queue overflowed
+        watch.rescan(&root);
+        
+        let change = watch.changes.lock().take().unwrap();
+        assert!(change.removed.contains(&removed));
+        assert!(change.added.contains(&id_of(&watch, &root.join("new/c.txt")).unwrap()));
+        assert_eq!(watch.scan_result.read().as_ref().unwrap().total_size, 6007);
+        assert_in_sync(&watch);
+    }
+}